@@ -0,0 +1,54 @@
+/**
+ * Compares passing results to JavaScript from a dedicated callback thread (in batches)
+ * with passing them from each searching thread (one at a time).
+ */
+
+const {multithreadedSearchDirectory} = require('../dist/ripgrepjs.node');
+const options = {
+    afterContext: 0,
+    beforeContext: 0,
+    multilineSearch: false,
+    invertMatch: false,
+    includeLineNumbers: true,
+    passthru: false,
+    caseInsensitive: false,
+    smartCase: true,
+    dotMatchesNewline: false,
+    greedySwap: false,
+    ignoreWhitespace: false,
+    unicode: true,
+    octal: false,
+    crlf: false,
+    wordBoundariesOnly: false,
+    pattern: "fo+"
+};
+
+const filePath = process.argv.pop();
+const runs = 5;
+
+function time(callbackThread) {
+    return new Promise(resolve => {
+        const start = process.hrtime.bigint();
+        let results = 0;
+        multithreadedSearchDirectory({
+            ...options,
+            callbackThread,
+            onComplete: () => resolve({ms: Number(process.hrtime.bigint() - start) / 1e6, results}),
+        }, filePath, () => results++);
+    });
+}
+
+(async () => {
+    for (const callbackThread of [true, false]) {
+        const times = [];
+        let results = 0;
+        for (let i = 0; i < runs; i++) {
+            const run = await time(callbackThread);
+            times.push(run.ms);
+            results = run.results;
+        }
+        const mean = times.reduce((a, b) => a + b) / runs;
+        const name = callbackThread ? 'callback thread' : 'direct';
+        console.log(`${name}: ${mean.toFixed(1)}ms mean over ${runs} runs (${results} results)`);
+    }
+})();
@@ -0,0 +1,57 @@
+/**
+ * Compares `prefixAnchor` with the equivalent hand-written pattern (`^\s*` followed by the escaped prefix),
+ * which it should match in speed, since it compiles to the same thing.
+ */
+
+const {multithreadedSearchDirectory} = require('../dist/ripgrepjs.node');
+const options = {
+    afterContext: 0,
+    beforeContext: 0,
+    multilineSearch: false,
+    invertMatch: false,
+    includeLineNumbers: true,
+    passthru: false,
+    caseInsensitive: false,
+    smartCase: true,
+    dotMatchesNewline: false,
+    greedySwap: false,
+    ignoreWhitespace: false,
+    unicode: true,
+    octal: false,
+    crlf: false,
+    wordBoundariesOnly: false,
+};
+const prefix = 'function';
+
+const filePath = process.argv.pop();
+const runs = 5;
+
+function time(patternOptions) {
+    return new Promise(resolve => {
+        const start = process.hrtime.bigint();
+        let results = 0;
+        multithreadedSearchDirectory({
+            ...options,
+            ...patternOptions,
+            onComplete: () => resolve({ms: Number(process.hrtime.bigint() - start) / 1e6, results}),
+        }, filePath, () => results++);
+    });
+}
+
+(async () => {
+    const cases = {
+        prefixAnchor: {pattern: prefix, prefixAnchor: true},
+        manual: {pattern: `^\\s*${prefix}`},
+    };
+    for (const [name, patternOptions] of Object.entries(cases)) {
+        const times = [];
+        let results = 0;
+        for (let i = 0; i < runs; i++) {
+            const run = await time(patternOptions);
+            times.push(run.ms);
+            results = run.results;
+        }
+        const mean = times.reduce((a, b) => a + b) / runs;
+        console.log(`${name}: ${mean.toFixed(1)}ms mean over ${runs} runs (${results} results)`);
+    }
+})();
@@ -0,0 +1,53 @@
+/**
+ * Compares Unicode-aware matching with ASCII-only matching (`unicode: false`)
+ * for a pattern using word boundaries and word characters, which is where the difference shows.
+ */
+
+const {multithreadedSearchDirectory} = require('../dist/ripgrepjs.node');
+const options = {
+    afterContext: 0,
+    beforeContext: 0,
+    multilineSearch: false,
+    invertMatch: false,
+    includeLineNumbers: true,
+    passthru: false,
+    caseInsensitive: false,
+    smartCase: true,
+    dotMatchesNewline: false,
+    greedySwap: false,
+    ignoreWhitespace: false,
+    octal: false,
+    crlf: false,
+    wordBoundariesOnly: true,
+    pattern: "\\w+o \\d+",
+};
+
+const filePath = process.argv.pop();
+const runs = 5;
+
+function time(unicode) {
+    return new Promise(resolve => {
+        const start = process.hrtime.bigint();
+        let results = 0;
+        multithreadedSearchDirectory({
+            ...options,
+            unicode,
+            onComplete: () => resolve({ms: Number(process.hrtime.bigint() - start) / 1e6, results}),
+        }, filePath, () => results++);
+    });
+}
+
+(async () => {
+    for (const unicode of [true, false]) {
+        const times = [];
+        let results = 0;
+        for (let i = 0; i < runs; i++) {
+            const run = await time(unicode);
+            times.push(run.ms);
+            results = run.results;
+        }
+        const mean = times.reduce((a, b) => a + b) / runs;
+        const name = unicode ? 'unicode' : 'ascii';
+        console.log(`${name}: ${mean.toFixed(1)}ms mean over ${runs} runs (${results} results)`);
+    }
+})();
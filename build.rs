@@ -0,0 +1,37 @@
+//! Records the versions of the search crates this is built with, from `Cargo.lock`, for `version()`.
+
+use std::{env, fs, path::Path};
+
+/// The crates whose versions are recorded, with the environment variable each is recorded in
+const CRATES: [(&str, &str); 5] = [
+    ("grep", "RIPGREPJS_GREP_VERSION"),
+    ("grep-regex", "RIPGREPJS_GREP_REGEX_VERSION"),
+    ("grep-searcher", "RIPGREPJS_GREP_SEARCHER_VERSION"),
+    ("regex-automata", "RIPGREPJS_REGEX_AUTOMATA_VERSION"),
+    ("regex-syntax", "RIPGREPJS_REGEX_SYNTAX_VERSION"),
+];
+
+/// Finds the version of a package in the lockfile, which lists each as a `name = "..."` line
+/// followed by a `version = "..."` line
+fn locked_version(lockfile: &str, name: &str) -> Option<String> {
+    let name_line = format!("name = \"{}\"", name);
+    let mut lines = lockfile.lines();
+    while let Some(line) = lines.next() {
+        if line == name_line {
+            let version = lines.next()?.strip_prefix("version = \"")?;
+            return Some(version.trim_end_matches('"').to_string());
+        }
+    }
+    None
+}
+
+fn main() {
+    let lockfile_path = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("Cargo.lock");
+    println!("cargo:rerun-if-changed={}", lockfile_path.display());
+    // Cargo writes the lockfile before building anything, so it's only missing if something's very wrong
+    let lockfile = fs::read_to_string(&lockfile_path).unwrap_or_default();
+    for (name, variable) in CRATES.iter() {
+        let version = locked_version(&lockfile, name).unwrap_or_else(|| String::from("unknown"));
+        println!("cargo:rustc-env={}={}", variable, version);
+    }
+}
@@ -0,0 +1,142 @@
+//! Searching the files inside tar and zip archives, for `searchArchives`
+
+use std::{
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+};
+
+use grep::searcher::Searcher;
+
+use crate::{
+    error::RipgrepjsError,
+    search::{CountingReader, DirectorySearch},
+    sink::JSCallbackSink,
+};
+
+/// What kind of archive a file is, for `searchArchives`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ArchiveKind {
+    /// An uncompressed tar archive
+    Tar,
+    /// A gzipped tar archive
+    TarGz,
+    Zip,
+    /// An archive compressed some other way, which can't be looked inside of
+    Unsupported,
+}
+
+impl ArchiveKind {
+    /// Recognizes an archive by its file name's extension
+    pub(crate) fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if [".tar.bz2", ".tar.xz", ".tar.zst"]
+            .iter()
+            .any(|extension| name.ends_with(extension))
+        {
+            Some(ArchiveKind::Unsupported)
+        } else {
+            None
+        }
+    }
+}
+
+impl DirectorySearch<'_> {
+    /// Searches each file in a tar (or gzipped tar) or zip archive as if it were a file at `<archive>!<member path>`,
+    /// for `searchArchives`.
+    ///
+    /// Members are streamed out of the archive one at a time, so none of it is extracted or held in memory.
+    pub(crate) fn search_archive(
+        &self,
+        searcher: &mut Searcher,
+        sink: &mut JSCallbackSink,
+        file: &Arc<std::fs::File>,
+        path: &Path,
+        kind: ArchiveKind,
+    ) -> Result<(), RipgrepjsError> {
+        match kind {
+            ArchiveKind::Tar => self.search_tar(
+                searcher,
+                sink,
+                path,
+                tar::Archive::new(BufReader::new(&**file)),
+            ),
+            ArchiveKind::TarGz => self.search_tar(
+                searcher,
+                sink,
+                path,
+                tar::Archive::new(flate2::read::MultiGzDecoder::new(BufReader::new(&**file))),
+            ),
+            ArchiveKind::Zip => {
+                let mut archive = zip::ZipArchive::new(&**file).map_err(std::io::Error::from)?;
+                for index in 0..archive.len() {
+                    if self.shared.should_stop() {
+                        break;
+                    }
+                    let member = archive.by_index(index).map_err(std::io::Error::from)?;
+                    if member.is_file() {
+                        let name = PathBuf::from(member.name());
+                        self.search_archive_member(searcher, sink, path, &name, member)?;
+                    }
+                }
+                Ok(())
+            }
+            ArchiveKind::Unsupported => Ok(()),
+        }
+    }
+
+    /// Searches each regular file in a tar archive, for `search_archive`
+    fn search_tar(
+        &self,
+        searcher: &mut Searcher,
+        sink: &mut JSCallbackSink,
+        path: &Path,
+        mut archive: tar::Archive<impl Read>,
+    ) -> Result<(), RipgrepjsError> {
+        for member in archive.entries()? {
+            if self.shared.should_stop() {
+                break;
+            }
+            let member = member?;
+            if matches!(
+                member.header().entry_type(),
+                tar::EntryType::Regular | tar::EntryType::Continuous
+            ) {
+                // Long names from GNU and pax headers are already taken into account
+                let name = member.path()?.into_owned();
+                self.search_archive_member(searcher, sink, path, &name, member)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Searches one file from an archive, reporting it at `<archive>!<member path>`
+    fn search_archive_member(
+        &self,
+        searcher: &mut Searcher,
+        sink: &mut JSCallbackSink,
+        archive_path: &Path,
+        name: &Path,
+        member: impl Read,
+    ) -> Result<(), RipgrepjsError> {
+        let mut member_path = archive_path.as_os_str().to_owned();
+        member_path.push("!");
+        member_path.push(name);
+        let member_path = PathBuf::from(member_path);
+        sink.start_file(&member_path);
+        let max_bytes = self.searcher_opts.max_bytes_per_file.unwrap_or(u64::MAX);
+        let mut reader = CountingReader::new(member.take(max_bytes));
+        let result = self.in_file_order(sink, |sink| {
+            searcher.search_reader(self.matcher, &mut reader, &mut *sink)
+        });
+        self.bytes_searched
+            .fetch_add(reader.bytes_read, Ordering::Relaxed);
+        self.finish_file(sink, member_path, result)
+    }
+}
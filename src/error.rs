@@ -0,0 +1,184 @@
+//! The errors a search can run into, and how they're passed to JavaScript
+
+use std::{
+    convert::Infallible,
+    fmt,
+    path::{Path, PathBuf},
+    str::Utf8Error,
+};
+
+use grep::{matcher::NoError, searcher::SinkError};
+use neon::prelude::*;
+
+#[derive(Debug)]
+pub(crate) enum RipgrepjsError {
+    JavaScript(neon::result::Throw),
+    StringConversion(Utf8Error),
+    /// A pattern failed to compile, with a note about how the options may have contributed
+    Regex(grep::regex::Error, Option<String>),
+    /// The pattern is empty, and so would match every line, without `allowEmptyPattern`
+    EmptyPattern,
+    /// A pattern couldn't be parsed to be explained, for `explain`
+    Syntax(Box<regex_syntax::Error>),
+    IO(std::io::Error),
+    Sink(String),
+    Glob(globset::Error),
+    /// A `.gitignore` file had a line that couldn't be parsed, for `respectGitignore`
+    Gitignore(ignore::Error),
+    /// An error that happened while searching a specific file
+    InFile(PathBuf, Box<RipgrepjsError>),
+    /// An error about one line of a file, with its line number
+    AtLine(u64, Box<RipgrepjsError>),
+}
+
+impl RipgrepjsError {
+    /// A stable code for the error that JavaScript can check, set as the `code` property of the JS error
+    pub(crate) fn code(&self) -> Option<&'static str> {
+        match self {
+            RipgrepjsError::IO(e) if is_heap_limit_error(e) => Some("HEAP_LIMIT"),
+            RipgrepjsError::IO(e) if e.kind() == std::io::ErrorKind::NotFound => Some("ENOENT"),
+            RipgrepjsError::IO(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                Some("EACCES")
+            }
+            RipgrepjsError::StringConversion(_) => Some("INVALID_UTF8"),
+            RipgrepjsError::EmptyPattern => Some("EMPTY_PATTERN"),
+            RipgrepjsError::InFile(_, inner) | RipgrepjsError::AtLine(_, inner) => inner.code(),
+            _ => None,
+        }
+    }
+
+    /// The file the error happened in, if it's specific to one
+    fn path(&self) -> Option<&Path> {
+        match self {
+            RipgrepjsError::InFile(path, _) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The line the error is about, if it's about one
+    fn line_number(&self) -> Option<u64> {
+        match self {
+            RipgrepjsError::InFile(_, inner) => inner.line_number(),
+            RipgrepjsError::AtLine(line_number, _) => Some(*line_number),
+            _ => None,
+        }
+    }
+
+    /// Moves the line the error is about (if any) down by `lines`, for errors from a chunk of a file
+    pub(crate) fn offset_line_number(self, lines: u64) -> Self {
+        match self {
+            RipgrepjsError::InFile(path, inner) => {
+                RipgrepjsError::InFile(path, Box::new(inner.offset_line_number(lines)))
+            }
+            RipgrepjsError::AtLine(line_number, inner) => {
+                RipgrepjsError::AtLine(line_number + lines, inner)
+            }
+            error => error,
+        }
+    }
+
+    /// Builds a JS `Error` with the given message, plus `code`, `path`, and `lineNumber` properties where applicable
+    pub(crate) fn to_js_error<'a>(
+        &self,
+        cx: &mut impl Context<'a>,
+        message: String,
+    ) -> JsResult<'a, JsError> {
+        let js_error = cx.error(message)?;
+        if let Some(code) = self.code() {
+            let js_code = cx.string(code);
+            js_error.set(cx, "code", js_code)?;
+        }
+        if let Some(path) = self.path() {
+            let js_path = cx.string(path.to_string_lossy());
+            js_error.set(cx, "path", js_path)?;
+        }
+        if let Some(line_number) = self.line_number() {
+            let js_line_number = cx.number(line_number as f64);
+            js_error.set(cx, "lineNumber", js_line_number)?;
+        }
+        Ok(js_error)
+    }
+}
+
+/// Checks whether an I/O error is grep's report that a searcher's `heap_limit` was exceeded.
+///
+/// grep doesn't expose a distinct error type for this, so its message is the only way to tell.
+pub(crate) fn is_heap_limit_error(error: &std::io::Error) -> bool {
+    error.kind() == std::io::ErrorKind::Other
+        && error.to_string().starts_with("configured allocation limit")
+}
+
+impl From<neon::result::Throw> for RipgrepjsError {
+    fn from(error: neon::result::Throw) -> Self {
+        RipgrepjsError::JavaScript(error)
+    }
+}
+impl From<Utf8Error> for RipgrepjsError {
+    fn from(error: Utf8Error) -> Self {
+        RipgrepjsError::StringConversion(error)
+    }
+}
+impl From<Infallible> for RipgrepjsError {
+    fn from(_: Infallible) -> Self {
+        unreachable!()
+    }
+}
+impl From<NoError> for RipgrepjsError {
+    fn from(_: NoError) -> Self {
+        unreachable!()
+    }
+}
+impl From<std::io::Error> for RipgrepjsError {
+    fn from(error: std::io::Error) -> Self {
+        RipgrepjsError::IO(error)
+    }
+}
+impl From<grep::regex::Error> for RipgrepjsError {
+    fn from(error: grep::regex::Error) -> Self {
+        RipgrepjsError::Regex(error, None)
+    }
+}
+
+impl fmt::Display for RipgrepjsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RipgrepjsError::JavaScript(e) => write!(f, "JavaScript exception: {}", e),
+            RipgrepjsError::StringConversion(e) => write!(f, "invalid UTF-8: {}", e),
+            RipgrepjsError::Regex(e, None) => write!(f, "invalid pattern: {}", e),
+            RipgrepjsError::Regex(e, Some(note)) => write!(f, "invalid pattern ({}): {}", note, e),
+            RipgrepjsError::EmptyPattern => write!(
+                f,
+                "invalid pattern: it's empty, so it would match every line (set allowEmptyPattern to search with it anyway)"
+            ),
+            RipgrepjsError::Syntax(e) => write!(f, "invalid pattern: {}", e),
+            RipgrepjsError::IO(e) => write!(f, "I/O error: {}", e),
+            RipgrepjsError::Sink(message) => write!(f, "{}", message),
+            RipgrepjsError::Glob(e) => write!(f, "invalid glob: {}", e),
+            RipgrepjsError::Gitignore(e) => write!(f, "invalid gitignore: {}", e),
+            RipgrepjsError::InFile(path, e) => write!(f, "{}: {}", path.display(), e),
+            RipgrepjsError::AtLine(line_number, e) => write!(f, "line {}: {}", line_number, e),
+        }
+    }
+}
+
+impl From<regex_syntax::Error> for RipgrepjsError {
+    fn from(error: regex_syntax::Error) -> Self {
+        RipgrepjsError::Syntax(Box::new(error))
+    }
+}
+
+impl From<globset::Error> for RipgrepjsError {
+    fn from(error: globset::Error) -> Self {
+        RipgrepjsError::Glob(error)
+    }
+}
+
+impl SinkError for RipgrepjsError {
+    fn error_message<T: std::fmt::Display>(message: T) -> Self {
+        RipgrepjsError::Sink(format!("{}", message))
+    }
+
+    fn error_io(err: std::io::Error) -> Self {
+        RipgrepjsError::IO(err)
+    }
+}
@@ -0,0 +1,523 @@
+//! The objects handed to JavaScript to refer back to a search, or to something searches share:
+//! search tokens, search contexts, result caches, `openSearch` handles, and compiled patterns
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::SystemTime,
+};
+
+use neon::prelude::*;
+
+use crate::{
+    compile_pattern_from_options,
+    error::RipgrepjsError,
+    get_search_setup,
+    matcher::{SearchMatcher, WordList},
+    options::{
+        check_option_keys, get_matcher, get_paths_argument, get_possible_int_from_js_object,
+        get_roots_argument, get_searcher_options, LineTerminatorMode, SearcherOptions,
+        PATTERN_OPTIONS, SEARCH_OPTIONS,
+    },
+    results::{Delivery, MatchResult, ResultSender, SearchSummary},
+    search::DirectorySearch,
+    search_roots, search_with_timeout,
+};
+
+/// A search started by one of the search functions, for `cancelSearch`.
+///
+/// Dropping it doesn't stop the search, since a caller that's only listening for results doesn't need to keep it.
+pub(crate) struct SearchToken {
+    // Shared with the search, which stops once this is set
+    pub(crate) cancelled: Arc<AtomicBool>,
+}
+
+impl Finalize for SearchToken {}
+
+/// Stops a search started by one of the search functions, without affecting any other searches.
+/// It still calls `onComplete`, with `cancelled` set in the summary, unless it had already finished.
+///
+/// JS function signature: (token) => void
+pub(crate) fn cancel_search(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let token = cx.argument::<JsBox<SearchToken>>(0)?;
+    token.cancelled.store(true, Ordering::SeqCst);
+    Ok(cx.undefined())
+}
+
+/// Everything needed to start a search, prepared ahead of time by `createSearchContext`
+/// so that back-to-back searches don't each have to parse options and compile the pattern.
+pub(crate) struct SearchContext {
+    pub(crate) searcher_opts: SearcherOptions,
+    pub(crate) matcher: Arc<SearchMatcher>,
+    // Searches using this context run here rather than in Rayon's global thread pool
+    pub(crate) thread_pool: Arc<rayon::ThreadPool>,
+}
+
+impl Finalize for SearchContext {}
+
+/// Builds a thread pool for searches to run in, with one thread per CPU unless `threads` is given.
+pub(crate) fn build_thread_pool<'a>(
+    cx: &mut impl Context<'a>,
+    threads: Option<usize>,
+) -> NeonResult<rayon::ThreadPool> {
+    let mut builder = rayon::ThreadPoolBuilder::new();
+    if let Some(threads) = threads {
+        builder = builder.num_threads(threads);
+    }
+    match builder.build() {
+        Ok(thread_pool) => Ok(thread_pool),
+        Err(e) => cx.throw_error(format!("Rust Error: couldn't start threads: {}", e)),
+    }
+}
+
+/// Prepares a context for the `searchContext` option.
+///
+/// Takes the same options object as `multithreadedSearchDirectory` (without the callbacks),
+/// plus `threads`, the number of threads to search with, which defaults to one per CPU
+/// (and is always one with `parallel: false`).
+pub(crate) fn create_search_context(mut cx: FunctionContext) -> JsResult<JsBox<SearchContext>> {
+    let options = cx.argument::<JsObject>(0)?;
+    check_option_keys(
+        options,
+        &mut cx,
+        &[PATTERN_OPTIONS, SEARCH_OPTIONS, &["threads"]],
+    )?;
+    let mut searcher_opts = get_searcher_options(options, &mut cx)?;
+    let matcher = get_matcher(options, &mut cx, &mut searcher_opts)?;
+
+    let threads = if searcher_opts.parallel {
+        get_possible_int_from_js_object(options, &mut cx, "threads")?
+    } else {
+        Some(1)
+    };
+    let thread_pool = build_thread_pool(&mut cx, threads)?;
+
+    Ok(cx.boxed(SearchContext {
+        searcher_opts,
+        matcher,
+        thread_pool: Arc::new(thread_pool),
+    }))
+}
+
+/// One file's results, kept by a `research` cache along with the size and modification time they were found at
+pub(crate) struct CachedFile {
+    size: u64,
+    modified: Option<SystemTime>,
+    results: Vec<MatchResult>,
+}
+
+/// What a search for `research` needs from its cache
+pub(crate) struct CacheLookup<'a> {
+    cached: &'a BTreeMap<PathBuf, CachedFile>,
+    // Every file the search has come across, with its size and modification time
+    found: Mutex<HashMap<PathBuf, (u64, Option<SystemTime>)>>,
+}
+
+impl CacheLookup<'_> {
+    /// Records a file that's been come across, returning whether it's cached with the same size and modification time.
+    pub(crate) fn is_unchanged(&self, path: &Path) -> Result<bool, RipgrepjsError> {
+        let metadata = std::fs::metadata(path)?;
+        let key = (metadata.len(), metadata.modified().ok());
+        self.found.lock().unwrap().insert(path.to_path_buf(), key);
+        Ok(self
+            .cached
+            .get(path)
+            .is_some_and(|file| (file.size, file.modified) == key))
+    }
+}
+
+/// Each file's results from earlier searches, for `research`
+pub(crate) struct ResultCache {
+    searcher_opts: SearcherOptions,
+    matcher: Arc<SearchMatcher>,
+    // Held for the whole of each `research`, so that they happen one at a time
+    files: Arc<Mutex<BTreeMap<PathBuf, CachedFile>>>,
+}
+
+impl Finalize for ResultCache {}
+
+/// Creates an empty cache of results for `research`.
+///
+/// Takes the same options object as `multithreadedSearchDirectory`, without the callbacks.
+pub(crate) fn create_result_cache(mut cx: FunctionContext) -> JsResult<JsBox<ResultCache>> {
+    let options = cx.argument::<JsObject>(0)?;
+    check_option_keys(options, &mut cx, &[PATTERN_OPTIONS, SEARCH_OPTIONS])?;
+    let mut searcher_opts = get_searcher_options(options, &mut cx)?;
+    let matcher = get_matcher(options, &mut cx, &mut searcher_opts)?;
+    Ok(cx.boxed(ResultCache {
+        searcher_opts,
+        matcher,
+        files: Arc::new(Mutex::new(BTreeMap::new())),
+    }))
+}
+
+/// Searches the paths again with a cache's options, replacing their results in the cache,
+/// then passes every result in the cache to `callback`, ordered by path.
+///
+/// JS function signature: (cache, paths: string[], callback: (error: Error | null, results?: object[]) => void) => void;
+///
+/// Directories are walked again, but only files whose size or modification time has changed since they were
+/// last searched are searched again. Files that are gone (or are now filtered out) are dropped from the cache,
+/// as are files that couldn't be searched, so that they're tried again next time. The first call, with the
+/// roots of the search, fills the cache. Options that don't report results as objects (like `countMode`,
+/// `matchStats`, `matchPathsOnly`, `jsonLines`, and `lspMode`) don't apply.
+pub(crate) fn research(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let cache = cx.argument::<JsBox<ResultCache>>(0)?;
+    let paths = get_paths_argument(&mut cx)?;
+    let callback = cx.argument::<JsFunction>(2)?.root(&mut cx);
+    let searcher_opts = cache.searcher_opts.clone();
+    let matcher = Arc::clone(&cache.matcher);
+    let files = Arc::clone(&cache.files);
+    let file_name_filter = match searcher_opts.to_file_name_filter() {
+        Ok(file_name_filter) => file_name_filter,
+        Err(e) => {
+            let js_error = e.to_js_error(&mut cx, format!("Rust Error: {}", e))?;
+            return cx.throw(js_error);
+        }
+    };
+    let channel = cx.channel();
+
+    thread::spawn(move || {
+        let mut files = files.lock().unwrap();
+        let roots = paths
+            .iter()
+            .map(PathBuf::from)
+            .filter_map(|path| match std::fs::metadata(&path) {
+                Ok(metadata) => Some((path, metadata.is_file())),
+                // Anything cached from under it is dropped along with the rest of what wasn't found
+                Err(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let mut search = DirectorySearch::new(
+            &searcher_opts,
+            file_name_filter,
+            &matcher,
+            ResultSender::Buffer(Arc::clone(&buffer)),
+            false,
+            Arc::new(AtomicBool::new(false)),
+        );
+        search.cache_lookup = Some(CacheLookup {
+            cached: &files,
+            found: Mutex::new(HashMap::new()),
+        });
+        let outcome = search_roots(&search, &roots).map(|()| {
+            let mut found = search
+                .cache_lookup
+                .take()
+                .unwrap()
+                .found
+                .into_inner()
+                .unwrap();
+            for error in search.errors.into_inner().unwrap() {
+                if let RipgrepjsError::InFile(path, _) = error {
+                    found.remove(&path);
+                }
+            }
+            found
+        });
+        let outcome = outcome.map(|found| {
+            let mut new_results: HashMap<Arc<str>, Vec<MatchResult>> = HashMap::new();
+            for delivery in std::mem::take(&mut *buffer.lock().unwrap()) {
+                match delivery {
+                    Delivery::Match(result) => {
+                        if let Some(path) = &result.path {
+                            new_results
+                                .entry(Arc::clone(path))
+                                .or_default()
+                                .push(result);
+                        }
+                    }
+                    Delivery::Group(_, Some(path), results) => {
+                        new_results.entry(path).or_default().extend(results);
+                    }
+                    _ => {}
+                }
+            }
+
+            files.retain(|path, _| {
+                found.contains_key(path) || !paths.iter().any(|root| path.starts_with(root))
+            });
+            for (path, (size, modified)) in found {
+                if files
+                    .get(&path)
+                    .is_some_and(|file| (file.size, file.modified) == (size, modified))
+                {
+                    continue;
+                }
+                let reported_path = searcher_opts.reported_path(&path);
+                let results = new_results.remove(&*reported_path).unwrap_or_default();
+                files.insert(
+                    path,
+                    CachedFile {
+                        size,
+                        modified,
+                        results,
+                    },
+                );
+            }
+
+            let mut results = files
+                .values()
+                .flat_map(|file| file.results.iter().cloned())
+                .collect::<Vec<_>>();
+            for (seq, result) in results.iter_mut().enumerate() {
+                result.seq = seq as u64;
+            }
+            results
+        });
+        drop(files);
+
+        channel.send(move |mut cx| {
+            let args: Vec<Handle<JsValue>> = match outcome {
+                Ok(results) => {
+                    let js_results = cx.empty_array();
+                    for (idx, result) in results.into_iter().enumerate() {
+                        let js_result = result.into_js_object(&mut cx)?;
+                        js_results.set(&mut cx, idx as u32, js_result)?;
+                    }
+                    vec![cx.null().upcast(), js_results.upcast()]
+                }
+                Err(e) => vec![e
+                    .to_js_error(&mut cx, format!("Rust Error: {}", e))?
+                    .upcast()],
+            };
+            let null = cx.null();
+            callback.into_inner(&mut cx).call(&mut cx, null, args)?;
+            Ok(())
+        });
+    });
+
+    Ok(cx.undefined())
+}
+
+/// The most results an `openSearch` search finds ahead of what's been pulled, before it waits
+pub(crate) const OPEN_SEARCH_QUEUE_SIZE: usize = 256;
+
+/// A search started by `openSearch`, whose results are pulled one at a time with `nextSearchResult`
+pub(crate) struct SearchHandle {
+    // Results wait here until they're pulled; `None` once the handle has been closed
+    receiver: Arc<Mutex<Option<mpsc::Receiver<Delivery>>>>,
+    // Set by the search's thread once it's over, before the queue is disconnected
+    outcome: Arc<Mutex<Option<Result<SearchSummary, RipgrepjsError>>>>,
+    // Set when the handle is cancelled or closed, so that the search stops at its next result
+    closed: Arc<AtomicBool>,
+}
+
+impl SearchHandle {
+    /// Stops the search and drops any results it had queued, but keeps the queue, so that pulling
+    /// waits for the search to wind down and then finds its summary.
+    fn cancel(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        // Frees up the queue for anything that was waiting to be queued before the search noticed the flag.
+        // If a pull is waiting on the queue, it's empty, and the pull drops whatever comes next anyway.
+        if let Ok(receiver) = self.receiver.try_lock() {
+            if let Some(receiver) = receiver.as_ref() {
+                receiver.try_iter().for_each(drop);
+            }
+        }
+    }
+
+    /// Stops the search and drops the queue along with any results it had, for a handle that's gone.
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        // If a pull is waiting on the queue, it's empty, so the search isn't blocked on it
+        // and will notice the flag instead
+        if let Ok(mut receiver) = self.receiver.try_lock() {
+            *receiver = None;
+        }
+    }
+}
+
+impl Finalize for SearchHandle {
+    fn finalize<'a, C: Context<'a>>(self, _: &mut C) {
+        self.close();
+    }
+}
+
+/// Takes the next result off an `openSearch` queue, skipping errors and skipped files,
+/// which only go to the summary. Once the search has been cancelled, every result is skipped.
+///
+/// Fails with `Disconnected` once the search is over and every result has been pulled (or the handle
+/// was closed), and with `Empty` if `wait` is false and there isn't a result ready yet.
+pub(crate) fn pull_result(
+    receiver: &Mutex<Option<mpsc::Receiver<Delivery>>>,
+    closed: &AtomicBool,
+    wait: bool,
+) -> Result<Delivery, mpsc::TryRecvError> {
+    let receiver = receiver.lock().unwrap();
+    let receiver = match receiver.as_ref() {
+        Some(receiver) => receiver,
+        None => return Err(mpsc::TryRecvError::Disconnected),
+    };
+    loop {
+        let delivery = if wait {
+            receiver
+                .recv()
+                .map_err(|_| mpsc::TryRecvError::Disconnected)?
+        } else {
+            receiver.try_recv()?
+        };
+        if delivery.is_result() && !closed.load(Ordering::SeqCst) {
+            return Ok(delivery);
+        }
+    }
+}
+
+/// Starts a search in the background whose results are pulled on demand, rather than pushed to a callback.
+///
+/// JS function signature: (searcherOptions, path: string | string[]) => handle
+///
+/// Takes the same options as `multithreadedSearchDirectory`, except for the callbacks; errors that
+/// only affect one file are only reported in the summary. Results are queued as they're found,
+/// and the search waits whenever `OPEN_SEARCH_QUEUE_SIZE` of them haven't been pulled yet.
+pub(crate) fn open_search(mut cx: FunctionContext) -> JsResult<JsBox<SearchHandle>> {
+    let options = cx.argument::<JsObject>(0)?;
+    let roots = get_roots_argument(&mut cx)?;
+    let (searcher_opts, matcher, thread_pool) = get_search_setup(options, &mut cx)?;
+    let file_name_filter = match searcher_opts.to_file_name_filter() {
+        Ok(file_name_filter) => file_name_filter,
+        Err(e) => {
+            let js_error = e.to_js_error(&mut cx, format!("Rust Error: {}", e))?;
+            return cx.throw(js_error);
+        }
+    };
+
+    let (sender, receiver) = mpsc::sync_channel(OPEN_SEARCH_QUEUE_SIZE);
+    let closed = Arc::new(AtomicBool::new(false));
+    let outcome = Arc::new(Mutex::new(None));
+    let results = ResultSender::Bounded(sender, Arc::clone(&closed));
+    let search_outcome = Arc::clone(&outcome);
+    // Closing the handle stops the search too
+    let cancelled = Arc::clone(&closed);
+    thread::spawn(move || {
+        // Kept until the outcome is set, so that pulling can't find the queue disconnected before then
+        let keep_open = results.clone();
+        let search = || {
+            search_with_timeout(
+                &searcher_opts,
+                file_name_filter,
+                &matcher,
+                results,
+                false,
+                cancelled,
+                &roots[..],
+                search_roots,
+            )
+        };
+        let summary = match thread_pool {
+            Some(thread_pool) => thread_pool.install(search),
+            None => search(),
+        };
+        *search_outcome.lock().unwrap() = Some(summary);
+        drop(keep_open);
+    });
+
+    Ok(cx.boxed(SearchHandle {
+        receiver: Arc::new(Mutex::new(Some(receiver))),
+        outcome,
+        closed,
+    }))
+}
+
+/// Pulls the next result from an `openSearch` handle.
+///
+/// JS function signature: (handle, callback: (error: Error | null, result?: object, summary?: object) => void) => void
+///
+/// The callback is always called asynchronously, with the next result, or without one once the search is over.
+/// The first call without a result has the search's summary (with `cancelled` set if `closeSearch` stopped it),
+/// or an error if the search failed. After `closeSearch`, it waits for the search to wind down first,
+/// so nothing is left searching once it's called back.
+pub(crate) fn next_search_result(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let handle = cx.argument::<JsBox<SearchHandle>>(0)?;
+    let callback = cx.argument::<JsFunction>(1)?.root(&mut cx);
+    let receiver = Arc::clone(&handle.receiver);
+    let outcome = Arc::clone(&handle.outcome);
+    let channel = cx.channel();
+
+    let respond = move |next: Result<Delivery, mpsc::TryRecvError>| {
+        channel.send(move |mut cx| {
+            let null = cx.null();
+            let args: Vec<Handle<JsValue>> = match next {
+                Ok(delivery) => match delivery.into_js_result(&mut cx)? {
+                    Some(js_result) => vec![null.upcast(), js_result.upcast()],
+                    None => vec![null.upcast()],
+                },
+                Err(_) => match outcome.lock().unwrap().take() {
+                    Some(Ok(summary)) => {
+                        let undefined = cx.undefined();
+                        let js_summary = summary.to_js_object(&mut cx)?;
+                        vec![null.upcast(), undefined.upcast(), js_summary.upcast()]
+                    }
+                    Some(Err(e)) => {
+                        let js_error = e.to_js_error(&mut cx, format!("Rust Error: {}", e))?;
+                        vec![js_error.upcast()]
+                    }
+                    None => vec![null.upcast()],
+                },
+            };
+            callback.into_inner(&mut cx).call(&mut cx, null, args)?;
+            Ok(())
+        });
+    };
+
+    // Only waiting for a result needs a thread of its own
+    match pull_result(&handle.receiver, &handle.closed, false) {
+        Err(mpsc::TryRecvError::Empty) => {
+            let closed = Arc::clone(&handle.closed);
+            thread::spawn(move || respond(pull_result(&receiver, &closed, true)));
+        }
+        next => respond(next),
+    }
+    Ok(cx.undefined())
+}
+
+/// Stops an `openSearch` search early, dropping any results it had queued. Pulling from its handle
+/// afterwards finds no more results, just the summary, with `cancelled` set.
+///
+/// JS function signature: (handle) => void
+pub(crate) fn close_search(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let handle = cx.argument::<JsBox<SearchHandle>>(0)?;
+    handle.cancel();
+    Ok(cx.undefined())
+}
+
+/// A matcher compiled ahead of time by `compilePattern`, so it can be reused across searches.
+///
+/// The line terminator and multiline mode are baked into the matcher, so searches using it
+/// must have the same ones.
+pub(crate) struct CompiledPattern {
+    pub(crate) matcher: Arc<SearchMatcher>,
+    pub(crate) line_terminator: LineTerminatorMode,
+    pub(crate) multi_line: bool,
+    pub(crate) word_list: Option<Arc<WordList>>,
+    pub(crate) pattern_matchers: Option<Arc<Vec<SearchMatcher>>>,
+    pub(crate) capture_names: Arc<Vec<Option<String>>>,
+}
+
+impl Finalize for CompiledPattern {}
+
+/// Compiles a pattern for use as the `compiledPattern` option.
+///
+/// Takes an options object with the same pattern-related keys as `multithreadedSearchDirectory`
+/// (`pattern`, `caseInsensitive`, `smartCase`, `caseFoldMode`, `dotMatchesNewline`, `greedySwap`, `ignoreWhitespace`,
+/// `unicode`, `octal`, `crlf`, `wordBoundariesOnly`, `wholeLine`, `prefixAnchor`, `fixedStrings`,
+/// `normalizeForMatch`, `allowEmptyPattern`, and the alternatives to `pattern`: `wordList`, `patterns`, and `patternFile`),
+/// along with `lineTerminatorMode`, `lineTerminator`, `nullData`, and `multilineSearch`, which the search must match.
+pub(crate) fn compile_pattern(mut cx: FunctionContext) -> JsResult<JsBox<CompiledPattern>> {
+    let options = cx.argument::<JsObject>(0)?;
+    check_option_keys(options, &mut cx, &[PATTERN_OPTIONS])?;
+    match compile_pattern_from_options(options, &mut cx)? {
+        Ok(compiled_pattern) => Ok(cx.boxed(compiled_pattern)),
+        Err(e) => {
+            let js_error = e.to_js_error(&mut cx, format!("Rust Error: {}", e))?;
+            cx.throw(js_error)
+        }
+    }
+}
@@ -15,68 +15,1339 @@
  */
 
 import {EventEmitter} from 'events';
-// TODO: figure out if an async iterator is possible
 // TODO: Support buffering it all in Rust to make it faster (or maybe only buffer n entries in a Vec?)
 export interface RipgrepOptions {
 	afterContext: number;
 	beforeContext: number;
+	/** Lets matches span several lines. They never span files: each file is searched separately. */
 	multilineSearch: boolean;
 	invertMatch: boolean;
 	includeLineNumbers: boolean;
+	/**
+	 * Reports every line, not just the matches: the lines in between are passed through as context,
+	 * with the `contextKind` `"other"`. Each result has `matched` set to tell them apart.
+	 */
 	passthru: boolean;
 	heapLimit?: number;
+	/**
+	 * Stops searching a file at the first non-matching line after a match, which is useful for sorted data.
+	 * That line is still reported as context, so there's at most one line of after-context.
+	 */
+	stopOnNonmatch?: boolean;
+	/**
+	 * Stops the search after this many matches in total, across every file searched.
+	 * Files are searched in parallel, so which matches those are can vary from run to run.
+	 */
+	maxTotalMatches?: number;
+	/**
+	 * Stops the search once this many files have been searched, setting the summary's `limitReached`,
+	 * as a safeguard against searching far more than intended (like `/`). Files filtered out by `fileNameGlob` don't count.
+	 */
+	maxFilesSearched?: number;
+	/**
+	 * Gives up on the search after this many milliseconds, setting the summary's `timedOut`.
+	 * Results found before then are still reported.
+	 */
+	timeoutMs?: number;
+	/** Stops searching each file at its first match, so every file contributes at most one result. */
+	firstMatchPerFile?: boolean;
+	/** Only searches files whose names (not full paths) match one of these globs, like `*.test.ts`. */
+	fileNameGlob?: string | string[];
+	/**
+	 * Defines file types by the globs their names match, like `rg --type-add 'web:*.html,*.css,*.js'`,
+	 * for `fileTypes` and `fileTypesNot`. A type defined more than once (or with the name of a built-in type)
+	 * matches all of its globs. Names can only have letters and numbers.
+	 */
+	customTypes?: {name: string, globs: string | string[]}[];
+	/**
+	 * Only searches files of these types, along with `fileNameGlob`: ripgrep's built-in types (like `rust` or `js`,
+	 * as `rg --type-list` lists them), or `customTypes`.
+	 */
+	fileTypes?: string | string[];
+	/** Leaves out files of these types (built-in or `customTypes`), even if they're also of a type in `fileTypes`. */
+	fileTypesNot?: string | string[];
+	/**
+	 * Only searches files within these limits, checked before each file is opened: sizes in bytes (inclusive),
+	 * and modification times in milliseconds since the Unix epoch, like `Date.now()` (exclusive).
+	 * For example, `{modifiedAfterMs: Date.now() - 7 * 24 * 60 * 60 * 1000}` only searches files changed
+	 * in the last week. Doesn't apply to `searchBlobs`.
+	 */
+	metadataFilter?: {minSize?: number, maxSize?: number, modifiedAfterMs?: number, modifiedBeforeMs?: number};
+	/**
+	 * How lines end in the searched files. `"byte"` uses the `lineTerminator` byte.
+	 *
+	 * Enabling `crlf` implies `"crlf"` mode, since the searcher and the pattern have to agree on line endings.
+	 */
+	lineTerminatorMode?: 'lf' | 'crlf' | 'byte';
+	/**
+	 * The line terminator for `lineTerminatorMode: "byte"`, as a number from 0 to 255 or a string of exactly one byte
+	 * (like `'\0'` or `';'`). Anything else, like `'\r\n'` or `'é'` (two bytes in UTF-8), throws rather than being cut short.
+	 */
+	lineTerminator?: number | string;
+	/**
+	 * Searches records separated by NUL bytes (like the output of `find -print0`) as if they were lines,
+	 * like `rg --null-data`: the pattern and searcher both treat NUL as the line terminator, `lineNumber`s count
+	 * records, and `matchedLines` are records. `binaryDetection` doesn't apply, since every record ends in a NUL.
+	 * Unlike in ripgrep, `^` and `$` still only match at the start and end of the file and around `\n`,
+	 * not around each NUL. Can't be used with `crlf` or `lineTerminatorMode`.
+	 */
+	nullData?: boolean;
+	/**
+	 * Skips matches whose text has already been reported, either per file or across the whole search (the default).
+	 * Every distinct matched line is held in memory to do so.
+	 */
+	unique?: boolean;
+	uniqueScope?: 'file' | 'search';
+	/**
+	 * How matches that aren't valid UTF-8 are reported: as a `hexDump` with `"hex"`, or with each invalid sequence
+	 * replaced by `invalidCharReplacement` with `"lossy"`. With `"text"` (the default), each one is passed to
+	 * `onError` instead, and the search carries on.
+	 */
+	binaryOutput?: 'text' | 'hex' | 'lossy';
+	/**
+	 * Stops the whole search at the first match that isn't valid UTF-8 with `binaryOutput: "text"`, rather than
+	 * passing it to `onError` and carrying on. Its error (with the code `INVALID_UTF8`, and the `path` and
+	 * `lineNumber` of the match) is in the summary's `errors`, and the summary isn't marked `cancelled`.
+	 */
+	strictUtf8?: boolean;
+	/**
+	 * Replaces each invalid UTF-8 sequence in lossily converted lines (including inline context lines).
+	 * Defaults to U+FFFD; use `''` to drop invalid bytes.
+	 */
+	invalidCharReplacement?: string;
+	/**
+	 * How context lines (from `beforeContext`, `afterContext`, and `passthru`) are reported.
+	 *
+	 * - `"separate"` (the default) reports each context line as its own result, with a `contextKind`.
+	 *   With `invertMatch`, these are the lines that matched the pattern.
+	 * - `"inline"` gives each result `before` and `after` arrays instead,
+	 *   holding it back until its after-context has been read.
+	 * - `"none"` doesn't report context lines.
+	 */
+	contextMode?: 'none' | 'separate' | 'inline';
+	/**
+	 * With `contextMode: "inline"`, combines matches whose context windows overlap (or touch) into one result,
+	 * like ripgrep draws overlapping `-C` windows as one block, so no line is reported twice.
+	 * Its `matchedLines` are every line in the combined span, starting at its `lineNumber`,
+	 * and its `matches` are the matches in it, without `before` or `after`.
+	 */
+	mergeAdjacent?: boolean;
+	/**
+	 * With `mergeAdjacent`, the most `matches` reported in one combined result (though always at least its first),
+	 * setting `matchesTruncated` if any were left out. Its `matchedLines` still cover every match.
+	 */
+	maxMatchesPerGroup?: number;
+	/**
+	 * Reports a result between groups of context lines that aren't next to each other, like ripgrep's
+	 * `--context-separator`, with a `contextKind` of `"separator"` and this string as its only `matchedLines`
+	 * (even from `searchRaw`). Without it, there are no separators.
+	 */
+	contextSeparator?: string;
+	/** Lists the paths of matching files in the summary's `matchedFiles`, each followed by a NUL byte. */
+	nullSeparated?: boolean;
+	/** Adds `fileSize` and `modifiedMs` to each result. Each file is only stat()ed once. */
+	includeFileMetadata?: boolean;
+	/**
+	 * Adds the names of the other files (not directories) in each matching file's directory to its results,
+	 * as `siblings`. Each directory is only read once per search.
+	 */
+	includeSiblings?: boolean;
+	/** Matches the pattern against file paths instead of file contents, reporting each matching `path`. */
+	matchPathsOnly?: boolean;
+	/**
+	 * Reports the `path` of each file that passes the filters (like `fileNameGlob`), exactly as a real search
+	 * would choose them, but never opens any of them. Useful for checking filters before a big search.
+	 */
+	dryRun?: boolean;
+	/**
+	 * Reports each match as its own result, with just the matched text (rather than the whole line) as its
+	 * `matchedLines`, for pulling tokens out of files. With `unique`, each distinct piece of text is only reported once.
+	 * Nothing is extracted from inverted matches.
+	 */
+	extractMatches?: boolean;
+	/** Strips leading and trailing whitespace, including the line terminator, from reported lines. */
+	trim?: boolean;
+	/**
+	 * Replaces each run of whitespace within reported lines (and `highlightedLine`s and context lines) with a single
+	 * space, leaving line terminators alone, for tidier output from irregularly spaced text. Only the reported text
+	 * is changed: `matchRanges` and other offsets still refer to the original lines, which `searchRaw` reports as they are.
+	 */
+	collapseWhitespace?: boolean;
+	/**
+	 * Whether reported lines (including context lines) end with their line terminator (`\n`, or `\r\n` with `crlf`).
+	 * Defaults to `true`, in which case only the last line of a file that doesn't end with a terminator lacks one,
+	 * so the lines can be joined back into the file's exact text.
+	 */
+	keepLineTerminator?: boolean;
+	/**
+	 * Adds `matchRanges` to each match, giving where in its lines the pattern matched.
+	 * With `invertMatch`, these are always empty.
+	 */
+	includeMatchRanges?: boolean;
+	/**
+	 * Adds `matchByteRange` to each match: where the first match in its lines starts and ends, in bytes from
+	 * the start of the file, for editors that select by byte offset. With `multilineSearch`, that's the whole
+	 * span of a match across lines. Inverted matches don't have one.
+	 */
+	includeMatchByteRange?: boolean;
+	/**
+	 * Adds `indent` to each match: how many columns of spaces and tabs its first line starts with,
+	 * for grouping matches by how deeply they're nested. It's measured before `trim`.
+	 */
+	includeIndent?: boolean;
+	/** With `includeIndent`, the columns between tab stops, which each tab reaches the next of. Defaults to 4. */
+	tabWidth?: number;
+	/**
+	 * The most `matchRanges` to report from any one line; the rest are dropped, and `matchRangesTruncated` is set.
+	 * They still count towards each range's `totalMatchesOnLine`.
+	 */
+	maxMatchesPerLine?: number;
+	/**
+	 * Adds `captures` to each match, giving where each of the pattern's capture groups matched.
+	 * With `invertMatch`, these are never reported.
+	 */
+	includeCaptures?: boolean;
+	/**
+	 * Adds `highlightedLine` to each match: its lines with `before` and `after` (like `'\x1b[31m'` and `'\x1b[0m'`)
+	 * around each match of the pattern. Matches that overlap or touch are wrapped together.
+	 */
+	highlight?: {before: string, after: string};
+	/**
+	 * Adds `snippet` to each match: its first match with up to this many characters either side of it,
+	 * and `…` at each end that was cut off, for compact previews of long lines.
+	 */
+	contextChars?: number;
+	/**
+	 * Holds back each file's results until it's been searched, then reports them together as one
+	 * `RipgrepFileGroup` result, which is easier to render than regrouping results that arrive interleaved.
+	 * Every result from a file is kept in memory until it's finished, which adds up for files with many matches.
+	 */
+	groupByFile?: boolean;
+	/**
+	 * Passes each file's results (and its `onFileStart` and `onFileEnd` calls) on together, with files in the
+	 * order they started being searched, while still searching several files at once. Unlike `groupByFile`,
+	 * results are still reported one at a time; but a file's results are held back until every file that
+	 * started before it is done, so one slow file holds up the rest.
+	 */
+	orderByFile?: boolean;
+	/**
+	 * Searches inside `.tar`, `.tar.gz` (or `.tgz`), and `.zip` archives found while searching, reporting each file
+	 * in one as if it were at `<archive path>!<path in the archive>`. Members are streamed straight out of the archive,
+	 * one at a time, without extracting anything. Filters like `fileNameGlob` apply to the archive's own name,
+	 * and `headingPattern`, `headerLines`, `includeFileMetadata`, `includeSiblings`, regions (like `startLine`),
+	 * `multilineWindow`, and `chunkSize` don't apply to members. Archives compressed any other way (`.tar.bz2`, `.tar.xz`, and `.tar.zst`)
+	 * can't be looked inside of, so they're skipped with the `onSkip` reason `"compressedArchive"`.
+	 */
+	searchArchives?: boolean;
+	/**
+	 * Passes results to JavaScript in batches from one dedicated thread (the default),
+	 * rather than one at a time from each searching thread. See `benches/callback-thread.js`.
+	 */
+	callbackThread?: boolean;
+	/**
+	 * With `callbackThread`, the most results passed to JavaScript at once. Defaults to 256.
+	 * Bigger batches mean fewer trips to the JavaScript thread, for throughput on a bulk export.
+	 */
+	batchSize?: number;
+	/**
+	 * With `callbackThread`, how long a batch can wait to fill up to `batchSize` after its first result,
+	 * in milliseconds, before it's passed on anyway. Without it, results are passed on as soon as they're ready,
+	 * for the lowest latency in a live UI. Batches never reorder results, and the last one isn't held up
+	 * once the search is over.
+	 */
+	flushIntervalMs?: number;
+	/**
+	 * Searches on several threads at once (the default). With `false`, everything runs on one thread, and
+	 * directories are walked depth-first in order of file name, so results (and their `seq`s) arrive in the same
+	 * order every run, which is slower but reproducible for debugging and tests.
+	 */
+	parallel?: boolean;
+	/** Only searches this many bytes from the start of each file, which is much faster for checking headers. */
+	maxBytesPerFile?: number;
+	/**
+	 * What to do with files containing a NUL byte, which are probably binary.
+	 * `"quit"` stops searching them, and `"convert"` searches them with NULs treated as line terminators.
+	 */
+	binaryDetection?: 'none' | 'quit' | 'convert';
+	/**
+	 * Counts matching lines or occurrences of the pattern (several per line) in the summary's `counts`,
+	 * instead of reporting each match.
+	 */
+	countMode?: 'lines' | 'matches';
+	/**
+	 * Adds up the lines, bytes (including line terminators), and whitespace-separated words of matching lines,
+	 * like `wc`, in the summary's `matchStats` and `totalMatchStats`, instead of reporting each match.
+	 */
+	matchStats?: boolean;
+	/**
+	 * Adds the `column` where each match starts, and counts `matchRanges` offsets, in this unit rather than bytes.
+	 * Use `"utf16"` for offsets into JavaScript strings (and editors like VS Code).
+	 */
+	columnEncoding?: 'utf8' | 'utf16' | 'char';
+	/**
+	 * Reports each result's lines as `encodedLines`, Buffers of their text in this encoding, rather than as
+	 * strings in `matchedLines`. They're decoded the same way either way, so `binaryOutput`, `trim`, and
+	 * `collapseWhitespace` still apply.
+	 */
+	outputEncoding?: 'utf8' | 'utf16le' | 'utf16be';
+	/**
+	 * Splits files bigger than this many bytes into chunks (split between lines) that are searched in parallel,
+	 * so one huge file can use every thread. Results still arrive in order with the right line numbers.
+	 * Ignored when an option needs to see the whole file in order: `multilineSearch`, context lines, `passthru`,
+	 * `stopOnNonmatch`, `firstMatchPerFile`, `uniqueScope: "file"`, `headingPattern`, `groupByFile`, `orderByFile`,
+	 * `maxConcurrentFiles`, and `maxTotalMatches`.
+	 * Chunks of several megabytes work best; each one is opened and searched separately.
+	 */
+	chunkSize?: number;
+	/**
+	 * The most files open for searching at once, whatever the number of threads, for systems with a low limit
+	 * on open files (`ulimit -n`). Threads wait for a file to be closed before opening another.
+	 */
+	maxConcurrentFiles?: number;
+	/**
+	 * With `multilineSearch`, reads each file this many lines at a time rather than all at once,
+	 * so huge files can be searched without holding them in memory. Every match spanning up to this many lines
+	 * is found; longer ones are only found if they happen to fit in the twice as many lines read at once,
+	 * so they shouldn't be relied on. Context lines aren't reported.
+	 */
+	multilineWindow?: number;
+	/**
+	 * Follows symbolic links to files and directories, rather than skipping them.
+	 * Each file is still only searched once, even if it can be reached through several links.
+	 */
+	followSymlinks?: boolean;
+	/** With `followSymlinks`, reports files by their path through the link, rather than where it points. */
+	reportLinkPath?: boolean;
+	/**
+	 * Leaves out files and directories matched by `.gitignore` files, in each directory searched
+	 * and every directory above it, like ripgrep does. Unlike ripgrep, they apply outside git repositories too.
+	 */
+	respectGitignore?: boolean;
+	/**
+	 * Leaves out files and directories matched by the global gitignore: the file named by git's `core.excludesFile`,
+	 * or `~/.config/git/ignore` (or `$XDG_CONFIG_HOME/git/ignore`). Defaults to `respectGitignore`,
+	 * but can be turned on or off by itself. `.gitignore` files take precedence over it.
+	 */
+	respectGlobalGitignore?: boolean;
+	/**
+	 * Reports paths (in results and the summary) with `/` between their components, even on Windows,
+	 * where they'd otherwise use `\`. Files are still opened by their real paths.
+	 */
+	forwardSlashPaths?: boolean;
+	/**
+	 * Reports paths (in results and the summary) as absolute, canonical paths, even for relative roots,
+	 * so they don't depend on the current directory. A file that can't be canonicalized (like one deleted
+	 * partway through the search) is made absolute against the current directory instead, as is every file
+	 * with `reportLinkPath`, which keeps links unresolved. Files are still opened by their real paths.
+	 */
+	absolutePaths?: boolean;
+	/**
+	 * Only searches lines `startLine` through `endLine` (inclusive, counting from 1) of each file.
+	 * Finding where `startLine` begins still means reading (but not searching) every line before it.
+	 */
+	startLine?: number;
+	endLine?: number;
+	/**
+	 * Only searches lines of each file that start at or after `startByte` and before `endByte`,
+	 * seeking straight to them. Line numbers are still counted from the start of the file,
+	 * which means reading everything before `startByte` unless `includeLineNumbers` is off.
+	 * Can't be used with `startLine` or `endLine`.
+	 */
+	startByte?: number;
+	endByte?: number;
+	/**
+	 * Only searches the last `tailLines` lines of each file (or all of a shorter file), like recent entries
+	 * in an append-only log, finding them by reading backwards from the end. Line numbers are still counted
+	 * from the start of the file, which means reading everything before them unless `includeLineNumbers` is off.
+	 * Can't be used with `startLine`, `endLine`, `startByte`, or `endByte`.
+	 */
+	tailLines?: number;
+	/**
+	 * Only reports matches starting on these line numbers (counting from 1), like lines from a diff that
+	 * should be checked again. Turns `includeLineNumbers` on, and stops reading each file after the last one.
+	 */
+	lineFilter?: number[];
+	/**
+	 * Reports results as strings of JSON in the format of `rg --json`: a `begin` message before each file's
+	 * first result, a `match` or `context` message for each line, and an `end` message with the file's statistics.
+	 * The `summary` message is in the SearchSummary's `jsonSummary`. Other options about the format of results,
+	 * like `countMode` or `extractMatches`, don't apply. See `searchJsonLines` to write them to a stream.
+	 */
+	jsonLines?: boolean;
+	/**
+	 * Reports an LspLocation for each match, rather than a RipgrepResult for each matching line, so that results
+	 * can be handed straight to an editor. Turns `includeLineNumbers` on; context lines aren't reported, and
+	 * an inverted match's location is its whole line. See `searchLspLocations`.
+	 */
+	lspMode?: boolean;
+	/**
+	 * Called for errors that only affect one file, which is skipped without stopping the search.
+	 * Currently, that's exceeding the `heapLimit`, with the code `HEAP_LIMIT`. It's also called for each match
+	 * that isn't valid UTF-8 with `binaryOutput: "text"` (unless `strictUtf8` is set), with the code
+	 * `INVALID_UTF8` and the match's `lineNumber`; only that match is left out, and the file is still searched.
+	 */
+	onError?: (error: RipgrepError) => void;
+	/**
+	 * Called for each file left out of the search: `"binary"` if `binaryDetection: "quit"` stopped reading it,
+	 * `"tooLarge"` if it exceeded the `heapLimit`, `"ignored"` if `fileNameGlob` (or `fileTypes`, or `fileTypesNot`)
+	 * filtered it out or a gitignore rule did (which also leaves out directories, with `respectGitignore` or
+	 * `respectGlobalGitignore`), `"metadata"` if `metadataFilter` did, `"skipPattern"` if one of its lines
+	 * matched the `skipFilePattern`, and `"compressedArchive"` if `searchArchives` found an archive compressed
+	 * in a way it can't read.
+	 */
+	onSkip?: (
+		path: string, reason: 'binary' | 'tooLarge' | 'ignored' | 'metadata' | 'skipPattern' | 'compressedArchive'
+	) => void;
+	/**
+	 * Calls `onIgnore` for each file filtered out by its name or by a gitignore rule, to find out why a file
+	 * is missing from the results. Off by default, since working out which filter it was takes extra matching.
+	 */
+	debugIgnores?: boolean;
+	/**
+	 * With `debugIgnores`, called for each file filtered out by its name, with the option that left it out
+	 * as its `source`. `rule` is the glob it matched for `fileTypesNot`, or `null` for `fileNameGlob` and
+	 * `fileTypes`, where it didn't match any.
+	 *
+	 * With `respectGitignore` or `respectGlobalGitignore`, it's also called for each file or directory a gitignore rule
+	 * left out, with the rule as it's written in the file (like `*.log` or `/build/`) as `rule`, and the path of
+	 * the `.gitignore` (or global gitignore) it's from as `source`.
+	 */
+	onIgnore?: (
+		path: string, rule: string | null, source: 'fileNameGlob' | 'fileTypes' | 'fileTypesNot' | string
+	) => void;
+	/**
+	 * Called with each file's path before any of its results, so that results can be grouped by file
+	 * as they stream in. A file whose results have all been reported gets an `onFileEnd`,
+	 * with the number of matches in it; one that fails partway through doesn't.
+	 */
+	onFileStart?: (path: string) => void;
+	onFileEnd?: (path: string, matchCount: number) => void;
+	/**
+	 * Called every so often as files are searched, with how far the search has got.
+	 * Updates are throttled in Rust, so a search of many small files doesn't flood the event loop.
+	 */
+	onProgress?: (progress: SearchProgress) => void;
+	/** The least time between `onProgress` calls, in milliseconds. Defaults to 50. */
+	progressIntervalMs?: number;
+	/**
+	 * Called before any results for likely mistakes that don't stop the search.
+	 * Currently, that's a pattern that can match the empty string (like `a*`), and so matches every line,
+	 * with the code `EMPTY_MATCH`.
+	 */
+	onWarning?: (warning: RipgrepError) => void;
+	/** Called once every result has been delivered. */
+	onComplete?: (summary: SearchSummary) => void;
 	caseInsensitive: boolean;
 	smartCase: boolean;
+	/**
+	 * How `caseInsensitive` and `smartCase` decide which characters are the same letter.
+	 * `"default"` uses the regex engine's Unicode case folding, where a letter also matches lookalikes
+	 * such as the Kelvin sign `K` for `k`. `"ascii"` only folds ASCII letters, so that locale-sensitive
+	 * letters like Turkish `ı` and `İ` only match themselves. `"simple"` matches each character with just its
+	 * own uppercase and lowercase forms. Inline flags like `(?i)` always use the default.
+	 */
+	caseFoldMode?: 'default' | 'ascii' | 'simple';
 	dotMatchesNewline: boolean;
 	greedySwap: boolean;
 	ignoreWhitespace: boolean;
+	/**
+	 * When false, `\b`, `wordBoundariesOnly`, `\w` and `.` work on ASCII bytes rather than
+	 * Unicode characters, so a letter like `é` counts as a non-word byte. This is noticeably
+	 * faster on ASCII-only input such as logs (see `benches/unicode.js`).
+	 */
 	unicode: boolean;
 	octal: boolean;
+	/**
+	 * Treats `\r\n` as a single line ending in `^`, `$`, and `.`, so matches never end with a stray `\r`.
+	 * With `multilineSearch`, write line breaks in the pattern as `\r\n`.
+	 */
 	crlf: boolean;
 	wordBoundariesOnly: boolean;
-	pattern: string;
+	/** Only matches entire lines. Takes precedence over `wordBoundariesOnly`. */
+	wholeLine?: boolean;
+	/**
+	 * Takes `pattern` as a plain string (no escaping needed) and only matches it at the start of a line,
+	 * after any indentation, like `^\s*prefix`. Handy for autocompletion; see `benches/prefix-anchor.js`.
+	 * Ignored with `wordList` or `patternFile`.
+	 */
+	prefixAnchor?: boolean;
+	/**
+	 * Takes `pattern` as a plain string (no escaping needed), like ripgrep's `--fixed-strings`,
+	 * so `highlight`, `includeMatchRanges`, and the like find it as written, metacharacters and all.
+	 * Ignored with `wordList` or `patternFile`, and when `pattern` is a Buffer, which is always literal.
+	 */
+	fixedStrings?: boolean;
+	/**
+	 * Matches the pattern against a copy of each line that's lowercased and has its diacritics stripped,
+	 * with the pattern's own literals normalized the same way, so `cafe` matches `Café`, whether the é is one
+	 * character or an e followed by a combining accent.
+	 * Results still have the original lines, and their offsets (like `matchRanges`) are mapped back into them.
+	 * Classes and escapes match the normalized line as they are, so `[^e]` doesn't match `é`, and `[A-Z]` matches
+	 * any (lowercased) letter from A to Z.
+	 * Letters in any script lose the marks that Unicode canonical decomposition (NFD) splits off them, so Greek `ά`,
+	 * Cyrillic `й`, and Vietnamese `ệ` match `α`, `и`, and `e`, but letters that don't decompose, like `ø` and `ß`,
+	 * stay as they are. It's ignored without `unicode` or when `pattern` is a Buffer,
+	 * and can't be used with `multilineSearch`, since each line is normalized on its own.
+	 */
+	normalizeForMatch?: boolean;
+	/**
+	 * The regex to search for, in the syntax of Rust's `regex` crate, which doesn't have lookaround or backreferences;
+	 * the error for a pattern that uses them (like one from PCRE) says which feature it was.
+	 * A Buffer is searched for as exactly those bytes instead, which don't have to be
+	 * valid UTF-8 (like a file format's magic number); case-insensitivity and `prefixAnchor` don't apply to it.
+	 *
+	 * The other patterns a search can have (`headingPattern`, `excludePattern`, `skipFilePattern`, and `subPattern`)
+	 * are compiled with the same options as this one.
+	 */
+	pattern: string | Buffer;
+	/**
+	 * Allows an empty `pattern`, which matches every line, much like `passthru`.
+	 * Without it, an empty `pattern` is usually a mistake, so it's an error with the code `EMPTY_PATTERN`.
+	 */
+	allowEmptyPattern?: boolean;
+	/**
+	 * Literal terms to match any of, instead of `pattern`, reporting which one matched as `matchedTerm`.
+	 * They're compiled into a single alternation, which the regex engine's literal optimizations make fast
+	 * even for thousands of terms.
+	 * With `wordBoundariesOnly`, each term has to start and end at a word boundary, except at an end that's
+	 * a non-word character (like the `@` in `@user`), which matches wherever it appears.
+	 */
+	wordList?: string[];
+	/**
+	 * Patterns to match any of, instead of `pattern`, reporting which ones matched each result as `patternIndices`.
+	 * They're searched for together, so each file is only read once however many there are (see `searchMultiPattern`).
+	 * If one of them doesn't compile, the error says which, by its index. An empty array matches nothing.
+	 */
+	patterns?: string[];
+	/**
+	 * A file of patterns to match any of, one per line (like ripgrep's `-f`), instead of `pattern`.
+	 * The file is read when the pattern is compiled. A blank line in it matches every line,
+	 * but a file with no patterns matches nothing. If one of them doesn't compile, the error says which,
+	 * by its index among the patterns (which doesn't count lines skipped by `patternFileSkipComments`).
+	 */
+	patternFile?: string;
+	/** Skips blank lines and lines starting with `#` in the `patternFile`, so it can have comments. */
+	patternFileSkipComments?: boolean;
+	/**
+	 * Annotates each match with the closest line before it (in the same file) that matches this pattern,
+	 * as its `heading`, like `git grep --show-function`.
+	 */
+	headingPattern?: string;
+	/**
+	 * Attaches the first this many lines of each file (like a log's timestamp and host banner) to every match in it,
+	 * as its `headerLines`, whether they matched or not. They're read before the file is searched.
+	 */
+	headerLines?: number;
+	/**
+	 * Leaves out matches whose lines also match this pattern.
+	 * Every matched line is searched again for it, which can nearly double the matching work when most lines match.
+	 */
+	excludePattern?: string;
+	/**
+	 * Leaves out every file with a line that matches this pattern, such as a marker for generated code,
+	 * even if it also matches `pattern`. Each file is read for it before it's searched, up to the first line
+	 * that matches, so files that are searched are read twice.
+	 */
+	skipFilePattern?: string;
+	/**
+	 * Only searches files containing this exact text, byte for byte, which is checked for much faster than
+	 * `pattern` is searched for. It's a shortcut for text every match must contain, so it has to agree with
+	 * `pattern`: it isn't affected by `caseInsensitive` or the like. Each file is read for it before it's searched,
+	 * only as far as where it's found. Ignored with `invertMatch`, `matchPathsOnly`, and `dryRun`.
+	 */
+	requiredLiteral?: string;
+	/**
+	 * Searched for within the text of each matched line, with every match of it reported as the line's
+	 * `subMatches`, like pulling a field out of log lines found by `pattern`.
+	 */
+	subPattern?: string;
+	/** A pattern from `compilePattern`, used instead of `pattern` and the other pattern-related options. */
+	compiledPattern?: CompiledPattern;
+	/** A context from `createSearchContext`, used instead of every other option except the callbacks. */
+	searchContext?: SearchContext;
+}
+
+/**
+ * An opaque handle to a search's options, compiled pattern, and thread pool,
+ * which can be reused across back-to-back searches (like one per keystroke) to skip setting them up each time.
+ */
+export interface SearchContext {
+	readonly __searchContext: never;
+}
+
+/** An opaque handle to each file's results from earlier searches, for `research`. */
+export interface ResultCache {
+	readonly __resultCache: never;
+}
+
+/** The options `compilePattern` reads. */
+export type PatternOptions = Pick<
+	RipgrepOptions,
+	'caseInsensitive' | 'smartCase' | 'caseFoldMode' | 'dotMatchesNewline' | 'greedySwap' | 'ignoreWhitespace' |
+	'unicode' | 'octal' | 'crlf' | 'wordBoundariesOnly' | 'wholeLine' | 'prefixAnchor' | 'fixedStrings' | 'pattern' |
+	'normalizeForMatch' | 'allowEmptyPattern' | 'lineTerminatorMode' | 'lineTerminator' | 'nullData' |
+	'multilineSearch' | 'wordList' | 'patterns' | 'patternFile' | 'patternFileSkipComments'
+>;
+
+/** The ways of giving the pattern to search for, one of which is required. */
+type PatternSource = {pattern: string | Buffer} | {wordList: string[]} | {patterns: string[]} | {patternFile: string};
+
+/**
+ * How `replaceInFile` writes the new contents of a file.
+ *
+ * - `"atomic"` (the default) writes them to a temporary file next to the original and renames it over the original,
+ *   so the file is never half-written, even if the process dies. This needs to be able to create that file.
+ * - `"truncate"` rewrites the original in place, for when a temporary file can't be created next to it.
+ *   If writing fails partway through (like when the disk is full), the file is left half-written.
+ */
+export type ReplaceStrategy = 'atomic' | 'truncate';
+
+/**
+ * An opaque handle to a compiled pattern, which can be reused across searches.
+ * Searches using it must have the same `lineTerminatorMode`, `crlf`, `nullData`, and `multilineSearch` options it was compiled with.
+ */
+export interface CompiledPattern {
+	readonly __compiledPattern: never;
+}
+
+/** An opaque handle to one running search, which `cancel` can stop without affecting any others. */
+export interface SearchToken {
+	readonly __searchToken: never;
+}
+
+/**
+ * The EventEmitter returned by `searchWithEventEmitter` and the like, with the token to pass to `cancel`.
+ * The search runs in the background, so its results are emitted after this is returned.
+ */
+export interface SearchEmitter extends EventEmitter {
+	readonly token: SearchToken;
+}
+
+export interface CaptureSpan {
+	/** The group's number, counting from 1 (0 is the whole match). */
+	index: number;
+	/** The group's name, if it's a named group like `(?P<name>...)`. */
+	name?: string;
+	start: number;
+	end: number;
+	text: string;
+}
+
+/** The versions of ripgrepjs and the crates doing the searching, from `version()`. */
+export interface Versions {
+	ripgrepjs: string;
+	/** The `grep` crate, which ripgrep's searching is built on. */
+	grep: string;
+	grepRegex: string;
+	grepSearcher: string;
+	/** The regex engine itself, which decides how patterns match. */
+	regexAutomata: string;
+	/** The regex parser, which decides which patterns are valid. */
+	regexSyntax: string;
+}
+
+/** What `explain` can tell about a pattern without searching anything. */
+export interface PatternExplanation {
+	/** Whether every match starts at the start of a line (like `^foo`). */
+	anchoredStart: boolean;
+	/** Whether every match ends at the end of a line (like `foo$`). */
+	anchoredEnd: boolean;
+	/** Whether the pattern was compiled for `multilineSearch`, so matches can span lines. */
+	multiline: boolean;
+	/**
+	 * Literals one of which every match starts with, which the regex engine scans for to skip
+	 * most of each file quickly. Absent if there aren't any worth scanning for, which is usually
+	 * why a pattern is slow; a pattern starting with something like `\w+` often has none.
+	 */
+	prefixLiterals?: string[];
+	/** Like `prefixLiterals`, but for what every match ends with. */
+	suffixLiterals?: string[];
+	/** The length in bytes of the shortest possible match. */
+	minLength?: number;
+	/** The length in bytes of the longest possible match; absent if there's no limit. */
+	maxLength?: number;
+}
+
+export interface RipgrepError extends Error {
+	/** A stable code identifying the kind of error, like `HEAP_LIMIT`. */
+	code?: string;
+	/** The file the error happened in, if it's specific to one. */
+	path?: string;
+	/** The line the error is about, if it's about one (and `includeLineNumbers` is on). */
+	lineNumber?: number;
+}
+
+export interface SearchSummary {
+	/** The number of distinct files with at least one match. */
+	filesWithMatches: number;
+	/** Whether anything matched at all, for exit-code-style checks without collecting or counting results. */
+	hadMatches: boolean;
+	/**
+	 * What `grep` would exit with: 2 if there were any errors (in `errors`, or passed to `onError`),
+	 * otherwise 0 if anything matched and 1 if nothing did. A CLI can pass it straight to `process.exit`.
+	 */
+	exitCode: 0 | 1 | 2;
+	/**
+	 * With `nullSeparated`, the path of every file with a match, each followed by `\0`.
+	 * Split it with `.split('\0').slice(0, -1)`; unlike newlines, NUL can't appear in a path.
+	 */
+	matchedFiles?: string;
+	/** With `countMode`, the count for each file with a match, keyed by path. */
+	counts?: {[path: string]: number};
+	/** With `countMode`, the sum of every file's count. */
+	totalCount?: number;
+	/** With `matchStats`, the statistics for each file with a match, keyed by path. */
+	matchStats?: {[path: string]: MatchStats};
+	/** With `matchStats`, every file's statistics added together. */
+	totalMatchStats?: MatchStats;
+	/** Whether `timeoutMs` stopped the search before it finished. */
+	timedOut: boolean;
+	/** Whether `cancel` stopped the search before it finished. */
+	cancelled: boolean;
+	/** Whether `maxFilesSearched` stopped the search before it finished. */
+	limitReached: boolean;
+	/**
+	 * The bytes read from every file opened by the search. Files that stopped being searched early
+	 * (e.g. because of `firstMatchPerFile`) only count what was read of them.
+	 */
+	bytesSearched: number;
+	/** With `dryRun` (or from `countCandidateFiles`), how many files passed the filters and would have been searched. */
+	candidateFiles?: number;
+	/**
+	 * Every error that stopped a file or directory from being searched, like one that couldn't be read,
+	 * each with its `path`. These don't stop the rest of the search, so it always runs to the end.
+	 */
+	errors: RipgrepError[];
+	/** How long the search took, for working out throughput with `bytesSearched`. */
+	elapsedMs: number;
+	/** With `jsonLines`, the final `summary` message, with the totals of every file's statistics. */
+	jsonSummary?: string;
+}
+
+/** Where the pattern matched within a result's lines, for `includeMatchRanges` */
+export interface MatchRange {
+	start: number;
+	end: number;
+	/** Which match on its line this is, counting from 0, for "match 2 of 5 on this line". */
+	matchIndex: number;
+	/** How many times the pattern matched on the line this match starts on, even past `maxMatchesPerLine`. */
+	totalMatchesOnLine: number;
+}
+
+/** Matching lines added up, for `matchStats` */
+export interface MatchStats {
+	lines: number;
+	bytes: number;
+	words: number;
+}
+
+/** How far a search has got, for `onProgress` */
+export interface SearchProgress {
+	filesSearched: number;
+	/** Like the summary's `bytesSearched`, so far. */
+	bytesSearched: number;
+	elapsedMs: number;
 }
 
 export interface RipgrepResult {
-	lines: string[];
+	/**
+	 * Counts up from 0 in the order results were sent, across every thread in the search.
+	 * Results from different files can arrive in any order, so this is useful for reconstructing it.
+	 */
+	seq: number;
+	/**
+	 * The file the result is from, or with `matchPathsOnly` the matching file, or with `dryRun` a file that would be searched.
+	 * Files found through a symbolic link (with `followSymlinks`) are reported by their real path, unless `reportLinkPath` is set.
+	 */
+	path: string;
+	/** The matched lines; absent when a `hexDump` or `encodedLines` are reported instead. */
+	matchedLines?: string[];
+	/** With `outputEncoding`, the matched lines in that encoding. */
+	encodedLines?: Buffer[];
+	/** A `hexdump -C`-style dump of the matched bytes, for non-UTF-8 matches with `binaryOutput: "hex"`. */
+	hexDump?: string;
 	lineNumber?: number;
+	/**
+	 * With `multilineSearch` (and `includeLineNumbers`), the first and last lines a match spans, inclusive,
+	 * for highlighting the whole of a match across lines. `startLine` is the same as `lineNumber`.
+	 * Like `matchedLines`, this covers every match in the result, since matches on adjacent lines are reported together.
+	 */
+	startLine?: number;
+	endLine?: number;
+	/**
+	 * With `includeMatchByteRange`, where the first match in the lines starts and ends (just past its last byte),
+	 * counted in bytes from the start of the file, however the file was searched.
+	 */
+	matchByteRange?: {start: number, end: number};
+	/** With `includeIndent`, the width in columns of the spaces and tabs the first line starts with. */
+	indent?: number;
+	/**
+	 * Set if this result is a context line rather than a match, with `contextMode: "separate"`,
+	 * or a separator between groups of context, with `contextSeparator`.
+	 */
+	contextKind?: 'before' | 'after' | 'other' | 'separator';
+	/** With `passthru`, whether this result is a match, rather than a line that was passed through. */
+	matched?: boolean;
+	/** Context lines before and after the match, with `contextMode: "inline"`. */
+	before?: string[];
+	after?: string[];
+	/** With `mergeAdjacent`, the matches in this span of context, whose lines are all in its `matchedLines`. */
+	matches?: RipgrepResult[];
+	/** With `mergeAdjacent`, whether some of the matches in this span were left out of `matches` for `maxMatchesPerGroup`. */
+	matchesTruncated?: boolean;
+	/** The matched file's size in bytes and modification time, with `includeFileMetadata`. */
+	fileSize?: number;
+	modifiedMs?: number;
+	/**
+	 * With `includeMatchRanges`, the byte offsets of each match, counted from the start of the first matched line.
+	 * Offsets are in the `columnEncoding`'s unit if there is one, and don't account for `trim` or `collapseWhitespace`.
+	 */
+	matchRanges?: MatchRange[];
+	/** Whether some ranges were left out because of `maxMatchesPerLine`. */
+	matchRangesTruncated?: boolean;
+	/**
+	 * With `includeCaptures`, one array for each match of the pattern in the lines, with an entry for each capture group
+	 * (starting with the whole match, as group 0). Groups that didn't take part in the match are `null`.
+	 * Offsets are counted like `matchRanges`.
+	 */
+	captures?: (CaptureSpan | null)[][];
+	/** With `columnEncoding`, where the first match starts in the first line, counting from 1. */
+	column?: number;
+	/** With `wordList`, the term that matched first in the lines. */
+	matchedTerm?: string;
+	/** With `patterns`, the index of the first pattern that matches the lines. */
+	patternIndex?: number;
+	/** With `patterns`, the indices of every pattern that matches the lines, in order. */
+	patternIndices?: number[];
+	/** With `headingPattern`, the closest heading line before the match, without its line terminator. */
+	heading?: string;
+	/** With `headerLines`, the first lines of the match's file, without their line terminators. */
+	headerLines?: string[];
+	/** With `includeSiblings`, the names of the other files in the match's file's directory, sorted. */
+	siblings?: string[];
+	/** With `highlight`, the matched lines (joined together) with the highlighting strings around each match. */
+	highlightedLine?: string;
+	/** With `contextChars`, the first match and the characters around it. Other offsets are still into the whole line. */
+	snippet?: string;
+	/** With `subPattern`, each of its matches within the lines. Offsets are counted like `matchRanges`. */
+	subMatches?: {start: number, end: number, text: string}[];
 }
 
-const multithreadedSearchDirectory = require('./ripgrepjs.node').multithreadedSearchDirectory as (
-	options: RipgrepOptions,
-	path: string,
-	onResult: (result: RipgrepResult) => void
-) => void;
+/** A position in a file, as the Language Server Protocol has it. */
+export interface LspPosition {
+	/** Counting from 0. */
+	line: number;
+	/** UTF-16 code units into the line, counting from 0. */
+	character: number;
+}
+
+/** Where a match is, with `lspMode`: an LSP `Location`. */
+export interface LspLocation {
+	/** The file's `file://` URI, made absolute if its path was relative; `null` for text that isn't from a file. */
+	uri: string | null;
+	/** The end is just past the match, and is on a later line than the start for matches across lines. */
+	range: {start: LspPosition, end: LspPosition};
+}
+
+/** A result from `searchRaw`, whose lines are left as bytes. */
+export interface RawRipgrepResult {
+	seq: number;
+	path: string;
+	/** The matched (or context) lines, exactly as they are in the file, minus the last line terminator unless `keepLineTerminator` is on. */
+	bytes: Buffer;
+	/** Where `bytes` starts in the file. */
+	byteOffset: number;
+	lineNumber?: number;
+	/** Like `RipgrepResult`'s. */
+	startLine?: number;
+	endLine?: number;
+	contextKind?: 'before' | 'after' | 'other';
+	matched?: boolean;
+}
+
+/** Every result from one file, with `groupByFile`. */
+export interface RipgrepFileGroup {
+	/** Counts up along with results' `seq`s; the results in the group have their own. */
+	seq: number;
+	path: string;
+	matches: RipgrepResult[];
+}
 
 /**
- * Searches a directory with multithreading, returning results through an EventEmitter.
- *
- * @returns An EventEmitter whose 'result' event will emit RipgrepResult objects.
+ * A search started by `openSearch`, whose results are found as they're pulled. It's an async iterator,
+ * so it works with `for await`; once it's done, `next()` resolves with the SearchSummary as its `value`.
  */
-export function searchWithEventEmitter(options: Partial<RipgrepOptions> & {pattern: string}, path: string) {
-	const rustOptions: RipgrepOptions = {
-		afterContext: options.afterContext || 0,
-		beforeContext: options.beforeContext || 0,
+export interface SearchHandle extends AsyncIterableIterator<RipgrepResult | RipgrepFileGroup> {
+	next(): Promise<IteratorResult<RipgrepResult | RipgrepFileGroup, SearchSummary | undefined>>;
+	/**
+	 * Stops the search early, dropping any results it had found but that haven't been pulled.
+	 * The next `next()` resolves as done once the search has wound down, with a summary that has `cancelled` set
+	 * (unless it had already finished).
+	 */
+	cancel(): void;
+	/**
+	 * Cancels the search, and resolves once it has wound down, so nothing is left searching.
+	 * Breaking out of a `for await` loop does this.
+	 */
+	return(): Promise<IteratorResult<RipgrepResult | RipgrepFileGroup, undefined>>;
+}
+
+/** The Rust side of a SearchHandle */
+interface SearchHandleBinding {
+	readonly __searchHandle: never;
+}
+
+/** A file's contents to search with `searchBlobs`, and the path to report its results with */
+export interface Blob {
+	path: string;
+	data: Buffer;
+}
+
+type SearchBinding<Targets = string | string[]> = (
+	options: RipgrepOptions,
+	path: Targets,
+	onResult: (result: RipgrepResult | RipgrepFileGroup) => void
+) => SearchToken;
+
+const bindings = require('./ripgrepjs.node');
+const multithreadedSearchDirectory = bindings.multithreadedSearchDirectory as SearchBinding;
+const searchFileList = bindings.searchFileList as SearchBinding;
+const searchRawBinding = bindings.searchRaw as SearchBinding;
+const countCandidateFilesBinding = bindings.countCandidateFiles as SearchBinding;
+const searchBlobsBinding = bindings.searchBlobs as SearchBinding<Blob[]>;
+const openSearchBinding = bindings.openSearch as (
+	options: RipgrepOptions, path: string | string[]
+) => SearchHandleBinding;
+const nextSearchResultBinding = bindings.nextSearchResult as (
+	handle: SearchHandleBinding,
+	callback: (error: RipgrepError | null, result?: RipgrepResult | RipgrepFileGroup, summary?: SearchSummary) => void
+) => void;
+const closeSearchBinding = bindings.closeSearch as (handle: SearchHandleBinding) => void;
+const cancelSearchBinding = bindings.cancelSearch as (token: SearchToken) => void;
+const createSearchContextBinding = bindings.createSearchContext as (
+	options: RipgrepOptions & {threads?: number}
+) => SearchContext;
+const createResultCacheBinding = bindings.createResultCache as (options: RipgrepOptions) => ResultCache;
+const researchBinding = bindings.research as (
+	cache: ResultCache, paths: string[], callback: (error: RipgrepError | null, results?: RipgrepResult[]) => void
+) => void;
+const compilePatternBinding = bindings.compilePattern as (options: PatternOptions) => CompiledPattern;
+const validatePatternBinding = bindings.validatePattern as (
+	options: PatternOptions
+) => {valid: true} | {valid: false, error: string};
+const explainBinding = bindings.explain as (options: PatternOptions) => PatternExplanation;
+const versionBinding = bindings.version as () => Versions;
+const replaceInFileBinding = bindings.replaceInFile as (
+	options: PatternOptions & {replaceStrategy?: ReplaceStrategy}, path: string, replacement: string,
+	callback: (error: RipgrepError | null, replacements?: number) => void
+) => void;
+
+// Options without defaults are passed along as they were given, so that the bindings throw for any they don't know
+function patternOptionsWithDefaults(options: Partial<PatternOptions>) {
+	const rustOptions: PatternOptions = {
+		...options,
 		multilineSearch: options.multilineSearch || false,
-		invertMatch: options.invertMatch || false,
-		includeLineNumbers: options.includeLineNumbers || true,
-		passthru: options.passthru || false,
 		caseInsensitive: options.caseInsensitive || false,
 		smartCase: options.smartCase || false,
 		dotMatchesNewline: options.dotMatchesNewline || false,
 		greedySwap: options.greedySwap || false,
 		ignoreWhitespace: options.ignoreWhitespace || false,
-		unicode: options.unicode || true,
+		unicode: options.unicode ?? true,
 		octal: options.octal ?? false,
 		crlf: options.crlf || false,
 		wordBoundariesOnly: options.wordBoundariesOnly || false,
-		pattern: options.pattern,
+		pattern: options.pattern ?? '',
+	};
+	return rustOptions;
+}
+
+/**
+ * Compiles a pattern once, so it can be passed as `compiledPattern` to any number of searches
+ * without being recompiled each time.
+ */
+export function compilePattern(options: Partial<PatternOptions> & PatternSource) {
+	return compilePatternBinding(patternOptionsWithDefaults(options));
+}
+
+/**
+ * Prepares a search's options, pattern, and thread pool once, so they can be passed as `searchContext`
+ * to any number of searches. `threads` defaults to one per CPU.
+ */
+export function createSearchContext(
+	options: Omit<
+		Partial<RipgrepOptions>,
+		'onError' | 'onSkip' | 'debugIgnores' | 'onIgnore' | 'onFileStart' | 'onFileEnd' | 'onProgress' | 'onWarning'
+		| 'onComplete' | 'searchContext'
+	> &
+	(PatternSource | {compiledPattern: CompiledPattern}) & {threads?: number}
+) {
+	return createSearchContextBinding(rustOptionsWithDefaults(options));
+}
+
+/**
+ * Checks whether a pattern compiles with the given options, without searching anything.
+ * Unlike `compilePattern`, this doesn't throw for an invalid pattern, so it's handy for validating input as it's typed.
+ */
+export function validatePattern(options: Partial<PatternOptions> & PatternSource) {
+	return validatePatternBinding(patternOptionsWithDefaults(options));
+}
+
+/**
+ * Describes what the regex engine makes of a pattern, like the literals it scans for,
+ * for working out why a search is slow. Throws if the pattern doesn't compile.
+ */
+export function explain(options: Partial<PatternOptions> & PatternSource) {
+	return explainBinding(patternOptionsWithDefaults(options));
+}
+
+/**
+ * Reports the versions of ripgrepjs and the `grep` and regex crates it was built with,
+ * for working out why a pattern behaves differently in another environment.
+ */
+export function version() {
+	return versionBinding();
+}
+
+/**
+ * Replaces every match of the pattern in a file with `replacement`, in which `$1` or `${name}` stand for
+ * what a capture group matched (and `$$` for a literal `$`). Matches never span lines.
+ *
+ * Lines without matches, and every line's terminator, are left exactly as they were. By default, the new contents
+ * are written to a temporary file next to the original and renamed over it, so the file is never half-written;
+ * see `ReplaceStrategy` for writing to the original directly. If `path` is a symlink, the file it points to is rewritten.
+ *
+ * @returns A Promise resolving to the number of matches replaced; the file isn't touched if there weren't any.
+ */
+export function replaceInFile(
+	options: Partial<PatternOptions> & PatternSource & {replaceStrategy?: ReplaceStrategy},
+	path: string,
+	replacement: string
+) {
+	return new Promise<number>((resolve, reject) => {
+		replaceInFileBinding(patternOptionsWithDefaults(options), path, replacement, (error, replacements) => {
+			if (error) {
+				reject(error);
+			} else {
+				resolve(replacements || 0);
+			}
+		});
+	});
+}
+
+type SearchOptions = Partial<RipgrepOptions> & (
+	PatternSource | {compiledPattern: CompiledPattern} | {searchContext: SearchContext}
+);
+
+/**
+ * Creates an empty cache of each file's results, for `research`. It keeps the options it's created with,
+ * so that every search into it matches in the same way.
+ */
+export function createResultCache(
+	options: Omit<
+		Partial<RipgrepOptions>,
+		'onError' | 'onSkip' | 'debugIgnores' | 'onIgnore' | 'onFileStart' | 'onFileEnd' | 'onProgress' | 'onWarning'
+		| 'onComplete' | 'searchContext'
+	> &
+	(PatternSource | {compiledPattern: CompiledPattern})
+) {
+	return createResultCacheBinding(rustOptionsWithDefaults(options));
+}
+
+/**
+ * Searches the given files and directories again with a cache's options, like when a file watcher reports changes,
+ * and merges their new results into the cache. Call it with the roots of the search first to fill the cache.
+ *
+ * Only files whose size or modification time has changed since they were last searched are actually searched again.
+ * Files that are gone (or that couldn't be read) are dropped from the cache. Options that don't report
+ * RipgrepResults, like `countMode`, `matchStats`, `matchPathsOnly`, `jsonLines`, and `lspMode`, don't apply.
+ *
+ * Paths are compared as they're given, so a directory should be spelled the same way in every call.
+ *
+ * @returns A Promise resolving to every result in the cache, ordered by path, with `seq`s counting up in that order.
+ */
+export function research(cache: ResultCache, paths: string | string[]) {
+	return new Promise<RipgrepResult[]>((resolve, reject) => {
+		researchBinding(cache, typeof paths === 'string' ? [paths] : paths, (error, results) => {
+			if (error) {
+				reject(error);
+			} else {
+				resolve(results || []);
+			}
+		});
+	});
+}
+
+function rustOptionsWithDefaults(options: Partial<RipgrepOptions>) {
+	const rustOptions: RipgrepOptions = {
+		...patternOptionsWithDefaults(options),
+		afterContext: options.afterContext || 0,
+		beforeContext: options.beforeContext || 0,
+		invertMatch: options.invertMatch || false,
+		includeLineNumbers: options.includeLineNumbers ?? true,
+		passthru: options.passthru || false,
 	};
-	if (typeof options.heapLimit === 'number') rustOptions.heapLimit = options.heapLimit;
+	return rustOptions;
+}
+
+function searchWith<Targets>(binding: SearchBinding<Targets>, options: SearchOptions, path: Targets): SearchEmitter {
+	const rustOptions = rustOptionsWithDefaults(options);
 
 	const emitter = new EventEmitter();
-	multithreadedSearchDirectory(rustOptions, path, result => {
+	rustOptions.onError = error => {
+		options.onError?.(error);
+		emitter.emit('fileError', error);
+	};
+	rustOptions.onWarning = warning => {
+		options.onWarning?.(warning);
+		emitter.emit('warning', warning);
+	};
+	rustOptions.onComplete = summary => {
+		options.onComplete?.(summary);
+		emitter.emit('end', summary);
+	};
+	const token = binding(rustOptions, path, result => {
 		emitter.emit('result', result);
 	});
-	return emitter;
+	return Object.assign(emitter, {token});
+}
+
+/**
+ * Stops a search started by `searchWithEventEmitter` and the like early, leaving any other searches running.
+ * Its 'end' event is still emitted, with `cancelled` set in the summary, unless it had already finished.
+ */
+export function cancel(search: SearchEmitter | SearchToken) {
+	cancelSearchBinding('token' in search ? search.token : search);
+}
+
+/**
+ * Searches a directory (or several) with multithreading, returning results through an EventEmitter.
+ * Directories reachable from more than one of the given paths are only searched once.
+ * Paths to files are searched on their own, and a path that can't be read (e.g. one that doesn't exist)
+ * throws a RipgrepError with its `path` and a `code` like `ENOENT` before anything is searched.
+ *
+ * @returns An EventEmitter whose 'result' event will emit RipgrepResult objects (or RipgrepFileGroups, with `groupByFile`),
+ * followed by a single 'end' event with a SearchSummary once the search is complete.
+ * Errors that only affect one file are emitted as 'fileError' events with a RipgrepError (not as 'error' events,
+ * which would throw without a listener), and warnings (see `onWarning`) as 'warning' events.
+ */
+export function searchWithEventEmitter(options: SearchOptions, path: string | string[]) {
+	return searchWith(multithreadedSearchDirectory, options, path);
+}
+
+/**
+ * Searches exactly the given files with multithreading, without traversing any directories,
+ * like a list from `git diff --name-only`.
+ *
+ * @returns An EventEmitter like `searchWithEventEmitter`'s.
+ * Paths that don't exist (with the code `ENOENT`) or aren't files are emitted as 'fileError' events.
+ */
+export function searchFilesWithEventEmitter(options: SearchOptions, paths: string[]) {
+	return searchWith(searchFileList, options, paths);
+}
+
+/**
+ * Searches like `searchWithEventEmitter`, but emits RawRipgrepResults, whose lines are `Buffer`s of the bytes
+ * in the file, rather than strings. Nothing is decoded, so this works with any encoding and never throws for
+ * invalid UTF-8; decode them however suits. `binaryOutput`, `trim`, `collapseWhitespace`, and
+ * `invalidCharReplacement` don't apply.
+ */
+export function searchRaw(options: SearchOptions, path: string | string[]) {
+	return searchWith(searchRawBinding, options, path);
+}
+
+/**
+ * Searches like `searchWithEventEmitter`, but only as fast as results are pulled from the returned SearchHandle:
+ * the search finds a limited number ahead, then waits. This suits a slow consumer better than a stream of events.
+ * Callbacks like `onError` aren't called; errors that only affect one file are in the summary's `errors`.
+ */
+export function openSearch(
+	options: Omit<
+		SearchOptions,
+		'onError' | 'onSkip' | 'debugIgnores' | 'onIgnore' | 'onFileStart' | 'onFileEnd' | 'onProgress' | 'onWarning'
+		| 'onComplete'
+	>,
+	path: string | string[]
+): SearchHandle {
+	const binding = openSearchBinding(rustOptionsWithDefaults(options), path);
+	const handle: SearchHandle = {
+		next() {
+			return new Promise((resolve, reject) => {
+				nextSearchResultBinding(binding, (error, result, summary) => {
+					if (error) {
+						reject(error);
+					} else if (result) {
+						resolve({done: false, value: result});
+					} else {
+						resolve({done: true, value: summary});
+					}
+				});
+			});
+		},
+		cancel() {
+			closeSearchBinding(binding);
+		},
+		async return() {
+			handle.cancel();
+			// Pulling after cancelling only finds the end, once the search has wound down;
+			// a search that failed has nothing more to report to a loop that's been broken out of
+			await handle.next().catch(() => undefined);
+			return {done: true, value: undefined};
+		},
+		[Symbol.asyncIterator]() {
+			return handle;
+		},
+	};
+	return handle;
+}
+
+/**
+ * Searches for several patterns at once, reading each file only once rather than once per pattern,
+ * like a set of lint rules over the same tree. Each result's `patternIndices` say which of `patterns`
+ * match its lines.
+ *
+ * @returns An EventEmitter like `searchWithEventEmitter`'s.
+ */
+export function searchMultiPattern(
+	patterns: string[],
+	options: Partial<
+		Omit<RipgrepOptions, 'pattern' | 'wordList' | 'patternFile' | 'compiledPattern' | 'searchContext'>
+	>,
+	path: string | string[]
+) {
+	return searchWith(multithreadedSearchDirectory, {...options, patterns}, path);
+}
+
+/**
+ * Searches the contents of blobs, like files from a git tree, without them having to be on disk.
+ * Blobs are searched in parallel, and their results reported with their `path`s, which `fileNameGlob` applies to.
+ *
+ * @returns An EventEmitter like `searchWithEventEmitter`'s.
+ * Options that read the file itself (`headingPattern`, `includeFileMetadata`, `includeSiblings`, `startLine`,
+ * `endLine`, `startByte`, `endByte`, and `chunkSize`) are ignored.
+ */
+export function searchBlobs(options: SearchOptions, blobs: Blob[]) {
+	return searchWith(searchBlobsBinding, options, blobs);
+}
+
+/**
+ * Searches with `extractMatches` and `unique`, collecting every distinct piece of matched text into a set.
+ * A `searchContext` has to have been created with those options itself, since they can't be changed.
+ */
+export function collectDistinctMatches(options: SearchOptions, path: string | string[]) {
+	return new Promise<Set<string>>((resolve, reject) => {
+		const matches = new Set<string>();
+		try {
+			const emitter = searchWithEventEmitter({...options, extractMatches: true, unique: true}, path);
+			emitter.on('result', (result: RipgrepResult) => {
+				if (!result.contextKind && result.matchedLines) matches.add(result.matchedLines[0]);
+			});
+			emitter.on('end', () => resolve(matches));
+		} catch (error) {
+			reject(error);
+		}
+	});
+}
+
+/**
+ * Searches like `searchWithEventEmitter`, collecting the results into an array instead.
+ * At most `limit` matches are collected (along with any context lines reported with them);
+ * if there are more, the search stops early and `truncated` is set.
+ *
+ * With a `searchContext`, whose `maxTotalMatches` can't be changed, the search runs to the end
+ * but results past the limit are still dropped.
+ */
+export function collectWithLimit(options: SearchOptions, path: string | string[], limit: number) {
+	return new Promise<{matches: RipgrepResult[], truncated: boolean}>((resolve, reject) => {
+		const matches: RipgrepResult[] = [];
+		let matchCount = 0;
+		let truncated = false;
+		// Searching for one more match than the limit shows whether there were any more
+		const maxTotalMatches = Math.min(options.maxTotalMatches ?? Infinity, limit + 1);
+		try {
+			const emitter = searchWithEventEmitter({...options, maxTotalMatches}, path);
+			emitter.on('result', (result: RipgrepResult) => {
+				if (!result.contextKind && ++matchCount > limit) truncated = true;
+				if (!truncated) matches.push(result);
+			});
+			emitter.on('end', () => resolve({matches, truncated}));
+		} catch (error) {
+			reject(error);
+		}
+	});
+}
+
+/**
+ * Walks the paths and applies the filters exactly like a `dryRun`, but only counts the files that would be searched,
+ * without opening any of them or passing their paths to JavaScript. Handy as the total for a progress bar
+ * driven by `onProgress`'s `filesSearched`.
+ *
+ * @returns A Promise resolving to the number of files that would be searched.
+ */
+export function countCandidateFiles(options: SearchOptions, path: string | string[]) {
+	return new Promise<number>((resolve, reject) => {
+		try {
+			const emitter = searchWith(countCandidateFilesBinding, options, path);
+			emitter.on('end', (summary: SearchSummary) => resolve(summary.candidateFiles ?? 0));
+		} catch (error) {
+			reject(error);
+		}
+	});
+}
+
+/**
+ * Searches with `lspMode`.
+ *
+ * @returns A Promise resolving to the location of every match, in the order they were reported.
+ */
+export function searchLspLocations(options: SearchOptions, path: string | string[]): Promise<LspLocation[]> {
+	return new Promise<LspLocation[]>((resolve, reject) => {
+		const locations: LspLocation[] = [];
+		try {
+			const emitter = searchWithEventEmitter({...options, lspMode: true}, path);
+			emitter.on('result', (location: LspLocation) => locations.push(location));
+			emitter.on('end', () => resolve(locations));
+		} catch (error) {
+			reject(error);
+		}
+	});
+}
+
+/**
+ * Searches with `jsonLines`, writing each message followed by a newline to `output` (like a file or
+ * `process.stdout`), so that the output can be used by tools that read `rg --json`.
+ * The `summary` message is written last.
+ *
+ * @returns A Promise resolving once the search is complete; without an `output`, to every message.
+ */
+export function searchJsonLines(options: SearchOptions, path: string | string[]): Promise<string[]>;
+export function searchJsonLines(
+	options: SearchOptions,
+	path: string | string[],
+	output: {write(chunk: string): unknown}
+): Promise<void>;
+export function searchJsonLines(
+	options: SearchOptions,
+	path: string | string[],
+	output?: {write(chunk: string): unknown}
+) {
+	return new Promise<string[] | void>((resolve, reject) => {
+		const lines: string[] = [];
+		const write = (line: string) => {
+			if (output) {
+				output.write(line + '\n');
+			} else {
+				lines.push(line);
+			}
+		};
+		try {
+			const emitter = searchWithEventEmitter({...options, jsonLines: true}, path);
+			emitter.on('result', write);
+			emitter.on('end', (summary: SearchSummary) => {
+				if (summary.jsonSummary) write(summary.jsonSummary);
+				resolve(output ? undefined : lines);
+			});
+		} catch (error) {
+			reject(error);
+		}
+	});
 }
@@ -4,402 +4,706 @@
 //! - to support the use of BurntSushi's `grep` crate from within Node.js
 //! - to simplify the `grep` crate's API to make it more user-friendly
 
-use std::{convert::Infallible, path::Path, str::Utf8Error, sync::Arc};
-
-use grep::{
-    matcher::LineTerminator,
-    regex::{RegexMatcher, RegexMatcherBuilder},
-    searcher::{Searcher, SearcherBuilder, SinkError, SinkMatch},
+mod archive;
+mod error;
+mod handles;
+mod matcher;
+mod normalization;
+mod options;
+mod results;
+mod search;
+mod sink;
+mod walk;
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, mpsc, Arc},
+    thread,
+    time::SystemTime,
 };
-use neon::{macro_internal::runtime::string, prelude::*, result::Throw};
+
+use grep::matcher::{Captures, Matcher};
+use neon::prelude::*;
 use rayon::prelude::*;
 
-#[derive(Debug)]
-enum RipgrepjsError {
-    JavaScript(neon::result::Throw),
-    StringConversion(Utf8Error),
-    Regex(grep::regex::Error),
-    IO(std::io::Error),
-    Sink(String),
-}
+use crate::{
+    error::RipgrepjsError,
+    handles::{
+        build_thread_pool, cancel_search, close_search, compile_pattern, create_result_cache,
+        create_search_context, next_search_result, open_search, research, CompiledPattern,
+        SearchContext, SearchToken,
+    },
+    matcher::{capture_names, matches_empty, MatcherOptions, SearchMatcher, WordList},
+    options::{
+        byte_literal_pattern, check_option_keys, get_blobs_argument, get_bool_or_default,
+        get_line_terminator, get_matcher, get_matcher_options, get_paths_argument,
+        get_possible_buffer_from_js_object, get_possible_enum_from_js_object,
+        get_possible_function_from_js_object, get_possible_string_from_js_object,
+        get_roots_argument, get_searcher_options, get_string_from_js_object,
+        get_strings_from_js_object, js_value_to_strings, read_pattern_file, FileNameFilter,
+        LineTerminatorMode, ReplaceStrategy, SearcherOptions, PATTERN_OPTIONS, SEARCH_OPTIONS,
+    },
+    results::{spawn_callback_thread, Callbacks, Delivery, ResultSender, SearchSummary},
+    search::DirectorySearch,
+    sink::strip_line_terminator,
+    walk::{search_blob_list, search_directory_inner, search_file_list_inner},
+};
 
-impl From<neon::result::Throw> for RipgrepjsError {
-    fn from(error: neon::result::Throw) -> Self {
-        RipgrepjsError::JavaScript(error)
-    }
-}
-impl From<Utf8Error> for RipgrepjsError {
-    fn from(error: Utf8Error) -> Self {
-        RipgrepjsError::StringConversion(error)
+/// Replaces every match in a file, for `replaceInFile`. Returns the number of matches replaced.
+///
+/// Each line is replaced on its own, so matches never span lines, and every line keeps its original terminator.
+/// A symlink is followed, so that the file it points to is what's rewritten, rather than the link being replaced.
+/// With `ReplaceStrategy::Atomic`, the new contents are written to a temporary file next to the original,
+/// which is then renamed over it, so the file is never left half-written. With `ReplaceStrategy::Truncate`,
+/// they're written straight into the original, which can be left half-written if that fails partway through.
+/// Nothing is written if nothing matched.
+fn replace_matches_in_file(
+    matcher: &SearchMatcher,
+    line_terminator: LineTerminatorMode,
+    path: &Path,
+    replacement: &[u8],
+    strategy: ReplaceStrategy,
+) -> Result<usize, RipgrepjsError> {
+    let path = std::fs::canonicalize(path)?;
+    let contents = std::fs::read(&path)?;
+    let line_terminator = line_terminator.to_line_terminator();
+    let mut replaced = Vec::with_capacity(contents.len());
+    let mut captures = matcher.new_captures()?;
+    let mut replacements = 0;
+    for line in contents.split_inclusive(|&byte| byte == line_terminator.as_byte()) {
+        let text = strip_line_terminator(line_terminator, line);
+        matcher.replace_with_captures(text, &mut captures, &mut replaced, |captures, dst| {
+            captures.interpolate(|name| matcher.capture_index(name), text, replacement, dst);
+            replacements += 1;
+            true
+        })?;
+        replaced.extend_from_slice(&line[text.len()..]);
     }
-}
-impl From<Infallible> for RipgrepjsError {
-    fn from(_: Infallible) -> Self {
-        unreachable!()
+    if replacements == 0 {
+        return Ok(0);
     }
-}
-impl From<std::io::Error> for RipgrepjsError {
-    fn from(error: std::io::Error) -> Self {
-        RipgrepjsError::IO(error)
+    if strategy == ReplaceStrategy::Truncate {
+        std::fs::write(&path, &replaced)?;
+        return Ok(replacements);
     }
-}
-impl From<grep::regex::Error> for RipgrepjsError {
-    fn from(error: grep::regex::Error) -> Self {
-        RipgrepjsError::Regex(error)
+
+    let (temp_path, mut temp_file) = create_temp_file(&path)?;
+    let mut write = || -> std::io::Result<()> {
+        temp_file.set_permissions(std::fs::metadata(&path)?.permissions())?;
+        temp_file.write_all(&replaced)?;
+        temp_file.sync_all()?;
+        std::fs::rename(&temp_path, &path)
+    };
+    if let Err(error) = write() {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(error.into());
     }
+    Ok(replacements)
 }
 
-impl SinkError for RipgrepjsError {
-    fn error_message<T: std::fmt::Display>(message: T) -> Self {
-        RipgrepjsError::Sink(format!("{}", message))
+/// Creates a temporary file next to `path`, for `ReplaceStrategy::Atomic`, returning it and its path.
+///
+/// Its name ends in a random suffix, so it can't be known ahead of time, and it's always newly created:
+/// if anything (like a symlink) is already at a name, it's left alone and another name is tried.
+fn create_temp_file(path: &Path) -> std::io::Result<(PathBuf, std::fs::File)> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut attempts = 0;
+    loop {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(std::process::id());
+        if let Ok(since_epoch) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            hasher.write_u128(since_epoch.as_nanos());
+        }
+        let temp_path = path.with_file_name(format!(
+            ".{}.{:016x}.ripgrepjs-tmp",
+            file_name,
+            hasher.finish()
+        ));
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists && attempts < 100 => {
+                attempts += 1;
+            }
+            Err(error) => return Err(error),
+        }
     }
+}
 
-    fn error_io(err: std::io::Error) -> Self {
-        RipgrepjsError::IO(err)
-    }
+/// JS function signature: `(options: RipgrepOptions, path: string | string[], callback: (result) => void) => SearchToken;`
+///
+/// The options, and the results passed to `callback`, are documented in `index.ts`. Missing options take their defaults.
+///
+/// A path that's a file rather than a directory is searched on its own. If any of the paths
+/// can't be read (e.g. it doesn't exist), this throws an error with its `path` and a `code`
+/// like `ENOENT` before anything is searched.
+///
+/// The search runs in the background, and this returns a token for it, which `cancelSearch` takes to stop it early.
+/// `onComplete` is called with the summary after every result has been passed to `callback`, even if it's cancelled.
+fn multithreaded_search_directory(cx: FunctionContext) -> JsResult<JsBox<SearchToken>> {
+    run_search(
+        cx,
+        |_| {},
+        get_roots_argument,
+        |search, roots| search_roots(search, roots),
+    )
 }
-/// Options for building a searcher
-pub struct SearcherOptions {
-    pub line_terminator: Option<u8>,
-    pub invert_match: bool,
-    pub include_line_numbers: bool,
-    pub multiline_search: bool,
-    pub after_context: usize,
-    pub before_context: usize,
-    pub passthru: bool,
-    pub heap_limit: Option<usize>,
+
+/// JS function signature: (searcherOptions, path: string | string[], callback: () => void) => void;
+///
+/// Walks the paths like a `dryRun` of `multithreadedSearchDirectory`, but only counts the files that would be
+/// searched, which `onComplete`'s summary has as its `candidateFiles`. No paths are passed to `callback`,
+/// and no files are opened, so it's much cheaper than searching them.
+fn count_candidate_files(cx: FunctionContext) -> JsResult<JsBox<SearchToken>> {
+    run_search(
+        cx,
+        |searcher_opts| {
+            searcher_opts.dry_run = true;
+            searcher_opts.count_candidates = true;
+        },
+        get_roots_argument,
+        |search, roots| search_roots(search, roots),
+    )
 }
 
-impl SearcherOptions {
-    /// Generates a ripgrep Seacher from an options struct.
-    ///
-    /// This abstracts away the builder pattern, which doesn't work well across the FFI boundary.
-    fn to_searcher(&self) -> Searcher {
-        let mut builder = SearcherBuilder::new();
+/// JS function signature: (searcherOptions, path: string | string[], callback: (result: {
+///     seq: number,
+///     path: string,
+///     bytes: Buffer,
+///     byteOffset: number,
+///     lineNumber?: number,
+///     contextKind?: "before" | "after" | "other",
+///     matched?: boolean,
+/// }) => void) => void;
+///
+/// Searches like `multithreadedSearchDirectory`, but passes the matched (or context) lines to `callback`
+/// as they are, in a `Buffer` along with the offset in the file where they start, rather than as strings.
+/// Nothing is ever converted from UTF-8, so `binaryOutput`, `trim`, `collapseWhitespace`, and `invalidCharReplacement`
+/// don't apply.
+fn search_raw(cx: FunctionContext) -> JsResult<JsBox<SearchToken>> {
+    run_search(
+        cx,
+        |searcher_opts| searcher_opts.raw_bytes = true,
+        get_roots_argument,
+        |search, roots| search_roots(search, roots),
+    )
+}
 
-        if let Some(term) = self.line_terminator {
-            builder.line_terminator(LineTerminator::byte(term));
+/// Searches each of the given files and directories, for `multithreadedSearchDirectory`, `searchRaw`,
+/// and `countCandidateFiles`.
+///
+/// Takes the roots from `get_roots_argument`, with whether each is a file.
+fn search_roots(search: &DirectorySearch, roots: &[(PathBuf, bool)]) -> Result<(), RipgrepjsError> {
+    roots.par_iter().for_each(|(root, is_file)| {
+        let result = if *is_file {
+            let mut searcher = search.searcher_opts.to_searcher();
+            search.search_file(&mut searcher, &mut search.sink(), root.clone())
+        } else {
+            search_directory_inner(root, search)
+        };
+        if let Err(error) = result {
+            search.record_error(root.clone(), error);
         }
-
-        builder.invert_match(self.invert_match);
-        builder.line_number(self.include_line_numbers);
-        builder.multi_line(self.multiline_search);
-        builder.after_context(self.after_context);
-        builder.before_context(self.before_context);
-        builder.passthru(self.passthru);
-        builder.heap_limit(self.heap_limit);
-
-        builder.build()
-    }
+    });
+    Ok(())
 }
 
-pub struct MatcherOptions<'a> {
-    pub case_insensitive: bool,
-    pub smart_case: bool,
-    pub multi_line: bool,
-    pub dot_matches_new_line: bool,
-    pub greedy_swap: bool,
-    pub ignore_whitespace: bool,
-    pub unicode: bool,
-    pub octal: bool,
-    pub line_terminator: Option<u8>,
-    pub crlf: bool,
-    pub word_boundaries_only: bool,
-
-    pub pattern: &'a str,
+/// JS function signature: (searcherOptions, paths: string[], callback) => void;
+///
+/// Takes the same options and callback as `multithreadedSearchDirectory`, but searches exactly the given files
+/// rather than traversing directories. Paths that don't exist (with the code `ENOENT`) or aren't files
+/// are passed to `onError` rather than stopping the search.
+fn search_file_list(cx: FunctionContext) -> JsResult<JsBox<SearchToken>> {
+    run_search(
+        cx,
+        |_| {},
+        get_paths_argument,
+        |search, paths| search_file_list_inner(paths, search),
+    )
 }
 
-impl<'a> MatcherOptions<'a> {
-    /// Generates a ripgrep Matcher from an options struct.
-    ///
-    /// This abstracts away the builder pattern, which doesn't work well across the FFI boundary.
-    fn to_matcher(&self) -> Result<RegexMatcher, RipgrepjsError> {
-        let mut builder = RegexMatcherBuilder::new();
-
-        builder.case_insensitive(self.case_insensitive);
-        builder.case_smart(self.smart_case);
-        builder.multi_line(self.multi_line);
-        builder.dot_matches_new_line(self.dot_matches_new_line);
-        builder.swap_greed(self.greedy_swap);
-        builder.ignore_whitespace(self.ignore_whitespace);
-        builder.unicode(self.unicode);
-        builder.octal(self.octal);
-        builder.line_terminator(self.line_terminator);
-        builder.crlf(self.crlf);
-        builder.word(self.word_boundaries_only);
-
-        Ok(builder.build(self.pattern)?)
-    }
+/// Searches blobs of data, such as files' contents from a git tree, as though they were files at their paths.
+///
+/// JS function signature: (searcherOptions, blobs: {path: string, data: Buffer}[], callback) => void;
+///
+/// Takes the same options and callback as `multithreadedSearchDirectory`, and reports results with the blobs' paths.
+/// Options that have to read the file itself (`headingPattern`, `includeFileMetadata`, `includeSiblings`,
+/// `startLine`/`endLine`, `startByte`/`endByte`, and `chunkSize`) don't apply. `fileNameGlob` does, to the blobs' paths.
+fn search_blobs(cx: FunctionContext) -> JsResult<JsBox<SearchToken>> {
+    run_search(
+        cx,
+        |_| {},
+        get_blobs_argument,
+        |search, blobs| search_blob_list(blobs, search),
+    )
 }
 
-/// Sink that executes a JavaScript callback on each match
+/// Parses the arguments shared by every search function, then starts the search in the background,
+/// which schedules `onComplete` once it's finished.
 ///
-/// TODO: buffer matches for better perf?
-struct JSCallbackSink {
-    on_match: Arc<Root<JsFunction>>,
-    // Sends a match to the calling thread so that it can be passed to the JavaScript callback
-    channel: Channel,
-}
+/// `adjust_options` sets the options that only some search functions have, like `raw_bytes` for `searchRaw`.
+///
+/// `get_targets` parses what to search from the second argument, which `search_targets` then searches.
+/// Returns the search's token for `cancelSearch`.
+fn run_search<'a, T, G, F>(
+    mut cx: FunctionContext<'a>,
+    adjust_options: fn(&mut SearcherOptions),
+    get_targets: G,
+    search_targets: F,
+) -> JsResult<'a, JsBox<SearchToken>>
+where
+    T: Sync + Send + 'static,
+    G: FnOnce(&mut FunctionContext<'a>) -> NeonResult<T>,
+    F: FnOnce(&DirectorySearch, &T) -> Result<(), RipgrepjsError> + Send + 'static,
+{
+    let options = cx.argument::<JsObject>(0)?;
+    let targets = get_targets(&mut cx)?;
+    let callback = cx.argument::<JsFunction>(2)?.root(&mut cx);
+    let on_complete = get_possible_function_from_js_object(options, &mut cx, "onComplete")?;
+    let on_error = get_possible_function_from_js_object(options, &mut cx, "onError")?
+        .map(|on_error| on_error.root(&mut cx));
+    let on_skip = get_possible_function_from_js_object(options, &mut cx, "onSkip")?
+        .map(|on_skip| on_skip.root(&mut cx));
+    let on_ignore = if get_bool_or_default(options, &mut cx, "debugIgnores", false)? {
+        get_possible_function_from_js_object(options, &mut cx, "onIgnore")?
+            .map(|on_ignore| on_ignore.root(&mut cx))
+    } else {
+        None
+    };
+    let on_file_start = get_possible_function_from_js_object(options, &mut cx, "onFileStart")?
+        .map(|on_file_start| on_file_start.root(&mut cx));
+    let on_file_end = get_possible_function_from_js_object(options, &mut cx, "onFileEnd")?
+        .map(|on_file_end| on_file_end.root(&mut cx));
+    let on_progress = get_possible_function_from_js_object(options, &mut cx, "onProgress")?
+        .map(|on_progress| on_progress.root(&mut cx));
+    let on_warning = get_possible_function_from_js_object(options, &mut cx, "onWarning")?;
+
+    let (mut searcher_opts, matcher, thread_pool) = get_search_setup(options, &mut cx)?;
+    adjust_options(&mut searcher_opts);
+    searcher_opts.report_file_boundaries = on_file_start.is_some() || on_file_end.is_some();
+    searcher_opts.report_progress = on_progress.is_some();
+    searcher_opts.report_ignores = on_ignore.is_some();
+    let file_name_filter = match searcher_opts.to_file_name_filter() {
+        Ok(file_name_filter) => file_name_filter,
+        Err(e) => {
+            let js_error = e.to_js_error(&mut cx, format!("Rust Error: {}", e))?;
+            return cx.throw(js_error);
+        }
+    };
 
-impl JSCallbackSink {
-    /// on_match JS function signature: `(results: {matchedLines: string[], lineNumber?: number}) => void;`
-    ///
-    /// `matchedLines` is an array of lines that matchsed the search pattern.
-    /// It should have length 1 unless multiline searching is enabled.
-    fn new(on_match: Arc<Root<JsFunction>>, channel: Channel) -> Self {
-        Self { channel, on_match }
+    if let Some(on_warning) = on_warning {
+        if matches_empty(&matcher) {
+            // Queued rather than called now, so that it's after any listeners have been set up,
+            // and before any results
+            let on_warning = on_warning.root(&mut cx);
+            cx.channel().send(move |mut context| {
+                let warning = context
+                    .error("the pattern can match the empty string, so it matches every line")?;
+                let code = context.string("EMPTY_MATCH");
+                warning.set(&mut context, "code", code)?;
+                let null = context.null();
+                on_warning
+                    .into_inner(&mut context)
+                    .call(&mut context, null, vec![warning])?;
+                Ok(())
+            });
+        }
     }
-}
 
-impl<'a> grep::searcher::Sink for JSCallbackSink {
-    type Error = RipgrepjsError;
-
-    fn matched(&mut self, _: &Searcher, matched: &SinkMatch) -> Result<bool, Self::Error> {
-        let line_number = matched.line_number();
-        // TODO: perf improvements possible here?
-        let mut lines_iter = matched
-            .lines()
-            .map(|line| match std::str::from_utf8(line) {
-                Ok(s) => Ok(s.to_string()),
-                Err(e) => Err(e),
-            })
-            .collect::<Vec<_>>();
-
-        let callback = self.on_match.clone();
-        self.channel.send(move |mut context| {
-            let js_match_object = context.empty_object();
-
-            if let Some(line_num) = line_number {
-                let js_line_num = context.number(line_num as f64);
-                js_match_object.set(&mut context, "lineNumber", js_line_num)?;
+    let report_skips = on_skip.is_some();
+    let callbacks = Callbacks {
+        on_match: Arc::new(callback),
+        on_error: on_error.map(Arc::new),
+        on_skip: on_skip.map(Arc::new),
+        on_ignore: on_ignore.map(Arc::new),
+        on_file_start: on_file_start.map(Arc::new),
+        on_file_end: on_file_end.map(Arc::new),
+        on_progress: on_progress.map(Arc::new),
+    };
+    let channel = cx.channel();
+    let (results, callback_thread) = if searcher_opts.callback_thread {
+        let (sender, handle) = spawn_callback_thread(
+            channel.clone(),
+            callbacks,
+            searcher_opts.batch_size,
+            searcher_opts.flush_interval,
+        );
+        (ResultSender::CallbackThread(sender), Some(handle))
+    } else {
+        (ResultSender::Direct(channel.clone(), callbacks), None)
+    };
+    let on_complete = on_complete.map(|on_complete| on_complete.root(&mut cx));
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let search_cancelled = Arc::clone(&cancelled);
+    thread::spawn(move || {
+        // For an error that stops the whole search, which has no summary to be in
+        let errors = results.clone();
+        let search = || {
+            search_with_timeout(
+                &searcher_opts,
+                file_name_filter,
+                &matcher,
+                results,
+                report_skips,
+                search_cancelled,
+                &targets,
+                search_targets,
+            )
+        };
+        let summary = match thread_pool {
+            Some(thread_pool) => thread_pool.install(search),
+            None => search(),
+        };
+        let summary = match summary {
+            Ok(summary) => Some(summary),
+            Err(e) => {
+                errors.send(Delivery::Error(e));
+                None
             }
+        };
+        drop(errors);
+        // Every result has to be queued before `onComplete` is. If the callback thread panicked,
+        // the results it had left are lost, but the summary is still worth reporting.
+        if let Some(handle) = callback_thread {
+            let _ = handle.join();
+        }
 
-            let js_lines = context.empty_array();
-            for (idx, line) in lines_iter.iter_mut().enumerate() {
-                let line = match line {
-                    Ok(s) => s,
-                    Err(e) => context.throw_error(format!(
-                        "Error converting byte sequence to a string using UTF-8: {}",
-                        e
-                    ))?,
-                };
-                let js_line = context.string(line);
-                js_lines.set(&mut context, idx as u32, js_line)?;
-            }
-            js_match_object.set(&mut context, "matchedLines", js_lines)?;
+        if let (Some(on_complete), Some(summary)) = (on_complete, summary) {
+            // Results are queued on the channel as they're found, so this will run after all of them
+            channel.send(move |mut context| {
+                let js_summary = summary.to_js_object(&mut context)?;
+                let null = context.null();
+                on_complete
+                    .into_inner(&mut context)
+                    .call(&mut context, null, vec![js_summary])?;
+                Ok(())
+            });
+        }
+    });
 
-            let null = context.null();
-            callback
-                .to_inner(&mut context)
-                .call(&mut context, null, vec![js_match_object])?;
-            Ok(())
-        });
-        Ok(true)
-    }
+    Ok(cx.boxed(SearchToken { cancelled }))
 }
 
-/// Searches a file with a `JsFunction` callback
-fn search_file<P>(
-    searcher_opts: SearcherOptions,
-    matcher_opts: MatcherOptions,
-    file: P,
-    callback: JsFunction,
-    js_context: &mut FunctionContext,
-) -> Result<(), RipgrepjsError>
-where
-    P: AsRef<Path>,
-{
-    let mut searcher = searcher_opts.to_searcher();
-    let matcher = matcher_opts.to_matcher()?;
-    let mut channel = js_context.channel();
-    let sink = JSCallbackSink::new(Arc::new(callback.root(js_context)), channel);
+/// Gets the options and compiled pattern for a search, from its `searchContext` if it has one.
+///
+/// Also returns the context's thread pool, which the search should run in.
+fn get_search_setup(
+    options: Handle<JsObject>,
+    cx: &mut FunctionContext,
+) -> NeonResult<(
+    SearcherOptions,
+    Arc<SearchMatcher>,
+    Option<Arc<rayon::ThreadPool>>,
+)> {
+    check_option_keys(options, cx, &[PATTERN_OPTIONS, SEARCH_OPTIONS])?;
+    let search_context = options.get(cx, "searchContext")?;
+    if search_context.is_a::<JsUndefined, _>(cx) {
+        let mut searcher_opts = get_searcher_options(options, cx)?;
+        let matcher = get_matcher(options, cx, &mut searcher_opts)?;
+        // One thread runs everything that would otherwise be split between threads, in order
+        let thread_pool = if searcher_opts.parallel {
+            None
+        } else {
+            Some(Arc::new(build_thread_pool(cx, Some(1))?))
+        };
+        return Ok((searcher_opts, matcher, thread_pool));
+    }
 
-    searcher.search_path(matcher, file, sink)
+    let search_context = match search_context.downcast::<JsBox<SearchContext>, _>(cx) {
+        Ok(search_context) => search_context,
+        Err(_) => {
+            return cx
+                .throw_type_error("searchContext must be a handle returned by createSearchContext")
+        }
+    };
+    Ok((
+        search_context.searcher_opts.clone(),
+        Arc::clone(&search_context.matcher),
+        Some(Arc::clone(&search_context.thread_pool)),
+    ))
 }
 
-/// Searches a directory with a `JsFunction` callback
+/// Searches `targets` with `search_targets`, cancelling the search if `timeoutMs` passes first.
 ///
-/// Parallelized with Rayon.
-fn search_directory_with_rayon<P>(
-    searcher_opts: SearcherOptions,
-    matcher_opts: MatcherOptions,
-    directory: P,
-    callback: Root<JsFunction>,
-    js_context: &mut FunctionContext,
-) -> Result<(), RipgrepjsError>
-where
-    P: AsRef<Path>,
-{
-    let matcher = matcher_opts.to_matcher()?;
-    search_directory_inner(
-        directory,
-        &searcher_opts,
-        &matcher,
-        Arc::new(callback),
-        js_context.channel(),
-    )
-}
-
-fn search_directory_inner<P>(
-    path: P,
+/// The search (and every sender it holds) is dropped once it's done, letting the callback thread finish.
+#[allow(clippy::too_many_arguments)]
+fn search_with_timeout<T, F>(
     searcher_opts: &SearcherOptions,
-    matcher: &RegexMatcher,
-    callback: Arc<Root<JsFunction>>,
-    channel: Channel,
-) -> Result<(), RipgrepjsError>
+    file_name_filter: Option<FileNameFilter>,
+    matcher: &SearchMatcher,
+    results: ResultSender,
+    report_skips: bool,
+    cancelled: Arc<AtomicBool>,
+    targets: &T,
+    search_targets: F,
+) -> Result<SearchSummary, RipgrepjsError>
 where
-    P: AsRef<Path>,
+    T: ?Sized,
+    F: FnOnce(&DirectorySearch, &T) -> Result<(), RipgrepjsError>,
 {
-    std::fs::read_dir(path)?
-        .collect::<Vec<_>>()
-        .par_iter()
-        .try_for_each_init(
-            // TODO: use our own threading system
-            // (Rayon + one thread to call the JS callback)
-            // (we can't share the JS context across threads)
-            || {
-                (
-                    searcher_opts.to_searcher(),
-                    JSCallbackSink::new(callback.clone(), channel.clone()),
-                )
-            },
-            |(searcher, sink), entry| -> Result<(), RipgrepjsError> {
-                if let Ok(entry) = entry {
-                    // Recurse further into directories
-                    let file_type = entry.file_type()?;
-                    if file_type.is_file() {
-                        // otherwise, search the file
-                        searcher.search_path(matcher, entry.path(), sink).unwrap();
-                    } else if file_type.is_dir() {
-                        // Rayon _should_ use the global thread pool,
-                        // meaning this will go on the same work pool as other directories.
-                        return search_directory_inner(
-                            entry.path(),
-                            searcher_opts,
-                            matcher,
-                            callback.clone(),
-                            channel.clone(),
-                        );
-                    }
+    let search = DirectorySearch::new(
+        searcher_opts,
+        file_name_filter,
+        matcher,
+        results,
+        report_skips,
+        cancelled,
+    );
+    thread::scope(|scope| {
+        // Dropped once the search is over, which wakes the timer up early
+        let (finished, finished_receiver) = mpsc::channel::<()>();
+        if let Some(timeout) = searcher_opts.timeout {
+            let shared = &search.shared;
+            scope.spawn(move || {
+                if finished_receiver.recv_timeout(timeout) == Err(mpsc::RecvTimeoutError::Timeout) {
+                    shared.time_out();
                 }
-                Ok(())
-            },
-        )?;
-
-    Ok(())
+            });
+        }
+        let result = search_targets(&search, targets);
+        drop(finished);
+        result
+    })?;
+    Ok(search.summary())
 }
 
-/// helper to get ints from a JS obj
-fn get_int_from_js_object<'a>(
-    obj: Handle<JsObject>,
-    cx: &mut impl Context<'a>,
-    key: &str,
-) -> Result<usize, Throw> {
-    match obj.get(cx, key) {
-        Ok(item) => Ok(item.downcast_or_throw::<JsNumber, _>(cx)?.value(cx) as usize),
-        Err(e) => Err(e),
+/// JS function signature: `(patternOptions) => {valid: true} | {valid: false, error: string};`
+///
+/// Takes the same options as `compilePattern`, but only reports whether the pattern compiles,
+/// so it doesn't throw for invalid patterns.
+fn validate_pattern(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let options = cx.argument::<JsObject>(0)?;
+    check_option_keys(options, &mut cx, &[PATTERN_OPTIONS])?;
+    let result = compile_pattern_from_options(options, &mut cx)?;
+
+    let js_result = cx.empty_object();
+    let js_valid = cx.boolean(result.is_ok());
+    js_result.set(&mut cx, "valid", js_valid)?;
+    if let Err(e) = result {
+        let js_error = cx.string(e.to_string());
+        js_result.set(&mut cx, "error", js_error)?;
     }
+    Ok(js_result)
 }
 
-fn get_possible_int_from_js_object<'a>(
-    obj: Handle<JsObject>,
-    cx: &mut impl Context<'a>,
-    key: &str,
-) -> Option<usize> {
-    match obj.get(cx, key) {
-        Ok(item) => Some(item.downcast::<JsNumber, _>(cx).ok()?.value(cx) as usize),
-        Err(_) => None,
-    }
-}
+/// JS function signature: `(patternOptions & {replaceStrategy?: "atomic" | "truncate"}, path: string, replacement: string, callback: (error: RipgrepError | null, replacements?: number) => void) => void;`
+///
+/// Takes the same options as `compilePattern`, and replaces every match of the pattern in the file
+/// with `replacement`, in which `$1` or `${name}` stand for what a capture group matched (and `$$` for `$`).
+/// Lines without matches are left exactly as they were, line terminators included.
+/// With `replaceStrategy: "atomic"` (the default), the file is replaced all at once, through a temporary file
+/// next to it. `"truncate"` rewrites it in place instead, for where that temporary file can't be created,
+/// at the cost of leaving the file half-written if writing fails.
+///
+/// The file is read and written on another thread; `callback` is called with the number of matches replaced.
+/// Bad options and patterns that don't compile are thrown straight away.
+fn replace_in_file(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let options = cx.argument::<JsObject>(0)?;
+    let path = PathBuf::from(cx.argument::<JsString>(1)?.value(&mut cx));
+    let replacement = cx.argument::<JsString>(2)?.value(&mut cx);
+    let callback = cx.argument::<JsFunction>(3)?.root(&mut cx);
+    check_option_keys(options, &mut cx, &[PATTERN_OPTIONS, &["replaceStrategy"]])?;
+    let strategy = get_possible_enum_from_js_object(
+        options,
+        &mut cx,
+        "replaceStrategy",
+        &[
+            ("atomic", ReplaceStrategy::Atomic),
+            ("truncate", ReplaceStrategy::Truncate),
+        ],
+    )?
+    .unwrap_or(ReplaceStrategy::Atomic);
+    let compiled = with_matcher_options(options, &mut cx, |matcher_opts, line_terminator| {
+        Ok((matcher_opts.to_matcher()?, line_terminator))
+    })?;
+    let (matcher, line_terminator) = match compiled {
+        Ok(compiled) => compiled,
+        Err(e) => {
+            let js_error = e.to_js_error(&mut cx, format!("Rust Error: {}", e))?;
+            return cx.throw(js_error);
+        }
+    };
+    let channel = cx.channel();
+
+    thread::spawn(move || {
+        let outcome = replace_matches_in_file(
+            &matcher,
+            line_terminator,
+            &path,
+            replacement.as_bytes(),
+            strategy,
+        )
+        .map_err(|e| RipgrepjsError::InFile(path.clone(), Box::new(e)));
+
+        channel.send(move |mut cx| {
+            let args: Vec<Handle<JsValue>> = match outcome {
+                Ok(replacements) => {
+                    vec![cx.null().upcast(), cx.number(replacements as f64).upcast()]
+                }
+                Err(e) => vec![e
+                    .to_js_error(&mut cx, format!("Rust Error: {}", e))?
+                    .upcast()],
+            };
+            let null = cx.null();
+            callback.into_inner(&mut cx).call(&mut cx, null, args)?;
+            Ok(())
+        });
+    });
 
-fn get_bool_from_js_object<'a>(
-    obj: Handle<JsObject>,
-    cx: &mut impl Context<'a>,
-    key: &str,
-) -> Result<bool, Throw> {
-    match obj.get(cx, key) {
-        Ok(item) => Ok(item.downcast_or_throw::<JsBoolean, _>(cx)?.value(cx)),
-        Err(e) => Err(e),
-    }
+    Ok(cx.undefined())
 }
 
-fn get_string_from_js_object<'a>(
-    obj: Handle<JsObject>,
+/// Parses the options for `compilePattern` and compiles the pattern.
+///
+/// Bad options are thrown, but a pattern that doesn't compile is returned as an error.
+fn compile_pattern_from_options<'a>(
+    options: Handle<JsObject>,
     cx: &mut impl Context<'a>,
-    key: &str,
-) -> Result<String, Throw> {
-    match obj.get(cx, key) {
-        Ok(item) => Ok(item.downcast_or_throw::<JsString, _>(cx)?.value(cx)),
-        Err(e) => Err(e),
-    }
+) -> NeonResult<Result<CompiledPattern, RipgrepjsError>> {
+    with_matcher_options(options, cx, |matcher_opts, line_terminator| {
+        let matcher = matcher_opts.to_matcher()?;
+        Ok(CompiledPattern {
+            capture_names: Arc::new(capture_names(matcher_opts.pattern, &matcher)),
+            matcher: Arc::new(matcher),
+            line_terminator,
+            multi_line: matcher_opts.multi_line,
+            word_list: if matcher_opts.word_list.is_empty() {
+                None
+            } else {
+                Some(Arc::new(WordList::new(matcher_opts.word_list)))
+            },
+            pattern_matchers: if matcher_opts.attribute_patterns {
+                Some(Arc::new(matcher_opts.pattern_matchers()?))
+            } else {
+                None
+            },
+        })
+    })
 }
 
-/// JS function signature: (
-///     searcherOptions: {
-///         afterContext: number,
-///         beforeContext: number,
-///         multilineSearch: boolean,
-///         invertMatch: boolean,
-///         includeLineNumbers: boolean,
-///         passthru: boolean,
-///         heapLimit?: number,
-///         caseInsensitive: boolean,
-///         smartCase: boolean,
-///         dotMatchesNewline: boolean,
-///         greedySwap: boolean,
-///         ignoreWhitespace: boolean,
-///         unicode: boolean,
-///         octal: boolean,
-///         crlf: boolean,
-///         wordBoudariesOnly: boolean,
-///         pattern: string,
-///     },
-///     path: string,
-///     callback: (results: {matchedLines: string[], lineNumber?: number}) => void,
-/// ) => void;
-fn multithreaded_search_directory(mut cx: FunctionContext) -> JsResult<JsUndefined> {
-    let options = cx.argument::<JsObject>(0)?;
-    let path = cx.argument::<JsString>(1)?.value(&mut cx);
-    let callback = cx.argument::<JsFunction>(2)?;
-
-    // TODO: make this a macro?
-    let searcher_opts = SearcherOptions {
-        line_terminator: None, // TODO: implement
-        after_context: get_int_from_js_object(options, &mut cx, "afterContext")?,
-        before_context: get_int_from_js_object(options, &mut cx, "beforeContext")?,
-        multiline_search: get_bool_from_js_object(options, &mut cx, "multilineSearch")?,
-        invert_match: get_bool_from_js_object(options, &mut cx, "invertMatch")?,
-        include_line_numbers: get_bool_from_js_object(options, &mut cx, "includeLineNumbers")?,
-        passthru: get_bool_from_js_object(options, &mut cx, "passthru")?,
-        heap_limit: get_possible_int_from_js_object(options, &mut cx, "heapLimit"),
+/// Parses the options for `compilePattern` (reading the `patternFile`, if there is one)
+/// and passes them to `build`, along with the line terminator.
+///
+/// Patterns come from the first of `wordList`, `patterns`, `patternFile`, and `pattern` that's given.
+///
+/// Bad options are thrown, but a pattern file that can't be read is returned as an error.
+fn with_matcher_options<'a, T>(
+    options: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    build: impl FnOnce(&MatcherOptions, LineTerminatorMode) -> Result<T, RipgrepjsError>,
+) -> NeonResult<Result<T, RipgrepjsError>> {
+    let line_terminator = get_line_terminator(options, cx)?;
+    let multi_line = get_bool_or_default(options, cx, "multilineSearch", false)?;
+    let word_list = get_strings_from_js_object(options, cx, "wordList")?;
+    let js_patterns = options.get(cx, "patterns")?;
+    let inline_patterns = if js_patterns.is_a::<JsUndefined, _>(cx) {
+        None
+    } else {
+        Some(js_value_to_strings(js_patterns, cx, "patterns")?)
     };
-    let pattern = get_string_from_js_object(options, &mut cx, "pattern")?;
-    let matcher_opts = MatcherOptions {
-        case_insensitive: get_bool_from_js_object(options, &mut cx, "caseInsensitive")?,
-        smart_case: get_bool_from_js_object(options, &mut cx, "smartCase")?,
-        multi_line: searcher_opts.multiline_search,
-        dot_matches_new_line: get_bool_from_js_object(options, &mut cx, "dotMatchesNewline")?,
-        greedy_swap: get_bool_from_js_object(options, &mut cx, "greedySwap")?,
-        ignore_whitespace: get_bool_from_js_object(options, &mut cx, "ignoreWhitespace")?,
-        unicode: get_bool_from_js_object(options, &mut cx, "unicode")?,
-        octal: get_bool_from_js_object(options, &mut cx, "octal")?,
-        line_terminator: searcher_opts.line_terminator,
-        crlf: get_bool_from_js_object(options, &mut cx, "crlf")?,
-        word_boundaries_only: get_bool_from_js_object(options, &mut cx, "wordBoundariesOnly")?,
-        pattern: pattern.as_str(),
+    let pattern_file = get_possible_string_from_js_object(options, cx, "patternFile")?;
+    let mut patterns = Vec::new();
+    let mut attribute_patterns = false;
+    let mut byte_pattern = false;
+    let pattern = if !word_list.is_empty() {
+        String::new()
+    } else if let Some(inline_patterns) = inline_patterns {
+        patterns = inline_patterns;
+        // Like an empty `patternFile`, no patterns match nothing
+        attribute_patterns = !patterns.is_empty();
+        if patterns.is_empty() {
+            patterns.push(String::from(r"[^\s\S]"));
+        }
+        // Only used to find capture group names
+        patterns.join("|")
+    } else if let Some(pattern_file) = pattern_file {
+        let skip_comments = get_bool_or_default(options, cx, "patternFileSkipComments", false)?;
+        patterns = match read_pattern_file(Path::new(&pattern_file), skip_comments) {
+            Ok(patterns) => patterns,
+            Err(e) => return Ok(Err(e)),
+        };
+        if patterns.is_empty() {
+            // Like ripgrep, a file with no patterns matches nothing, rather than everything like an empty pattern
+            patterns.push(String::from(r"[^\s\S]"));
+        }
+        // Only used to find capture group names
+        patterns.join("|")
+    } else {
+        let (pattern, is_empty) = match get_possible_buffer_from_js_object(options, cx, "pattern")?
+        {
+            Some(bytes) => {
+                byte_pattern = true;
+                (byte_literal_pattern(&bytes), bytes.is_empty())
+            }
+            None => {
+                let pattern = get_string_from_js_object(options, cx, "pattern")?;
+                let is_empty = pattern.is_empty();
+                (pattern, is_empty)
+            }
+        };
+        if is_empty && !get_bool_or_default(options, cx, "allowEmptyPattern", false)? {
+            return Ok(Err(RipgrepjsError::EmptyPattern));
+        }
+        pattern
     };
+    let mut matcher_opts = get_matcher_options(options, cx, &pattern, line_terminator, multi_line)?;
+    matcher_opts.word_list = &word_list;
+    matcher_opts.patterns = &patterns;
+    matcher_opts.attribute_patterns = attribute_patterns;
+    // The pattern is already escaped, so it mustn't be escaped again
+    matcher_opts.prefix_anchor &= !byte_pattern;
+    matcher_opts.fixed_strings &= !byte_pattern;
+    // It matches exactly its bytes, whatever the options
+    matcher_opts.normalize_for_match &= !byte_pattern;
+
+    Ok(build(&matcher_opts, line_terminator))
+}
 
-    if let Err(e) = search_directory_with_rayon(
-        searcher_opts,
-        matcher_opts,
-        path,
-        callback.root(&mut cx),
-        &mut cx,
-    ) {
-        cx.throw_error(format!("Rust Error: {:?}", e))?;
+/// JS function signature: `(patternOptions) => PatternExplanation;`
+///
+/// Takes the same options as `compilePattern`, and describes what the regex engine can tell
+/// about the pattern without searching anything: whether it's anchored to the start or end of lines,
+/// whether it's multiline, the literals the engine scans for (`prefixLiterals` and `suffixLiterals`,
+/// absent if there aren't any worth using), and the shortest and longest possible matches in bytes.
+/// Throws if the pattern doesn't compile.
+fn explain(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let options = cx.argument::<JsObject>(0)?;
+    check_option_keys(options, &mut cx, &[PATTERN_OPTIONS])?;
+    match with_matcher_options(options, &mut cx, |matcher_opts, _| matcher_opts.explain())? {
+        Ok(explanation) => explanation.to_js_object(&mut cx),
+        Err(e) => {
+            let js_error = e.to_js_error(&mut cx, format!("Rust Error: {}", e))?;
+            cx.throw(js_error)
+        }
     }
+}
 
-    Ok(cx.undefined())
+/// JS function signature: `() => Versions;`
+///
+/// Reports the version of ripgrepjs, along with the versions of the `grep` and regex crates it was built with
+/// (from `Cargo.lock`, by `build.rs`), for working out why a pattern behaves differently in another environment.
+fn version(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let versions = [
+        ("ripgrepjs", env!("CARGO_PKG_VERSION")),
+        ("grep", env!("RIPGREPJS_GREP_VERSION")),
+        ("grepRegex", env!("RIPGREPJS_GREP_REGEX_VERSION")),
+        ("grepSearcher", env!("RIPGREPJS_GREP_SEARCHER_VERSION")),
+        ("regexAutomata", env!("RIPGREPJS_REGEX_AUTOMATA_VERSION")),
+        ("regexSyntax", env!("RIPGREPJS_REGEX_SYNTAX_VERSION")),
+    ];
+    let js_versions = cx.empty_object();
+    for &(name, version) in versions.iter() {
+        let js_version = cx.string(version);
+        js_versions.set(&mut cx, name, js_version)?;
+    }
+    Ok(js_versions)
 }
 
 #[neon::main]
@@ -407,5 +711,21 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function(
         "multithreadedSearchDirectory",
         multithreaded_search_directory,
-    )
+    )?;
+    cx.export_function("searchFileList", search_file_list)?;
+    cx.export_function("searchRaw", search_raw)?;
+    cx.export_function("countCandidateFiles", count_candidate_files)?;
+    cx.export_function("searchBlobs", search_blobs)?;
+    cx.export_function("compilePattern", compile_pattern)?;
+    cx.export_function("validatePattern", validate_pattern)?;
+    cx.export_function("explain", explain)?;
+    cx.export_function("version", version)?;
+    cx.export_function("replaceInFile", replace_in_file)?;
+    cx.export_function("openSearch", open_search)?;
+    cx.export_function("nextSearchResult", next_search_result)?;
+    cx.export_function("closeSearch", close_search)?;
+    cx.export_function("cancelSearch", cancel_search)?;
+    cx.export_function("createResultCache", create_result_cache)?;
+    cx.export_function("research", research)?;
+    cx.export_function("createSearchContext", create_search_context)
 }
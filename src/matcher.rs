@@ -0,0 +1,710 @@
+//! Building matchers from patterns and their options
+
+use std::collections::{HashMap, HashSet};
+
+use grep::{
+    matcher::{ByteSet, Captures, LineMatchKind, LineTerminator, Match, Matcher, NoError},
+    regex::{RegexCaptures, RegexMatcher, RegexMatcherBuilder},
+};
+use neon::prelude::*;
+use regex_syntax::{
+    escape,
+    hir::{
+        self,
+        literal::{ExtractKind, Extractor, Literal},
+        Capture, Class, ClassUnicode, ClassUnicodeRange, Hir, HirKind, Look, Repetition,
+    },
+    ParserBuilder,
+};
+
+use crate::{
+    error::RipgrepjsError,
+    normalization::{normalized_pattern, normalized_text, NormalizedLine},
+    options::unsupported_feature,
+    results::{strings_to_js_array, to_lossy_string},
+};
+
+/// How `caseInsensitive` and `smartCase` decide which characters are the same letter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CaseFoldMode {
+    /// The regex engine's Unicode case folding, where every character in a case-equivalence class matches
+    /// the others (so `k` matches the Kelvin sign `K`, and `s` matches the long s `ſ`)
+    Default,
+    /// Only ASCII letters match their other case; every other character only matches itself
+    Ascii,
+    /// Each character only matches its own uppercase and lowercase forms, where those are single characters
+    Simple,
+}
+
+#[derive(Clone)]
+pub(crate) struct MatcherOptions<'a> {
+    pub case_insensitive: bool,
+    pub smart_case: bool,
+    /// How case-insensitivity from `case_insensitive` or `smart_case` folds case;
+    /// inline flags like `(?i)` always use the regex engine's folding
+    pub case_fold_mode: CaseFoldMode,
+    pub multi_line: bool,
+    pub dot_matches_new_line: bool,
+    pub greedy_swap: bool,
+    pub ignore_whitespace: bool,
+    /// When false, word boundaries and classes like `\w` and `.` are ASCII/byte-based.
+    pub unicode: bool,
+    pub octal: bool,
+    pub line_terminator: Option<u8>,
+    pub crlf: bool,
+    pub word_boundaries_only: bool,
+    /// Only matches entire lines; takes precedence over `word_boundaries_only`
+    pub whole_line: bool,
+    /// Takes `pattern` literally, only matching it at the start of a line (after any indentation)
+    pub prefix_anchor: bool,
+    /// Takes `pattern` literally, like ripgrep's `--fixed-strings`
+    pub fixed_strings: bool,
+    /// Matches as if the pattern and the text were both lowercased and had their diacritics stripped
+    pub normalize_for_match: bool,
+
+    pub pattern: &'a str,
+    /// Literal terms to match any of, instead of `pattern`, if there are any
+    pub word_list: &'a [String],
+    /// Patterns to match any of, instead of `pattern`, if there are any (from `patternFile` or `patterns`)
+    pub patterns: &'a [String],
+    /// Whether matches report which of the `patterns` they matched, as they do for the `patterns` option
+    pub attribute_patterns: bool,
+}
+
+impl<'a> MatcherOptions<'a> {
+    /// Generates a ripgrep Matcher from an options struct.
+    ///
+    /// This abstracts away the builder pattern, which doesn't work well across the FFI boundary.
+    pub(crate) fn to_matcher(&self) -> Result<SearchMatcher, RipgrepjsError> {
+        let mut builder = RegexMatcherBuilder::new();
+
+        let sources = self.sources();
+        let normalize = self.normalizes();
+        // The regex engine only folds case its own way, so any other way is written out in the pattern,
+        // which is compiled case-sensitively. If the pattern doesn't parse, it's compiled as it was
+        // so that the error comes from grep like any other.
+        let folded_sources = if self.folds_case_itself(&sources) {
+            self.parse_sources(&sources)
+                .ok()
+                .map(|hirs| hirs.iter().map(Hir::to_string).collect::<Vec<_>>())
+        } else {
+            None
+        };
+        let folded = folded_sources.is_some();
+
+        // The normalized line is lowercase, so classes like `[A-Z]` would never match it otherwise
+        builder.case_insensitive((self.case_insensitive || normalize) && !folded);
+        builder.case_smart(self.smart_case && !folded && !normalize);
+        builder.multi_line(self.multi_line);
+        builder.dot_matches_new_line(self.dot_matches_new_line);
+        builder.swap_greed(self.greedy_swap);
+        // Terms in a word list are taken literally, even if they have whitespace or backslashes,
+        // and so is a `prefixAnchor` prefix or a `fixedStrings` pattern
+        let prefix = self.prefix_anchor && self.word_list.is_empty() && self.patterns.is_empty();
+        let fixed = self.fixed_strings && self.word_list.is_empty() && self.patterns.is_empty();
+        let literal = !self.word_list.is_empty() || prefix || fixed;
+        builder.ignore_whitespace(self.ignore_whitespace && !literal && !folded);
+        builder.unicode(self.unicode);
+        builder.octal(self.octal && !literal && !folded);
+        // `crlf` overwrites the line terminator, so it has to come first
+        builder.crlf(self.crlf);
+        if self.multi_line {
+            // Multiline matches can span line terminators, so they mustn't be banned from the pattern.
+            // `crlf` still makes `$` and `.` treat `\r\n` as a single line ending.
+            builder.line_terminator(None);
+        } else if !self.crlf {
+            builder.line_terminator(self.line_terminator);
+        }
+        // Word list terms get their own boundaries (see `escaped_terms`), rather than grep's around the whole list
+        builder.word(self.word_boundaries_only && self.word_list.is_empty());
+        builder.whole_line(self.whole_line);
+
+        let build = |builder: &RegexMatcherBuilder| {
+            if let Some(folded_sources) = &folded_sources {
+                builder.build_many(folded_sources)
+            } else if !self.word_list.is_empty() || normalize {
+                // Joined into one alternation, which the regex engine's literal optimizations handle well
+                builder.build_many(&sources)
+            } else if !self.patterns.is_empty() {
+                builder.build_many(self.patterns)
+            } else if prefix || fixed {
+                builder.build(&sources[0])
+            } else {
+                builder.build(self.pattern)
+            }
+        };
+
+        let regex = build(&builder).map_err(|error| {
+            // An error about the whole list of patterns doesn't say which one is broken,
+            // so each is compiled on its own to find it
+            if self.patterns.len() > 1 {
+                for (index, pattern) in self.patterns.iter().enumerate() {
+                    if let Err(error) = builder.build(pattern) {
+                        let mut note = format!("pattern at index {}, {:?}", index, pattern);
+                        if let Some(feature) = unsupported_feature(pattern, self.octal) {
+                            note.push_str(&format!("; {} isn't supported", feature));
+                        }
+                        return RipgrepjsError::Regex(error, Some(note));
+                    }
+                }
+            }
+            // The regex engine's own errors for these don't say that the syntax is fine elsewhere
+            // (like in PCRE), just that it's wrong
+            if !literal && self.patterns.is_empty() {
+                if let Some(feature) = unsupported_feature(self.pattern, self.octal) {
+                    let note = format!("{} isn't supported", feature);
+                    return RipgrepjsError::Regex(error, Some(note));
+                }
+            }
+            // grep wraps the whole pattern to apply these, which can interact badly with
+            // anchors or `\b` already in it, so say so if that's what broke it.
+            let modifier = if self.whole_line {
+                "wholeLine"
+            } else if self.word_boundaries_only {
+                "wordBoundariesOnly"
+            } else {
+                return RipgrepjsError::Regex(error, None);
+            };
+
+            builder.word(false);
+            builder.whole_line(false);
+            let note = if build(&builder).is_ok() {
+                format!(
+                    "the pattern is valid on its own, but not with {} applied",
+                    modifier
+                )
+            } else {
+                format!("with {} applied", modifier)
+            };
+            RipgrepjsError::Regex(error, Some(note))
+        })?;
+        Ok(SearchMatcher { regex, normalize })
+    }
+
+    /// A matcher for each of `patterns` on its own, compiled with the same options as the matcher for all of them,
+    /// for working out which ones a match came from.
+    pub(crate) fn pattern_matchers(&self) -> Result<Vec<SearchMatcher>, RipgrepjsError> {
+        self.patterns
+            .iter()
+            .map(|pattern| {
+                MatcherOptions {
+                    pattern,
+                    patterns: &[],
+                    ..self.clone()
+                }
+                .to_matcher()
+            })
+            .collect()
+    }
+
+    /// The `wordList` terms as patterns that match them literally.
+    ///
+    /// With `word_boundaries_only`, each term is anchored at word boundaries, but only on an end that is a word
+    /// character: `\b` can never match before `@` in `@user` when it follows a space, so that end is left as is.
+    fn escaped_terms(&self) -> Vec<String> {
+        self.word_list
+            .iter()
+            .map(|term| {
+                let escaped = escape(term);
+                if !self.word_boundaries_only || self.whole_line {
+                    return escaped;
+                }
+                let is_word = |c: Option<char>| {
+                    c.is_some_and(|c| {
+                        c == '_'
+                            || if self.unicode {
+                                c.is_alphanumeric()
+                            } else {
+                                c.is_ascii_alphanumeric()
+                            }
+                    })
+                };
+                let start = if is_word(term.chars().next()) {
+                    r"\b"
+                } else {
+                    ""
+                };
+                let end = if is_word(term.chars().next_back()) {
+                    r"\b"
+                } else {
+                    ""
+                };
+                format!("{}{}{}", start, escaped, end)
+            })
+            .collect()
+    }
+
+    /// The patterns the matcher is built from, with word list terms, `prefixAnchor` prefixes,
+    /// and `fixedStrings` patterns escaped, and their literals normalized with `normalize_for_match`
+    fn sources(&self) -> Vec<String> {
+        let sources = if !self.word_list.is_empty() {
+            self.escaped_terms()
+        } else if !self.patterns.is_empty() {
+            self.patterns.to_vec()
+        } else if self.prefix_anchor {
+            vec![anchored_prefix(self.pattern)]
+        } else if self.fixed_strings {
+            vec![escape(self.pattern)]
+        } else {
+            vec![self.pattern.to_string()]
+        };
+        if !self.normalizes() {
+            return sources;
+        }
+        let literal = !self.word_list.is_empty()
+            || ((self.prefix_anchor || self.fixed_strings) && self.patterns.is_empty());
+        sources
+            .iter()
+            .map(|source| {
+                normalized_pattern(
+                    source,
+                    self.ignore_whitespace && !literal,
+                    self.octal && !literal,
+                )
+            })
+            .collect()
+    }
+
+    /// Whether the pattern matches normalized lines, with `normalize_for_match`.
+    ///
+    /// Normalizing needs Unicode, just like folding case does.
+    fn normalizes(&self) -> bool {
+        self.normalize_for_match && self.unicode
+    }
+
+    /// Whether the options make the pattern case-insensitive, which `normalize_for_match` always does.
+    ///
+    /// Smart case is approximated by looking for any uppercase letter in the pattern.
+    fn is_case_insensitive(&self, sources: &[String]) -> bool {
+        self.case_insensitive
+            || self.normalizes()
+            || (self.smart_case && !sources.iter().any(|s| s.chars().any(char::is_uppercase)))
+    }
+
+    /// Whether case has to be folded by rewriting the pattern, rather than by the regex engine.
+    ///
+    /// Without Unicode, the regex engine only folds ASCII letters anyway.
+    fn folds_case_itself(&self, sources: &[String]) -> bool {
+        self.case_fold_mode != CaseFoldMode::Default
+            && self.unicode
+            && !self.normalizes()
+            && self.is_case_insensitive(sources)
+    }
+
+    /// Parses each pattern with the same flags grep uses, folding case with `case_fold_mode`.
+    fn parse_sources(&self, sources: &[String]) -> Result<Vec<Hir>, RipgrepjsError> {
+        let literal = !self.word_list.is_empty()
+            || ((self.prefix_anchor || self.fixed_strings) && self.patterns.is_empty());
+        let fold_case = self.folds_case_itself(sources);
+        let parser = ParserBuilder::new()
+            .case_insensitive(self.is_case_insensitive(sources) && !fold_case)
+            // grep always treats `^` and `$` as line anchors
+            .multi_line(true)
+            .dot_matches_new_line(self.dot_matches_new_line)
+            .swap_greed(self.greedy_swap)
+            .ignore_whitespace(self.ignore_whitespace && !literal)
+            .unicode(self.unicode)
+            .octal(self.octal && !literal)
+            .crlf(self.crlf)
+            .utf8(false)
+            .clone();
+        let mut hirs = Vec::with_capacity(sources.len());
+        for source in sources {
+            // A parser panics if it's reused after some patterns, so each one gets its own
+            let hir = parser.build().parse(source)?;
+            hirs.push(if fold_case {
+                fold_case_with(&hir, self.case_fold_mode)
+            } else {
+                hir
+            });
+        }
+        Ok(hirs)
+    }
+
+    /// Describes what the regex engine can tell about the pattern before searching, for `explain`.
+    ///
+    /// grep doesn't expose what the engine made of the pattern, so it's parsed again here with the same flags,
+    /// including the case-insensitivity `is_case_insensitive` decides on.
+    pub(crate) fn explain(&self) -> Result<PatternExplanation, RipgrepjsError> {
+        // Compiling it first means an invalid pattern gets the same error a search would
+        self.to_matcher()?;
+
+        let sources = self.sources();
+        let mut hirs = self.parse_sources(&sources)?;
+        if self.whole_line {
+            let (start, end) = if self.crlf {
+                (Look::StartCRLF, Look::EndCRLF)
+            } else {
+                (Look::StartLF, Look::EndLF)
+            };
+            for hir in hirs.iter_mut() {
+                let inner = std::mem::replace(hir, Hir::empty());
+                *hir = Hir::concat(vec![Hir::look(start), inner, Hir::look(end)]);
+            }
+        }
+        let hir = Hir::alternation(hirs);
+
+        let properties = hir.properties();
+        let mut prefixes = Extractor::new().extract(&hir);
+        prefixes.optimize_for_prefix_by_preference();
+        let mut suffixes = Extractor::new().kind(ExtractKind::Suffix).extract(&hir);
+        suffixes.optimize_for_suffix_by_preference();
+        let to_strings = |literals: &[Literal]| {
+            literals
+                .iter()
+                .map(|literal| to_lossy_string(literal.as_bytes(), "\u{FFFD}"))
+                .collect()
+        };
+        Ok(PatternExplanation {
+            anchored_start: properties.look_set_prefix().contains_anchor(),
+            anchored_end: properties.look_set_suffix().contains_anchor(),
+            multi_line: self.multi_line,
+            prefix_literals: prefixes.literals().map(to_strings),
+            suffix_literals: suffixes.literals().map(to_strings),
+            min_length: properties.minimum_len(),
+            max_length: properties.maximum_len(),
+        })
+    }
+}
+
+/// What the regex engine can tell about a pattern ahead of time, for `explain`
+pub(crate) struct PatternExplanation {
+    // Whether every match starts (or ends) at the start (or end) of a line
+    anchored_start: bool,
+    anchored_end: bool,
+    // Whether matches can span lines
+    multi_line: bool,
+    // Literals that every match starts (or ends) with, which the engine scans for before running the regex.
+    // `None` if there aren't any worth using, which makes searching much slower.
+    prefix_literals: Option<Vec<String>>,
+    suffix_literals: Option<Vec<String>>,
+    // The shortest and longest matches possible in bytes; the longest is `None` if there's no limit
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+}
+
+impl PatternExplanation {
+    pub(crate) fn to_js_object<'a>(&self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+        let js_explanation = cx.empty_object();
+        let js_anchored_start = cx.boolean(self.anchored_start);
+        js_explanation.set(cx, "anchoredStart", js_anchored_start)?;
+        let js_anchored_end = cx.boolean(self.anchored_end);
+        js_explanation.set(cx, "anchoredEnd", js_anchored_end)?;
+        let js_multi_line = cx.boolean(self.multi_line);
+        js_explanation.set(cx, "multiline", js_multi_line)?;
+        if let Some(prefix_literals) = &self.prefix_literals {
+            let js_prefix_literals = strings_to_js_array(cx, prefix_literals.clone())?;
+            js_explanation.set(cx, "prefixLiterals", js_prefix_literals)?;
+        }
+        if let Some(suffix_literals) = &self.suffix_literals {
+            let js_suffix_literals = strings_to_js_array(cx, suffix_literals.clone())?;
+            js_explanation.set(cx, "suffixLiterals", js_suffix_literals)?;
+        }
+        if let Some(min_length) = self.min_length {
+            let js_min_length = cx.number(min_length as f64);
+            js_explanation.set(cx, "minLength", js_min_length)?;
+        }
+        if let Some(max_length) = self.max_length {
+            let js_max_length = cx.number(max_length as f64);
+            js_explanation.set(cx, "maxLength", js_max_length)?;
+        }
+        Ok(js_explanation)
+    }
+}
+
+/// Builds the pattern for `prefixAnchor`, which matches a literal prefix at the start of a line,
+/// after any indentation.
+///
+/// Anchoring lets the regex engine give up on each line as soon as the prefix doesn't match.
+pub(crate) fn anchored_prefix(prefix: &str) -> String {
+    format!(r"^\s*{}", escape(prefix))
+}
+
+/// Rewrites a case-sensitive pattern to match case-insensitively, folding case the way `mode` says.
+///
+/// Every literal becomes a class of the characters it folds to, which is how the regex engine's own
+/// case-insensitivity works too.
+pub(crate) fn fold_case_with(hir: &Hir, mode: CaseFoldMode) -> Hir {
+    match hir.kind() {
+        HirKind::Literal(hir::Literal(bytes)) => match std::str::from_utf8(bytes) {
+            Ok(text) => Hir::concat(
+                text.chars()
+                    .map(|c| {
+                        let mut class = ClassUnicode::new([ClassUnicodeRange::new(c, c)]);
+                        fold_class(&mut class, mode);
+                        Hir::class(Class::Unicode(class))
+                    })
+                    .collect(),
+            ),
+            // Bytes that aren't UTF-8 came from escapes like `(?-u:\xFF)`, which aren't letters
+            Err(_) => hir.clone(),
+        },
+        HirKind::Class(Class::Unicode(class)) => {
+            let mut class = class.clone();
+            fold_class(&mut class, mode);
+            Hir::class(Class::Unicode(class))
+        }
+        HirKind::Class(Class::Bytes(class)) => {
+            // Byte classes only ever fold ASCII letters
+            let mut class = class.clone();
+            class.case_fold_simple();
+            Hir::class(Class::Bytes(class))
+        }
+        HirKind::Repetition(repetition) => Hir::repetition(Repetition {
+            sub: Box::new(fold_case_with(&repetition.sub, mode)),
+            ..repetition.clone()
+        }),
+        HirKind::Capture(capture) => Hir::capture(Capture {
+            sub: Box::new(fold_case_with(&capture.sub, mode)),
+            ..capture.clone()
+        }),
+        HirKind::Concat(subs) => {
+            Hir::concat(subs.iter().map(|sub| fold_case_with(sub, mode)).collect())
+        }
+        HirKind::Alternation(subs) => {
+            Hir::alternation(subs.iter().map(|sub| fold_case_with(sub, mode)).collect())
+        }
+        HirKind::Empty | HirKind::Look(_) => hir.clone(),
+    }
+}
+
+/// Adds the other case of every character in a class, for `fold_case_with`
+pub(crate) fn fold_class(class: &mut ClassUnicode, mode: CaseFoldMode) {
+    // No character after these has another case, so there's no need to look any further
+    let last_cased = match mode {
+        CaseFoldMode::Ascii => 'z',
+        _ => '\u{1E943}',
+    };
+    let mut folded = Vec::new();
+    for range in class.iter() {
+        for c in range.start()..=range.end().min(last_cased) {
+            match mode {
+                CaseFoldMode::Ascii => {
+                    if c.is_ascii_alphabetic() {
+                        folded.push(c.to_ascii_lowercase());
+                        folded.push(c.to_ascii_uppercase());
+                    }
+                }
+                _ => {
+                    let mut lowercase = c.to_lowercase();
+                    if let (Some(lower), None) = (lowercase.next(), lowercase.next()) {
+                        folded.push(lower);
+                    }
+                    let mut uppercase = c.to_uppercase();
+                    if let (Some(upper), None) = (uppercase.next(), uppercase.next()) {
+                        folded.push(upper);
+                    }
+                }
+            }
+        }
+    }
+    class.union(&ClassUnicode::new(
+        folded.into_iter().map(|c| ClassUnicodeRange::new(c, c)),
+    ));
+}
+
+/// The matcher for a search's patterns.
+///
+/// With `normalizeForMatch`, it runs the regex against a normalized copy of each line (see `NormalizedLine`),
+/// which the pattern's literals have been normalized to match (see `normalized_pattern`), and maps its matches
+/// back to the line itself, so they can be reported like any other.
+#[derive(Clone, Debug)]
+pub(crate) struct SearchMatcher {
+    regex: RegexMatcher,
+    normalize: bool,
+}
+
+impl SearchMatcher {
+    /// Runs `find` on the normalized copy of each line, starting with the one `at` is in, until it finds something.
+    ///
+    /// The regex can't match across lines with `normalizeForMatch` (which can't be used with `multilineSearch`),
+    /// so this finds the same thing it would in the normalized copy of the whole haystack.
+    fn find_in_lines<T>(
+        &self,
+        haystack: &[u8],
+        at: usize,
+        mut find: impl FnMut(&[u8], usize) -> Result<Option<T>, NoError>,
+    ) -> Result<Option<(T, NormalizedLine)>, NoError> {
+        let terminator = self.regex.line_terminator().map_or(b'\n', |t| t.as_byte());
+        let mut start = memchr::memrchr(terminator, &haystack[..at]).map_or(0, |i| i + 1);
+        loop {
+            let end = memchr::memchr(terminator, &haystack[start..])
+                .map_or(haystack.len(), |i| start + i + 1);
+            let line = NormalizedLine::new(&haystack[start..end], start);
+            if let Some(found) = find(&line.text, line.position(at))? {
+                return Ok(Some((found, line)));
+            }
+            if end == haystack.len() {
+                return Ok(None);
+            }
+            start = end;
+        }
+    }
+}
+
+impl Matcher for SearchMatcher {
+    type Captures = SearchCaptures;
+    type Error = NoError;
+
+    fn find_at(&self, haystack: &[u8], at: usize) -> Result<Option<Match>, NoError> {
+        if !self.normalize {
+            return self.regex.find_at(haystack, at);
+        }
+        Ok(self
+            .find_in_lines(haystack, at, |line, at| self.regex.find_at(line, at))?
+            .map(|(found, line)| line.original(found)))
+    }
+
+    fn new_captures(&self) -> Result<SearchCaptures, NoError> {
+        Ok(SearchCaptures {
+            captures: self.regex.new_captures()?,
+            offsets: None,
+        })
+    }
+
+    fn capture_count(&self) -> usize {
+        self.regex.capture_count()
+    }
+
+    fn capture_index(&self, name: &str) -> Option<usize> {
+        self.regex.capture_index(name)
+    }
+
+    fn captures_at(
+        &self,
+        haystack: &[u8],
+        at: usize,
+        caps: &mut SearchCaptures,
+    ) -> Result<bool, NoError> {
+        if !self.normalize {
+            caps.offsets = None;
+            return self.regex.captures_at(haystack, at, &mut caps.captures);
+        }
+        let captures = &mut caps.captures;
+        let found = self.find_in_lines(haystack, at, |line, at| {
+            Ok(self.regex.captures_at(line, at, captures)?.then_some(()))
+        })?;
+        caps.offsets = found.map(|(_, line)| line.offsets);
+        Ok(caps.offsets.is_some())
+    }
+
+    fn shortest_match_at(&self, haystack: &[u8], at: usize) -> Result<Option<usize>, NoError> {
+        if !self.normalize {
+            return self.regex.shortest_match_at(haystack, at);
+        }
+        Ok(self.find_at(haystack, at)?.map(|found| found.end()))
+    }
+
+    fn non_matching_bytes(&self) -> Option<&ByteSet> {
+        // Bytes the regex can't match in the normalized line (like those of `É`) can still be in the line itself
+        if self.normalize {
+            None
+        } else {
+            self.regex.non_matching_bytes()
+        }
+    }
+
+    fn line_terminator(&self) -> Option<LineTerminator> {
+        self.regex.line_terminator()
+    }
+
+    fn find_candidate_line(&self, haystack: &[u8]) -> Result<Option<LineMatchKind>, NoError> {
+        if !self.normalize {
+            return self.regex.find_candidate_line(haystack);
+        }
+        Ok(self.shortest_match(haystack)?.map(LineMatchKind::Confirmed))
+    }
+}
+
+/// Captures from a `SearchMatcher`, mapped back to the line they're in with `normalizeForMatch`
+#[derive(Clone, Debug)]
+pub(crate) struct SearchCaptures {
+    captures: RegexCaptures,
+    // Where each byte of the normalized line the captures are in came from, with `normalizeForMatch`
+    offsets: Option<Vec<usize>>,
+}
+
+impl Captures for SearchCaptures {
+    fn len(&self) -> usize {
+        self.captures.len()
+    }
+
+    fn get(&self, i: usize) -> Option<Match> {
+        let found = self.captures.get(i)?;
+        Some(match &self.offsets {
+            Some(offsets) => Match::new(offsets[found.start()], offsets[found.end()]),
+            None => found,
+        })
+    }
+}
+
+/// The terms of the `wordList` option, for working out which one a match was
+pub(crate) struct WordList {
+    terms: HashSet<String>,
+    // Lowercased terms, for case-insensitive matches
+    lowercase_terms: HashMap<String, String>,
+    // Normalized terms, for matches with `normalizeForMatch`
+    normalized_terms: HashMap<String, String>,
+}
+
+impl WordList {
+    pub(crate) fn new(terms: &[String]) -> Self {
+        Self {
+            terms: terms.iter().cloned().collect(),
+            lowercase_terms: terms
+                .iter()
+                .map(|term| (term.to_lowercase(), term.clone()))
+                .collect(),
+            normalized_terms: terms
+                .iter()
+                .map(|term| (normalized_text(term), term.clone()))
+                .collect(),
+        }
+    }
+
+    /// Finds the term some matched text came from, which differs from the text if it matched case-insensitively
+    /// or with `normalizeForMatch`
+    pub(crate) fn term_for(&self, matched: &str) -> Option<&str> {
+        match self.terms.get(matched) {
+            Some(term) => Some(term),
+            None => self
+                .lowercase_terms
+                .get(&matched.to_lowercase())
+                .or_else(|| self.normalized_terms.get(&normalized_text(matched)))
+                .map(String::as_str),
+        }
+    }
+}
+
+/// Works out the name of each of a pattern's capture groups, indexed by group number.
+///
+/// The matcher can look up names but not list them, so possible names are picked out of the pattern
+/// and then checked against the matcher, which rules out look-alikes such as escaped parentheses.
+pub(crate) fn capture_names(pattern: &str, matcher: &SearchMatcher) -> Vec<Option<String>> {
+    let mut names = vec![None; matcher.capture_count()];
+    for opening in ["(?P<", "(?<"] {
+        for (start, _) in pattern.match_indices(opening) {
+            let rest = &pattern[start + opening.len()..];
+            let name = match rest.find('>') {
+                Some(end) => &rest[..end],
+                None => continue,
+            };
+            if let Some(slot) = matcher
+                .capture_index(name)
+                .and_then(|index| names.get_mut(index))
+            {
+                *slot = Some(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Checks whether a pattern matches lines without needing any of their text, like `a*`, `.*`, or `$` do.
+///
+/// Matching an empty line means it can match the empty string, and patterns like `^$` and `^\s*$`,
+/// which only match empty or blank lines, are ruled out by trying it on a line of text too.
+pub(crate) fn matches_empty(matcher: &SearchMatcher) -> bool {
+    // A line that no pattern would reasonably match on purpose
+    matches!(matcher.is_match(b""), Ok(true)) && matches!(matcher.is_match(b"\x01"), Ok(true))
+}
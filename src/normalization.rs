@@ -0,0 +1,207 @@
+//! Normalizing lines and patterns for `normalizeForMatch`
+
+use std::convert::Infallible;
+
+use grep::matcher::Match;
+use regex_syntax::{
+    ast::{self, Ast},
+    escape,
+};
+
+/// Whether a character is a combining mark (one with a nonzero canonical combining class, like an accent),
+/// which `normalizeForMatch` strips
+pub(crate) fn is_combining_mark(c: char) -> bool {
+    unicode_normalization::char::canonical_combining_class(c) != 0
+}
+
+/// What `normalizeForMatch` sees a character as: lowercased, and without the combining marks that
+/// canonical decomposition (NFD) splits off it, so `É`, `ά`, and `ệ` are seen as `e`, `α`, and `e`.
+///
+/// Characters that lowercase to more than one (other than to a letter and combining marks, like `İ`)
+/// are left as they are, as are those that decompose into more than one letter, like Hangul syllables.
+pub(crate) fn normalized_char(c: char) -> char {
+    let mut lowercase = c.to_lowercase().filter(|c| !is_combining_mark(*c));
+    let lower = match (lowercase.next(), lowercase.next()) {
+        (Some(lower), None) => lower,
+        _ => return c,
+    };
+    let mut bases = Vec::with_capacity(1);
+    unicode_normalization::char::decompose_canonical(lower, |c| {
+        if !is_combining_mark(c) {
+            bases.push(c);
+        }
+    });
+    match bases[..] {
+        [base] => base,
+        _ => lower,
+    }
+}
+
+/// Normalizes some text the way `normalizeForMatch` sees it, stripping combining marks.
+pub(crate) fn normalized_text(text: &str) -> String {
+    text.chars()
+        .filter(|c| !is_combining_mark(*c))
+        .map(normalized_char)
+        .collect()
+}
+
+/// Rewrites a pattern's literals the way `normalizeForMatch` sees text, so `Café` is searched for as `cafe`.
+///
+/// Only literals change: classes and escapes like `[a-z]` and `\w` match the normalized line as they are,
+/// so `[^e]` doesn't match `é`, which is seen as `e`. Combining marks in the pattern are dropped, since the line
+/// has none. If the pattern doesn't parse, it's left as it was, so that the error comes from grep like any other.
+pub(crate) fn normalized_pattern(pattern: &str, ignore_whitespace: bool, octal: bool) -> String {
+    struct Literals(Vec<(ast::Span, String)>);
+
+    impl Literals {
+        fn rewrite(&mut self, literal: &ast::Literal, in_class: bool) {
+            let normalized = normalized_char(literal.c);
+            if is_combining_mark(literal.c) && !in_class {
+                // An empty group, rather than nothing, so that a quantifier after it still has something to repeat
+                self.0.push((literal.span, "(?:)".to_string()));
+            } else if normalized != literal.c {
+                self.0
+                    .push((literal.span, escape(normalized.encode_utf8(&mut [0; 4]))));
+            }
+        }
+    }
+
+    impl ast::Visitor for Literals {
+        type Output = Vec<(ast::Span, String)>;
+        type Err = Infallible;
+
+        fn finish(self) -> Result<Self::Output, Infallible> {
+            Ok(self.0)
+        }
+
+        fn visit_pre(&mut self, ast: &Ast) -> Result<(), Infallible> {
+            if let Ast::Literal(literal) = ast {
+                self.rewrite(literal, false);
+            }
+            Ok(())
+        }
+
+        fn visit_class_set_item_pre(&mut self, item: &ast::ClassSetItem) -> Result<(), Infallible> {
+            match item {
+                ast::ClassSetItem::Literal(literal) => self.rewrite(literal, true),
+                // Unless that would turn the range around, like `[Z-à]` becoming `[z-a]`
+                ast::ClassSetItem::Range(range)
+                    if normalized_char(range.start.c) <= normalized_char(range.end.c) =>
+                {
+                    self.rewrite(&range.start, true);
+                    self.rewrite(&range.end, true);
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    let parsed = ast::parse::ParserBuilder::new()
+        .ignore_whitespace(ignore_whitespace)
+        .octal(octal)
+        .build()
+        .parse(pattern);
+    let mut literals = match parsed {
+        Ok(parsed) => match ast::visit(&parsed, Literals(Vec::new())) {
+            Ok(literals) => literals,
+            Err(never) => match never {},
+        },
+        Err(_) => return pattern.to_string(),
+    };
+    literals.sort_by_key(|(span, _)| span.start.offset);
+
+    let mut normalized = String::with_capacity(pattern.len());
+    let mut copied = 0;
+    for (span, replacement) in literals {
+        normalized.push_str(&pattern[copied..span.start.offset]);
+        normalized.push_str(&replacement);
+        copied = span.end.offset;
+    }
+    normalized.push_str(&pattern[copied..]);
+    normalized
+}
+
+/// A line as `normalizeForMatch` sees it (see `normalized_char`), with where each of its bytes came from
+pub(crate) struct NormalizedLine {
+    pub(crate) text: Vec<u8>,
+    // The offset in the original text of each byte of `text`, and then of the end of the line.
+    // Combining marks aren't in `text`, so a match that ends before one is mapped to after it.
+    pub(crate) offsets: Vec<usize>,
+}
+
+impl NormalizedLine {
+    /// Normalizes a line that starts at `start` in the text it's from.
+    ///
+    /// Bytes that aren't UTF-8 are kept as they are.
+    pub(crate) fn new(line: &[u8], start: usize) -> Self {
+        let mut text = Vec::with_capacity(line.len());
+        let mut offsets = Vec::with_capacity(line.len() + 1);
+        let mut chunk_start = start;
+        for chunk in line.utf8_chunks() {
+            for (i, c) in chunk.valid().char_indices() {
+                let normalized = if c.is_ascii() {
+                    c.to_ascii_lowercase()
+                } else if is_combining_mark(c) {
+                    continue;
+                } else {
+                    normalized_char(c)
+                };
+                text.extend_from_slice(normalized.encode_utf8(&mut [0; 4]).as_bytes());
+                offsets.resize(text.len(), chunk_start + i);
+            }
+            let invalid_start = chunk_start + chunk.valid().len();
+            text.extend_from_slice(chunk.invalid());
+            offsets.extend(invalid_start..invalid_start + chunk.invalid().len());
+            chunk_start = invalid_start + chunk.invalid().len();
+        }
+        offsets.push(start + line.len());
+        Self { text, offsets }
+    }
+
+    /// Where an offset in the original text is in `text`
+    pub(crate) fn position(&self, offset: usize) -> usize {
+        self.offsets[..self.text.len()].partition_point(|&original| original < offset)
+    }
+
+    /// Maps a match in `text` back to the original text
+    pub(crate) fn original(&self, normalized: Match) -> Match {
+        Match::new(
+            self.offsets[normalized.start()],
+            self.offsets[normalized.end()],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized_lines_map_matches_back_to_the_original() {
+        let line = NormalizedLine::new("Cafe\u{301} \u{FF}É\n".as_bytes(), 10);
+        assert_eq!(line.text, b"cafe ye\n");
+        // The whole of the e and its accent, then the two bytes each of ÿ and É
+        assert_eq!(line.original(Match::new(0, 4)), Match::new(10, 16));
+        assert_eq!(line.original(Match::new(5, 7)), Match::new(17, 21));
+        assert_eq!(line.position(16), 4);
+        // Bytes that aren't UTF-8 are kept as they are
+        let line = NormalizedLine::new(b"\xffA", 0);
+        assert_eq!(line.text, b"\xffa");
+        assert_eq!(line.original(Match::new(1, 2)), Match::new(1, 2));
+    }
+
+    #[test]
+    fn normalized_patterns_have_their_literals_normalized() {
+        assert_eq!(normalized_pattern("Café", false, false), "cafe");
+        // A combining mark leaves an empty group behind, for anything quantifying it
+        assert_eq!(normalized_pattern("CAFE\u{301}", false, false), "cafe(?:)");
+        // Literals in classes are too, but the classes themselves and escapes like `\w` are left alone
+        assert_eq!(
+            normalized_pattern("caf[^É-Ë]\\w+", false, false),
+            "caf[^e-e]\\w+"
+        );
+        // A pattern that doesn't parse is left for grep to report
+        assert_eq!(normalized_pattern("Café(", false, false), "Café(");
+    }
+}
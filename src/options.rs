@@ -0,0 +1,1766 @@
+//! The options a search takes, and reading them from the objects passed in from JavaScript
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use grep::{
+    matcher::LineTerminator,
+    searcher::{Searcher, SearcherBuilder},
+};
+use ignore::types::{Types, TypesBuilder};
+use neon::{prelude::*, result::Throw};
+
+use crate::{
+    compile_pattern_from_options,
+    error::RipgrepjsError,
+    handles::CompiledPattern,
+    matcher::{CaseFoldMode, MatcherOptions, SearchMatcher, WordList},
+    results::{absolute_path, FileMetadata, CALLBACK_BATCH_SIZE},
+    walk::Blob,
+};
+
+/// How lines end in the files being searched
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum LineTerminatorMode {
+    /// `\n`, the default
+    Lf,
+    /// `\r\n`, though a lone `\n` still ends a line
+    Crlf,
+    /// Any single byte
+    Byte(u8),
+}
+
+impl LineTerminatorMode {
+    pub(crate) fn to_line_terminator(self) -> LineTerminator {
+        match self {
+            LineTerminatorMode::Lf => LineTerminator::default(),
+            LineTerminatorMode::Crlf => LineTerminator::crlf(),
+            LineTerminatorMode::Byte(byte) => LineTerminator::byte(byte),
+        }
+    }
+}
+
+/// Options for building a searcher
+#[derive(Clone)]
+pub(crate) struct SearcherOptions {
+    pub line_terminator: LineTerminatorMode,
+    pub invert_match: bool,
+    pub include_line_numbers: bool,
+    pub multiline_search: bool,
+    pub after_context: usize,
+    pub before_context: usize,
+    pub passthru: bool,
+    pub heap_limit: Option<usize>,
+    /// Stops searching a file at the first non-matching line after a match.
+    ///
+    /// That line is still reported as after-context (or passed through with `passthru`),
+    /// so at most one line of after-context is ever reported; before-context is unaffected.
+    pub stop_on_nonmatch: bool,
+    /// Stops the whole search once this much time has passed.
+    ///
+    /// Each thread only notices between files or at its next match, so a file that's already
+    /// being searched may take a while longer to finish if it has no matches.
+    pub timeout: Option<Duration>,
+    /// Reports the search's progress after files are searched, for `onProgress`.
+    ///
+    /// Set when the callback is given.
+    pub report_progress: bool,
+    /// Reports which filter or gitignore rule left each file out of the search, for `onIgnore`.
+    ///
+    /// Set when the callback is given along with `debugIgnores`, since working that out takes extra matching.
+    pub report_ignores: bool,
+    /// The least time between progress reports, so that a search of many small files doesn't flood the JavaScript thread
+    pub progress_interval: Duration,
+    /// Reports results as lines of JSON in the format of `rg --json`, rather than as objects
+    pub json_lines: bool,
+    /// Reports each match as an LSP `Location`, with zero-based lines and UTF-16 characters, rather than as results
+    pub lsp_mode: bool,
+    /// Stops searching each file after its first match
+    pub first_match_per_file: bool,
+    /// Stops the whole search once this many matches have been found.
+    ///
+    /// The count itself is exact, but when searching a directory in parallel,
+    /// *which* matches make the cut depends on how the files are scheduled across threads.
+    pub max_total_matches: Option<usize>,
+    /// Stops the whole search once this many files have been searched.
+    ///
+    /// Files left out by `file_name_globs` don't count.
+    pub max_files_searched: Option<usize>,
+    /// Globs matched against file names (not full paths) when searching a directory.
+    ///
+    /// A file is searched if its name matches any of them; if there are none, every file is searched.
+    pub file_name_globs: Vec<String>,
+    /// The built-in types and `customTypes`, with those in `fileTypes` selected and those in `fileTypesNot`
+    /// negated. `None` if neither was given.
+    pub file_types: Option<Types>,
+    /// Limits on the size and modification time of files to search, checked before they're opened
+    pub metadata_filter: Option<MetadataFilter>,
+    /// The most files open for searching at once, however many threads there are.
+    ///
+    /// Files aren't split into chunks with this, since a thread waiting for its chunks could start another
+    /// file and wait for a slot that it's holding itself.
+    pub max_concurrent_files: Option<usize>,
+    /// Suppresses matches whose text has already been reported.
+    ///
+    /// Every distinct matched line is kept in memory to do this,
+    /// either until the end of each file or the end of the whole search, depending on the scope.
+    pub unique: Option<UniqueScope>,
+    /// What to report for matches that aren't valid UTF-8
+    pub binary_output: BinaryOutput,
+    /// Stops the search at the first match that isn't valid UTF-8 (with `BinaryOutput::Text`), with its error
+    /// in the summary, rather than passing it to `onError` and carrying on
+    pub strict_utf8: bool,
+    /// What replaces invalid UTF-8 in lossily converted lines
+    pub invalid_char_replacement: String,
+    /// How context lines (see `before_context` and `after_context`) are reported
+    pub context_mode: ContextMode,
+    /// Reported between groups of context lines that aren't next to each other, like ripgrep's `--context-separator`
+    pub context_separator: Option<String>,
+    /// Lists the paths of matching files in the summary, separated by NUL bytes
+    pub null_separated: bool,
+    /// Adds each file's size and modification time to its matches
+    pub include_file_metadata: bool,
+    /// Adds the names of the other files in each matching file's directory to its matches
+    pub include_siblings: bool,
+    /// Matches the pattern against the paths of files, rather than their contents
+    pub match_paths_only: bool,
+    /// Reports the path of each file that would be searched, without opening any of them
+    pub dry_run: bool,
+    /// Reports each match on its own, with just the matched text as its line
+    pub extract_matches: bool,
+    /// Strips leading and trailing whitespace (including the line terminator) from reported lines
+    pub trim: bool,
+    /// Replaces each run of whitespace within reported lines with a single space.
+    ///
+    /// Line terminators are kept as they are, and offsets still refer to the original lines.
+    pub collapse_whitespace: bool,
+    /// Keeps each reported line's terminator (`\n`, or `\r\n` in CRLF mode) at its end.
+    ///
+    /// It's only missing from the last line of a file that doesn't end with one.
+    pub keep_line_terminator: bool,
+    /// Reports the byte ranges of the pattern's matches within each matched line
+    pub include_match_ranges: bool,
+    /// Reports where the first match in each result starts and ends, as byte offsets into the file
+    pub include_match_byte_range: bool,
+    /// Reports the width of each match's indentation, in columns, with tabs reaching the next multiple of `tab_width`
+    pub include_indent: bool,
+    pub tab_width: usize,
+    /// The most match ranges to report per line, for `include_match_ranges`
+    pub max_matches_per_line: Option<usize>,
+    /// Passes results to JavaScript in batches from one thread, rather than one at a time from each searching thread
+    pub callback_thread: bool,
+    /// With `callback_thread`, the most results passed to JavaScript at once
+    pub batch_size: usize,
+    /// With `callback_thread`, how long a batch waits to fill up after its first result before it's passed on anyway.
+    /// Without it, each batch is whatever's ready, so results are passed on as soon as possible.
+    pub flush_interval: Option<Duration>,
+    /// Searches on several threads at once. Without it, the search runs on one thread and walks directories
+    /// in order of file name, so results arrive in the same order every time.
+    pub parallel: bool,
+    /// Only searches this many bytes from the start of each file
+    pub max_bytes_per_file: Option<u64>,
+    /// Reports the `column` of each match, and counts match ranges, in this unit
+    pub column_encoding: Option<ColumnEncoding>,
+    /// Reports matched lines as `encodedLines` in this encoding, rather than as strings in `matchedLines`
+    pub output_encoding: Option<OutputEncoding>,
+    /// Follows symbolic links found while searching directories, rather than skipping them
+    pub follow_symlinks: bool,
+    /// Reports files found through symbolic links by the path through the link, rather than the target's real path
+    pub report_link_path: bool,
+    /// Leaves out files and directories matched by the `.gitignore` files in the directories searched and above them
+    pub respect_gitignore: bool,
+    /// Leaves out files and directories matched by the global gitignore (git's `core.excludesFile`,
+    /// or `~/.config/git/ignore`), like `ignore::WalkBuilder::git_global`
+    pub respect_global_gitignore: bool,
+    /// Reports paths with `/` between their components, even where the platform's separator is `\`
+    pub forward_slash_paths: bool,
+    /// Reports absolute, canonical paths, even for files found under relative roots.
+    ///
+    /// With `report_link_path`, links aren't resolved; paths are just made absolute against the current directory.
+    pub absolute_paths: bool,
+    /// Only searches this part of each file, still numbering lines from the start of the file
+    pub region: Option<FileRegion>,
+    /// Only reports matches starting on these line numbers, which needs `include_line_numbers`
+    pub line_filter: Option<Arc<BTreeSet<u64>>>,
+    /// Splits files larger than this many bytes into chunks of about this size, which are searched in parallel.
+    ///
+    /// Only used when every other option can work a chunk at a time; see `can_search_in_chunks`.
+    pub chunk_size: Option<u64>,
+    /// With `multiline_search`, reads files this many lines at a time rather than all at once,
+    /// so only matches spanning up to this many lines are sure to be found. Context lines aren't reported.
+    pub multiline_window: Option<u64>,
+    pub binary_detection: BinaryDetection,
+    /// Counts matches for the summary instead of reporting them
+    pub count_mode: Option<CountMode>,
+    /// Adds up the lines, bytes, and words of matching lines for the summary instead of reporting them
+    pub match_stats: bool,
+    /// The `wordList` the matcher was built from, if any; set along with the matcher
+    pub word_list: Option<Arc<WordList>>,
+    /// A matcher for each of the `patterns` the matcher was built from, if any, for working out
+    /// each match's `patternIndices`; set along with the matcher
+    pub pattern_matchers: Option<Arc<Vec<SearchMatcher>>>,
+    /// Reports the spans of the pattern's capture groups in each match
+    pub include_captures: bool,
+    /// Strings to put before and after each match, to report a `highlightedLine`
+    pub highlight: Option<(String, String)>,
+    /// How many characters to keep either side of the first match, to report a `snippet`
+    pub context_chars: Option<usize>,
+    /// The name of each of the matcher's capture groups, if it has one; set along with the matcher
+    pub capture_names: Arc<Vec<Option<String>>>,
+    /// Matches "heading" lines (such as function definitions); each match is annotated
+    /// with the closest one before it in its file
+    pub heading_matcher: Option<Arc<SearchMatcher>>,
+    /// How many lines from the start of each file (like a log's banner) to attach to every match in it
+    pub header_lines: Option<usize>,
+    /// Searched for within each matched line, with every match of it reported under the line's result
+    pub sub_matcher: Option<Arc<SearchMatcher>>,
+    /// Reports matched bytes as they are, with their byte offsets, rather than converting them to strings.
+    ///
+    /// Only set by `searchRaw`.
+    pub raw_bytes: bool,
+    /// With `dry_run`, only counts the files that would be searched, rather than reporting each one.
+    ///
+    /// Only set by `countCandidateFiles`.
+    pub count_candidates: bool,
+    /// Reports the start and end of each file searched, for `onFileStart` and `onFileEnd`.
+    ///
+    /// Set when either callback is given.
+    pub report_file_boundaries: bool,
+    /// Holds back each file's results until it's been searched, then reports them together.
+    ///
+    /// Every result from a file is kept in memory until the end of that file.
+    pub group_by_file: bool,
+    /// Passes each file's results on together, with files in the order they started being searched,
+    /// while still searching several files at once.
+    ///
+    /// A file's results are held back until every file that started before it has been sent on.
+    pub order_by_file: bool,
+    /// Searches each file in `.tar`, `.tar.gz`, and `.zip` archives as if it were a file at `<archive>!<member path>`,
+    /// and skips archives compressed other ways (like `.tar.xz`), which can't be looked inside of.
+    pub search_archives: bool,
+    /// Combines matches whose context windows overlap (or touch) into one result, with `contextMode: "inline"`,
+    /// whose lines are the whole span and whose `matches` are the matches in it, so no line is reported twice.
+    ///
+    /// Each span is held back until it's been read to its end.
+    pub merge_adjacent: bool,
+    /// With `merge_adjacent`, the most matches reported in one span (though always at least the first),
+    /// so a dense span doesn't report hundreds. The span's lines still cover every match.
+    pub max_matches_per_group: Option<usize>,
+    /// Matches are left out if their lines also match this.
+    ///
+    /// That's an extra regex search over every matched line, so it's cheap when matches are rare,
+    /// but it can roughly double the matching work when most lines match.
+    pub exclude_matcher: Option<Arc<SearchMatcher>>,
+    /// Files are left out of the search entirely if any of their lines match this, like generated files' markers.
+    ///
+    /// Each file is read for it before being searched, up to the first line that matches it
+    /// (so all the way through a file that's searched).
+    pub skip_file_matcher: Option<Arc<SearchMatcher>>,
+    /// Only files containing this exact text are searched, which is checked for much faster than the pattern is.
+    ///
+    /// Each file is read for it before being searched, up to where it's found.
+    pub required_literal: Option<memchr::memmem::Finder<'static>>,
+}
+
+/// How context lines are passed to JavaScript
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ContextMode {
+    /// Context lines aren't reported
+    None,
+    /// Each context line is passed to the callback as its own result, with a `contextKind`.
+    ///
+    /// With `invert_match`, these are the lines that *did* match the pattern.
+    Separate,
+    /// Each match carries `before` and `after` arrays with its whole context window.
+    ///
+    /// Matches are held back until their after-context has been read.
+    Inline,
+}
+
+/// How matches containing invalid UTF-8 are passed to JavaScript
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BinaryOutput {
+    /// Passes an error to `onError` instead (or stops the search with it, with `strict_utf8`)
+    Text,
+    /// Reports a `hexDump` of the matched bytes instead of `matchedLines`
+    Hex,
+    /// Replaces invalid sequences with `invalid_char_replacement`
+    Lossy,
+}
+
+/// What the searcher does with files that look binary (that is, contain a NUL byte)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BinaryDetection {
+    /// Searches them like any other file
+    None,
+    /// Stops searching them as soon as a NUL byte is seen
+    Quit,
+    /// Searches them with NUL bytes replaced by line terminators
+    Convert,
+}
+
+/// What `countMode` counts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CountMode {
+    /// Matching lines, like `rg --count`
+    Lines,
+    /// Occurrences of the pattern, which can be several per line, like `rg --count-matches`
+    Matches,
+}
+
+/// The unit that `column` and `matchRanges` offsets are counted in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ColumnEncoding {
+    /// Bytes of UTF-8
+    Utf8,
+    /// UTF-16 code units, as JavaScript strings (and editors like VS Code) count them
+    Utf16,
+    /// Unicode scalar values; combining characters count separately from what they combine with
+    Char,
+}
+
+impl ColumnEncoding {
+    /// Converts a byte offset within some text to this unit.
+    ///
+    /// Invalid UTF-8 counts the same as it does once lossily converted.
+    pub(crate) fn offset_in(self, bytes: &[u8], byte_offset: usize) -> usize {
+        let prefix = &bytes[..byte_offset.min(bytes.len())];
+        match self {
+            ColumnEncoding::Utf8 => prefix.len(),
+            ColumnEncoding::Utf16 => String::from_utf8_lossy(prefix)
+                .chars()
+                .map(char::len_utf16)
+                .sum(),
+            ColumnEncoding::Char => String::from_utf8_lossy(prefix).chars().count(),
+        }
+    }
+}
+
+/// How `replaceInFile` writes the new contents of a file, for `replaceStrategy`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ReplaceStrategy {
+    /// Writes a temporary file next to the original, then renames it over the original
+    Atomic,
+    /// Truncates the original and writes the new contents straight into it
+    Truncate,
+}
+
+/// The encoding to report matched lines in, for `outputEncoding`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OutputEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl OutputEncoding {
+    pub(crate) fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            OutputEncoding::Utf8 => text.as_bytes().to_vec(),
+            OutputEncoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            OutputEncoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        }
+    }
+}
+
+/// The part of each file to search, for `startLine`/`endLine`, `startByte`/`endByte`, and `tailLines`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum FileRegion {
+    /// Lines `start` through `end` (inclusive), counting from 1
+    Lines { start: u64, end: Option<u64> },
+    /// Every line that starts at or after `start`, and before `end`
+    Bytes { start: u64, end: Option<u64> },
+    /// The last `lines` lines, found by reading backwards from the end
+    Tail { lines: u64 },
+}
+
+/// How widely the `unique` option deduplicates matches
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum UniqueScope {
+    File,
+    Search,
+}
+
+impl SearcherOptions {
+    /// Generates a ripgrep Seacher from an options struct.
+    ///
+    /// This abstracts away the builder pattern, which doesn't work well across the FFI boundary.
+    pub(crate) fn to_searcher(&self) -> Searcher {
+        let mut builder = SearcherBuilder::new();
+
+        builder.line_terminator(self.line_terminator.to_line_terminator());
+
+        builder.invert_match(self.invert_match);
+        builder.line_number(self.include_line_numbers);
+        builder.multi_line(self.multiline_search);
+        // Counting doesn't report context, so there's no point in finding any
+        let counting = self.is_counting();
+        builder.after_context(if counting { 0 } else { self.after_context });
+        builder.before_context(if counting { 0 } else { self.before_context });
+        builder.passthru(self.passthru && !counting);
+        builder.heap_limit(self.heap_limit);
+        builder.stop_on_nonmatch(self.stop_on_nonmatch);
+        builder.binary_detection(match self.binary_detection {
+            BinaryDetection::None => grep::searcher::BinaryDetection::none(),
+            BinaryDetection::Quit => grep::searcher::BinaryDetection::quit(b'\x00'),
+            BinaryDetection::Convert => grep::searcher::BinaryDetection::convert(b'\x00'),
+        });
+
+        builder.build()
+    }
+
+    /// Whether matches are only counted (with `count_mode` or `match_stats`) rather than reported
+    pub(crate) fn is_counting(&self) -> bool {
+        self.count_mode.is_some() || self.match_stats
+    }
+
+    /// Applies `trim` and `collapse_whitespace` to a line that's about to be reported
+    pub(crate) fn tidy_line(&self, line: String) -> String {
+        let line = if self.trim {
+            line.trim().to_string()
+        } else {
+            line
+        };
+        if !self.collapse_whitespace {
+            return line;
+        }
+        let mut collapsed = String::with_capacity(line.len());
+        let mut in_whitespace = false;
+        for c in line.chars() {
+            if c == '\n' || c == '\r' {
+                collapsed.push(c);
+                in_whitespace = false;
+            } else if c.is_whitespace() {
+                if !in_whitespace {
+                    collapsed.push(' ');
+                }
+                in_whitespace = true;
+            } else {
+                collapsed.push(c);
+                in_whitespace = false;
+            }
+        }
+        collapsed
+    }
+
+    /// Whether files can be split up with `chunk_size`.
+    ///
+    /// Chunks are split between lines, so anything that needs to see more than one line at a time
+    /// (or to know what came earlier in the file) has to search the whole file in order.
+    pub(crate) fn can_search_in_chunks(&self) -> bool {
+        let reports_context = self.before_context > 0 || self.after_context > 0 || self.passthru;
+        !self.multiline_search
+            && (self.is_counting() || !reports_context)
+            && !self.stop_on_nonmatch
+            && !self.first_match_per_file
+            && self.unique != Some(UniqueScope::File)
+            && self.heading_matcher.is_none()
+            && self.region.is_none()
+            && !self.group_by_file
+            && !self.order_by_file
+            && !self.report_file_boundaries
+            // Chunks' line numbers are only known once every chunk before them is done
+            && self.line_filter.is_none()
+            // Each file's `begin` and `end` messages have to surround all of its results
+            && !self.json_lines
+            && !self.lsp_mode
+            && self.max_concurrent_files.is_none()
+            // Later chunks could use up `max_total_matches` before earlier ones, leaving gaps in the file's matches
+            && self.max_total_matches.is_none()
+    }
+
+    /// Converts a path to the string it's reported to JavaScript as.
+    ///
+    /// Only the reported string changes with `forward_slash_paths` and `absolute_paths`;
+    /// files are still opened by their real paths.
+    pub(crate) fn reported_path(&self, path: &Path) -> String {
+        let absolute;
+        let path = if self.absolute_paths {
+            absolute = if self.report_link_path {
+                absolute_path(path)
+            } else {
+                // A file that's been deleted since it was searched (or an archive member) can't be canonicalized
+                std::fs::canonicalize(path).unwrap_or_else(|_| absolute_path(path))
+            };
+            &absolute
+        } else {
+            path
+        };
+        let path = path.to_string_lossy();
+        if self.forward_slash_paths && std::path::MAIN_SEPARATOR != '/' {
+            path.replace(std::path::MAIN_SEPARATOR, "/")
+        } else {
+            path.into_owned()
+        }
+    }
+
+    /// Compiles the `fileNameGlob`, `fileTypes`, and `fileTypesNot` options, if any of them were given.
+    pub(crate) fn to_file_name_filter(&self) -> Result<Option<FileNameFilter>, RipgrepjsError> {
+        if self.file_name_globs.is_empty() && self.file_types.is_none() {
+            return Ok(None);
+        }
+
+        let globs = if self.file_name_globs.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(&self.file_name_globs)?)
+        };
+        Ok(Some(FileNameFilter {
+            globs,
+            types: self.file_types.clone(),
+        }))
+    }
+}
+
+/// Which files to search, going by their names, from `fileNameGlob`, `fileTypes`, and `fileTypesNot`
+pub(crate) struct FileNameFilter {
+    // The name has to match one of these, if there are any
+    globs: Option<GlobSet>,
+    // ...and mustn't be ignored by these
+    types: Option<Types>,
+}
+
+impl FileNameFilter {
+    pub(crate) fn is_match(&self, name: &std::ffi::OsStr) -> bool {
+        self.globs.as_ref().is_none_or(|globs| globs.is_match(name))
+            && self
+                .types
+                .as_ref()
+                .is_none_or(|types| !types.matched(name, false).is_ignore())
+    }
+
+    /// Works out why a file's name didn't pass the filter, for `onIgnore`: the option that left it out,
+    /// and for `fileTypesNot`, the glob it matched.
+    pub(crate) fn why_excluded(&self, name: &std::ffi::OsStr) -> (&'static str, Option<String>) {
+        if let Some(types) = &self.types {
+            if let ignore::Match::Ignore(glob) = types.matched(name, false) {
+                // Only a type in `fileTypesNot` ignores a file it matches; the others ignore files they don't
+                return match glob.file_type_def() {
+                    Some(def) => (
+                        "fileTypesNot",
+                        def.globs()
+                            .iter()
+                            .find(|glob| {
+                                GlobBuilder::new(glob)
+                                    .literal_separator(true)
+                                    .build()
+                                    .is_ok_and(|glob| glob.compile_matcher().is_match(name))
+                            })
+                            .cloned(),
+                    ),
+                    None => ("fileTypes", None),
+                };
+            }
+        }
+        ("fileNameGlob", None)
+    }
+}
+
+pub(crate) fn build_glob_set(globs: &[String]) -> Result<GlobSet, RipgrepjsError> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Limits on files' sizes (in bytes) and modification times (in milliseconds since the Unix epoch),
+/// for `metadataFilter`. Every limit given has to be met for a file to be searched.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct MetadataFilter {
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after_ms: Option<f64>,
+    modified_before_ms: Option<f64>,
+}
+
+impl MetadataFilter {
+    /// Whether a file meets every limit. One whose modification time isn't known fails any limit on it.
+    pub(crate) fn matches(&self, metadata: &FileMetadata) -> bool {
+        let modified = |check: fn(f64, f64) -> bool, limit: Option<f64>| match limit {
+            Some(limit) => metadata
+                .modified_ms
+                .is_some_and(|modified_ms| check(modified_ms, limit)),
+            None => true,
+        };
+        self.min_size
+            .is_none_or(|min_size| metadata.size >= min_size)
+            && self
+                .max_size
+                .is_none_or(|max_size| metadata.size <= max_size)
+            && modified(
+                |modified_ms, after| modified_ms > after,
+                self.modified_after_ms,
+            )
+            && modified(
+                |modified_ms, before| modified_ms < before,
+                self.modified_before_ms,
+            )
+    }
+}
+
+/// The options that `compilePattern` reads, which every search reads too
+pub(crate) const PATTERN_OPTIONS: &[&str] = &[
+    "caseInsensitive",
+    "smartCase",
+    "caseFoldMode",
+    "dotMatchesNewline",
+    "greedySwap",
+    "ignoreWhitespace",
+    "unicode",
+    "octal",
+    "crlf",
+    "wordBoundariesOnly",
+    "wholeLine",
+    "prefixAnchor",
+    "fixedStrings",
+    "pattern",
+    "normalizeForMatch",
+    "allowEmptyPattern",
+    "lineTerminatorMode",
+    "lineTerminator",
+    "nullData",
+    "multilineSearch",
+    "wordList",
+    "patterns",
+    "patternFile",
+    "patternFileSkipComments",
+];
+
+/// The options a search reads besides `PATTERN_OPTIONS`
+pub(crate) const SEARCH_OPTIONS: &[&str] = &[
+    "afterContext",
+    "beforeContext",
+    "invertMatch",
+    "includeLineNumbers",
+    "passthru",
+    "heapLimit",
+    "stopOnNonmatch",
+    "maxTotalMatches",
+    "maxFilesSearched",
+    "timeoutMs",
+    "firstMatchPerFile",
+    "fileNameGlob",
+    "customTypes",
+    "fileTypes",
+    "fileTypesNot",
+    "metadataFilter",
+    "unique",
+    "uniqueScope",
+    "binaryOutput",
+    "strictUtf8",
+    "invalidCharReplacement",
+    "contextMode",
+    "mergeAdjacent",
+    "maxMatchesPerGroup",
+    "contextSeparator",
+    "nullSeparated",
+    "includeFileMetadata",
+    "includeSiblings",
+    "matchPathsOnly",
+    "dryRun",
+    "extractMatches",
+    "trim",
+    "collapseWhitespace",
+    "keepLineTerminator",
+    "includeMatchRanges",
+    "includeMatchByteRange",
+    "includeIndent",
+    "tabWidth",
+    "maxMatchesPerLine",
+    "includeCaptures",
+    "highlight",
+    "contextChars",
+    "groupByFile",
+    "orderByFile",
+    "searchArchives",
+    "callbackThread",
+    "batchSize",
+    "flushIntervalMs",
+    "parallel",
+    "maxBytesPerFile",
+    "binaryDetection",
+    "countMode",
+    "matchStats",
+    "columnEncoding",
+    "outputEncoding",
+    "chunkSize",
+    "maxConcurrentFiles",
+    "multilineWindow",
+    "followSymlinks",
+    "reportLinkPath",
+    "respectGitignore",
+    "respectGlobalGitignore",
+    "forwardSlashPaths",
+    "absolutePaths",
+    "startLine",
+    "endLine",
+    "startByte",
+    "endByte",
+    "tailLines",
+    "lineFilter",
+    "jsonLines",
+    "lspMode",
+    "onError",
+    "onSkip",
+    "debugIgnores",
+    "onIgnore",
+    "onFileStart",
+    "onFileEnd",
+    "onProgress",
+    "progressIntervalMs",
+    "onWarning",
+    "onComplete",
+    "headingPattern",
+    "headerLines",
+    "excludePattern",
+    "skipFilePattern",
+    "requiredLiteral",
+    "subPattern",
+    "compiledPattern",
+    "searchContext",
+];
+
+/// Throws a `TypeError` for the first key of `obj` that isn't one of `known`, so that a misspelled option
+/// isn't silently ignored, suggesting the closest known one if it's only a couple of edits away.
+pub(crate) fn check_option_keys<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    known: &[&[&str]],
+) -> NeonResult<()> {
+    let keys = obj.get_own_property_names(cx)?.to_vec(cx)?;
+    for key in keys {
+        let key = match key.downcast::<JsString, _>(cx) {
+            Ok(key) => key.value(cx),
+            Err(_) => continue,
+        };
+        let mut options = known.iter().flat_map(|options| options.iter().copied());
+        if options.any(|option| option == key) {
+            continue;
+        }
+        let closest = known
+            .iter()
+            .flat_map(|options| options.iter().copied())
+            .map(|option| {
+                (
+                    edit_distance(&key.to_lowercase(), &option.to_lowercase()),
+                    option,
+                )
+            })
+            .filter(|&(distance, _)| distance <= 2)
+            .min();
+        return cx.throw_type_error(match closest {
+            Some((_, option)) => format!("unknown option {} (did you mean {}?)", key, option),
+            None => format!("unknown option {}", key),
+        });
+    }
+    Ok(())
+}
+
+/// Counts the characters that have to be inserted, removed, or replaced to turn `a` into `b`
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(a != b);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Gets an option from a JS object, or `None` if it's `undefined` or `null`.
+///
+/// Every other option getter goes through this, so that a value of the wrong type throws a `TypeError`
+/// naming the option and what it should be (`expected`, like `"a boolean"`).
+pub(crate) fn get_option<'a, V: Value>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+    expected: &str,
+) -> NeonResult<Option<Handle<'a, V>>> {
+    let value = obj.get(cx, key)?;
+    if value.is_a::<JsUndefined, _>(cx) || value.is_a::<JsNull, _>(cx) {
+        return Ok(None);
+    }
+    match value.downcast::<V, _>(cx) {
+        Ok(value) => Ok(Some(value)),
+        Err(_) => {
+            let actual = js_type_name(value, cx);
+            cx.throw_type_error(format!("{} must be {}, not {}", key, expected, actual))
+        }
+    }
+}
+
+/// Like `get_option`, but throws a `TypeError` naming the option if it's missing.
+pub(crate) fn get_required_option<'a, V: Value>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+    expected: &str,
+) -> NeonResult<Handle<'a, V>> {
+    match get_option(obj, cx, key, expected)? {
+        Some(value) => Ok(value),
+        None => cx.throw_type_error(format!("{} is required, and must be {}", key, expected)),
+    }
+}
+
+/// Describes a JS value's type, for errors about options of the wrong type.
+pub(crate) fn js_type_name<'a>(value: Handle<JsValue>, cx: &mut impl Context<'a>) -> &'static str {
+    if value.is_a::<JsBoolean, _>(cx) {
+        "a boolean"
+    } else if value.is_a::<JsNumber, _>(cx) {
+        "a number"
+    } else if value.is_a::<JsString, _>(cx) {
+        "a string"
+    } else if value.is_a::<JsUndefined, _>(cx) {
+        "undefined"
+    } else if value.is_a::<JsNull, _>(cx) {
+        "null"
+    } else if value.is_a::<JsArray, _>(cx) {
+        "an array"
+    } else if value.is_a::<JsFunction, _>(cx) {
+        "a function"
+    } else {
+        "an object"
+    }
+}
+
+pub(crate) fn get_possible_int_from_js_object<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+) -> NeonResult<Option<usize>> {
+    let number = get_option::<JsNumber>(obj, cx, key, "a number")?;
+    Ok(number.map(|number| number.value(cx) as usize))
+}
+
+/// Gets a number option, or `default` if it's missing.
+pub(crate) fn get_int_or_default<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+    default: usize,
+) -> NeonResult<usize> {
+    Ok(get_possible_int_from_js_object(obj, cx, key)?.unwrap_or(default))
+}
+
+/// Gets a boolean option, or `default` if it's missing.
+pub(crate) fn get_bool_or_default<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+    default: bool,
+) -> NeonResult<bool> {
+    Ok(get_possible_bool_from_js_object(obj, cx, key)?.unwrap_or(default))
+}
+
+pub(crate) fn get_possible_bool_from_js_object<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+) -> NeonResult<Option<bool>> {
+    let boolean = get_option::<JsBoolean>(obj, cx, key, "a boolean")?;
+    Ok(boolean.map(|boolean| boolean.value(cx)))
+}
+
+pub(crate) fn get_string_from_js_object<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+) -> NeonResult<String> {
+    let string = get_required_option::<JsString>(obj, cx, key, "a string")?;
+    Ok(string.value(cx))
+}
+
+pub(crate) fn get_possible_string_from_js_object<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+) -> NeonResult<Option<String>> {
+    let string = get_option::<JsString>(obj, cx, key, "a string")?;
+    Ok(string.map(|string| string.value(cx)))
+}
+
+/// Gets an option's bytes if it's a `Buffer`, or `None` if it's anything else, so that it can be parsed another way.
+pub(crate) fn get_possible_buffer_from_js_object<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+) -> NeonResult<Option<Vec<u8>>> {
+    let value = obj.get(cx, key)?;
+    Ok(match value.downcast::<JsBuffer, _>(cx) {
+        Ok(buffer) => Some(cx.borrow(&buffer, |data| data.as_slice::<u8>().to_vec())),
+        Err(_) => None,
+    })
+}
+
+pub(crate) fn get_possible_function_from_js_object<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+) -> NeonResult<Option<Handle<'a, JsFunction>>> {
+    get_option(obj, cx, key, "a function")
+}
+
+/// Gets an option that's one of several strings, as the value `variants` pairs it with.
+///
+/// Throws naming the option and every string it can be if it's anything else.
+pub(crate) fn get_possible_enum_from_js_object<'a, T: Copy>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+    variants: &[(&str, T)],
+) -> NeonResult<Option<T>> {
+    let value = match get_possible_string_from_js_object(obj, cx, key)? {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    match variants.iter().find(|(name, _)| *name == value) {
+        Some((_, variant)) => Ok(Some(*variant)),
+        None => {
+            let names = variants
+                .iter()
+                .map(|(name, _)| format!("\"{}\"", name))
+                .collect::<Vec<_>>();
+            let allowed = match names.split_last() {
+                Some((last, [])) => last.clone(),
+                Some((last, [first])) => format!("{} or {}", first, last),
+                Some((last, rest)) => format!("{}, or {}", rest.join(", "), last),
+                None => String::from("nothing"),
+            };
+            cx.throw_error(format!("{} must be {}, not \"{}\"", key, allowed, value))
+        }
+    }
+}
+
+/// Gets a string or an array of strings from a JS obj.
+///
+/// Returns an empty `Vec` if the key is missing.
+pub(crate) fn get_strings_from_js_object<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+) -> Result<Vec<String>, Throw> {
+    let item = obj.get(cx, key)?;
+    js_value_to_strings(item, cx, key)
+}
+
+/// Converts a JS string or array of strings to a `Vec`, which is empty if the value is `undefined`.
+///
+/// `name` is what the value is called in the `TypeError` thrown if it's anything else.
+pub(crate) fn js_value_to_strings<'a>(
+    item: Handle<JsValue>,
+    cx: &mut impl Context<'a>,
+    name: &str,
+) -> Result<Vec<String>, Throw> {
+    if item.is_a::<JsUndefined, _>(cx) {
+        return Ok(Vec::new());
+    }
+    if let Ok(string) = item.downcast::<JsString, _>(cx) {
+        return Ok(vec![string.value(cx)]);
+    }
+
+    let array = match item.downcast::<JsArray, _>(cx) {
+        Ok(array) => array.to_vec(cx)?,
+        Err(_) => {
+            let actual = js_type_name(item, cx);
+            return cx.throw_type_error(format!(
+                "{} must be a string or an array of strings, not {}",
+                name, actual
+            ));
+        }
+    };
+    array
+        .into_iter()
+        .map(|element| match element.downcast::<JsString, _>(cx) {
+            Ok(string) => Ok(string.value(cx)),
+            Err(_) => {
+                let actual = js_type_name(element, cx);
+                cx.throw_type_error(format!("{} can only contain strings, not {}", name, actual))
+            }
+        })
+        .collect()
+}
+
+/// Gets the paths to search from a search function's second argument, which is a string or an array of them.
+pub(crate) fn get_paths_argument(cx: &mut FunctionContext) -> NeonResult<Vec<String>> {
+    let path_argument = cx.argument::<JsValue>(1)?;
+    js_value_to_strings(path_argument, cx, "path")
+}
+
+/// Gets the files and directories to search from a search function's second argument, like `get_paths_argument`,
+/// along with whether each one is a file.
+///
+/// Every root is checked before any of them are searched, so that a mistyped path
+/// throws straight away with an error naming it.
+pub(crate) fn get_roots_argument(cx: &mut FunctionContext) -> NeonResult<Vec<(PathBuf, bool)>> {
+    let paths = get_paths_argument(cx)?;
+    paths
+        .into_iter()
+        .map(|path| match std::fs::metadata(&path) {
+            Ok(metadata) => Ok((PathBuf::from(path), metadata.is_file())),
+            Err(error) => {
+                let error = RipgrepjsError::InFile(PathBuf::from(path), Box::new(error.into()));
+                let js_error = error.to_js_error(cx, format!("Rust Error: {}", error))?;
+                cx.throw(js_error)
+            }
+        })
+        .collect()
+}
+
+/// Gets the blobs to search from `searchBlobs`'s second argument, an array of `{path: string, data: Buffer}`.
+///
+/// Their contents are copied, since the search reads them from other threads.
+pub(crate) fn get_blobs_argument(cx: &mut FunctionContext) -> NeonResult<Vec<Blob>> {
+    let blobs = cx.argument::<JsArray>(1)?.to_vec(cx)?;
+    blobs
+        .into_iter()
+        .map(|blob| {
+            let blob = match blob.downcast::<JsObject, _>(cx) {
+                Ok(blob) => blob,
+                Err(_) => return cx.throw_type_error("blobs can only contain objects"),
+            };
+            let path = get_string_from_js_object(blob, cx, "path")?;
+            let buffer = get_required_option::<JsBuffer>(blob, cx, "data", "a Buffer")?;
+            let data = cx.borrow(&buffer, |data| data.as_slice::<u8>().to_vec());
+            Ok(Blob {
+                path: PathBuf::from(path),
+                data,
+            })
+        })
+        .collect()
+}
+
+/// Parses the `lineTerminatorMode` option, along with `lineTerminator` for `"byte"` mode.
+pub(crate) fn get_line_terminator_mode<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<LineTerminatorMode> {
+    let mode = match get_possible_string_from_js_object(obj, cx, "lineTerminatorMode")? {
+        Some(mode) => mode,
+        None => return Ok(LineTerminatorMode::Lf),
+    };
+
+    // Not `get_possible_enum_from_js_object`, since `"byte"` needs another option
+    match mode.as_str() {
+        "lf" => Ok(LineTerminatorMode::Lf),
+        "crlf" => Ok(LineTerminatorMode::Crlf),
+        "byte" => Ok(LineTerminatorMode::Byte(get_line_terminator_byte(obj, cx)?)),
+        other => cx.throw_error(format!(
+            "lineTerminatorMode must be \"lf\", \"crlf\", or \"byte\", not \"{}\"",
+            other
+        )),
+    }
+}
+
+/// Parses the `lineTerminator` option for `lineTerminatorMode: "byte"`: a number from 0 to 255,
+/// or a string of exactly one byte (like `"\0"` or `";"`).
+///
+/// Anything else is thrown rather than cut down to one byte, which would quietly search with a different
+/// line terminator (like `"\r\n"` becoming `"\r"`).
+pub(crate) fn get_line_terminator_byte<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<u8> {
+    let value = obj.get(cx, "lineTerminator")?;
+    if let Ok(number) = value.downcast::<JsNumber, _>(cx) {
+        let number = number.value(cx);
+        // Also rules out NaN and infinities, whose fractional parts aren't 0
+        if number.fract() != 0.0 || !(0.0..=255.0).contains(&number) {
+            return cx.throw_error(format!(
+                "lineTerminator must be a byte, from 0 to 255, not {}",
+                number
+            ));
+        }
+        return Ok(number as u8);
+    }
+    if let Ok(string) = value.downcast::<JsString, _>(cx) {
+        let string = string.value(cx);
+        return match string.as_bytes() {
+            [byte] => Ok(*byte),
+            [] => cx.throw_error("lineTerminator must be a single byte, not an empty string"),
+            b"\r\n" => cx.throw_error(
+                "lineTerminator must be a single byte, not \"\\r\\n\"; use lineTerminatorMode \"crlf\" for CRLF line endings",
+            ),
+            bytes => cx.throw_error(format!(
+                "lineTerminator must be a single byte, not {:?}, which is {} bytes in UTF-8",
+                string,
+                bytes.len()
+            )),
+        };
+    }
+    if value.is_a::<JsUndefined, _>(cx) || value.is_a::<JsNull, _>(cx) {
+        return cx.throw_type_error(
+            "lineTerminator is required with lineTerminatorMode \"byte\", and must be a number or a one-byte string",
+        );
+    }
+    let actual = js_type_name(value, cx);
+    cx.throw_type_error(format!(
+        "lineTerminator must be a number or a one-byte string, not {}",
+        actual
+    ))
+}
+
+/// Parses the line terminator options, reconciling `crlf` with `lineTerminatorMode`,
+/// and making NUL the line terminator with `nullData`.
+pub(crate) fn get_line_terminator<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<LineTerminatorMode> {
+    // The matcher's `crlf` option and the searcher's line terminator must agree,
+    // or grep will refuse to search, so either of them being CRLF makes both CRLF.
+    let crlf = get_bool_or_default(obj, cx, "crlf", false)?;
+    if get_bool_or_default(obj, cx, "nullData", false)? {
+        if crlf || get_possible_string_from_js_object(obj, cx, "lineTerminatorMode")?.is_some() {
+            return cx.throw_error(
+                "the nullData option can't be used with crlf or lineTerminatorMode, since it sets the line terminator",
+            );
+        }
+        return Ok(LineTerminatorMode::Byte(b'\0'));
+    }
+    match get_line_terminator_mode(obj, cx)? {
+        LineTerminatorMode::Lf if crlf => Ok(LineTerminatorMode::Crlf),
+        LineTerminatorMode::Byte(_) if crlf => {
+            cx.throw_error("the crlf option can't be used with lineTerminatorMode \"byte\"")
+        }
+        mode => Ok(mode),
+    }
+}
+
+/// Parses the options that configure the matcher.
+///
+/// The line terminator and multiline mode are shared with the searcher, so they're passed in.
+pub(crate) fn get_matcher_options<'a, 'p>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    pattern: &'p str,
+    line_terminator: LineTerminatorMode,
+    multi_line: bool,
+) -> NeonResult<MatcherOptions<'p>> {
+    let normalize_for_match = get_bool_or_default(obj, cx, "normalizeForMatch", false)?;
+    if normalize_for_match && multi_line {
+        return cx.throw_error(
+            "the normalizeForMatch option can't be used with multilineSearch, since it normalizes each line on its own",
+        );
+    }
+    Ok(MatcherOptions {
+        case_insensitive: get_bool_or_default(obj, cx, "caseInsensitive", false)?,
+        smart_case: get_bool_or_default(obj, cx, "smartCase", false)?,
+        case_fold_mode: get_case_fold_mode(obj, cx)?,
+        multi_line,
+        dot_matches_new_line: get_bool_or_default(obj, cx, "dotMatchesNewline", false)?,
+        greedy_swap: get_bool_or_default(obj, cx, "greedySwap", false)?,
+        ignore_whitespace: get_bool_or_default(obj, cx, "ignoreWhitespace", false)?,
+        unicode: get_bool_or_default(obj, cx, "unicode", true)?,
+        octal: get_bool_or_default(obj, cx, "octal", false)?,
+        line_terminator: match line_terminator {
+            LineTerminatorMode::Byte(byte) => Some(byte),
+            _ => None,
+        },
+        crlf: line_terminator == LineTerminatorMode::Crlf,
+        word_boundaries_only: get_bool_or_default(obj, cx, "wordBoundariesOnly", false)?,
+        whole_line: get_bool_or_default(obj, cx, "wholeLine", false)?,
+        prefix_anchor: get_bool_or_default(obj, cx, "prefixAnchor", false)?,
+        fixed_strings: get_bool_or_default(obj, cx, "fixedStrings", false)?,
+        normalize_for_match,
+        pattern,
+        word_list: &[],
+        patterns: &[],
+        attribute_patterns: false,
+    })
+}
+
+/// Looks for a feature of PCRE (and other regex flavours) that Rust's regex engine doesn't have,
+/// to explain why a pattern that would work elsewhere doesn't compile.
+///
+/// With `octal`, `\1` is an octal escape rather than a backreference. Possessive quantifiers aren't looked for,
+/// since the likes of `a++` are valid (if pointless) repetitions of repetitions here.
+pub(crate) fn unsupported_feature(pattern: &str, octal: bool) -> Option<&'static str> {
+    const GROUPS: &[(&str, &str)] = &[
+        ("(?=", "lookahead `(?=...)`"),
+        ("(?!", "negative lookahead `(?!...)`"),
+        ("(?<=", "lookbehind `(?<=...)`"),
+        ("(?<!", "negative lookbehind `(?<!...)`"),
+        ("(?>", "an atomic group `(?>...)`"),
+    ];
+    let bytes = pattern.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'\\', Some(b'1'..=b'9')) if !octal => return Some("a backreference like `\\1`"),
+            (b'\\', Some(b'k')) if bytes.get(i + 2) == Some(&b'<') => {
+                return Some("a named backreference `\\k<name>`")
+            }
+            // Whatever's escaped can't start anything
+            (b'\\', _) => i += 1,
+            (b'(', _) => {
+                if let Some((_, feature)) = GROUPS
+                    .iter()
+                    .find(|(syntax, _)| pattern[i..].starts_with(syntax))
+                {
+                    return Some(feature);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Converts bytes to a pattern that matches exactly them, for a `pattern` given as a `Buffer`.
+///
+/// Unicode has to be off for `\xFF` to mean that byte rather than the UTF-8 encoding of U+00FF,
+/// and case-insensitivity is turned off too, since the bytes aren't necessarily text.
+pub(crate) fn byte_literal_pattern(bytes: &[u8]) -> String {
+    let mut pattern = String::from("(?-ui:");
+    for byte in bytes {
+        pattern.push_str(&format!("\\x{:02X}", byte));
+    }
+    pattern.push(')');
+    pattern
+}
+
+/// Reads the patterns in a `patternFile`, one per line, like ripgrep's `-f`.
+///
+/// With `skip_comments`, blank lines and lines starting with `#` are left out;
+/// otherwise a blank line is an empty pattern, which matches every line.
+pub(crate) fn read_pattern_file(
+    path: &Path,
+    skip_comments: bool,
+) -> Result<Vec<String>, RipgrepjsError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| RipgrepjsError::InFile(path.to_path_buf(), Box::new(RipgrepjsError::IO(e))))?;
+    Ok(contents
+        .lines()
+        .filter(|line| !skip_comments || !(line.trim().is_empty() || line.starts_with('#')))
+        .map(String::from)
+        .collect())
+}
+
+/// Parses the `unique` and `uniqueScope` options.
+pub(crate) fn get_unique_scope<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<Option<UniqueScope>> {
+    if !get_bool_or_default(obj, cx, "unique", false)? {
+        return Ok(None);
+    }
+
+    let scope = get_possible_enum_from_js_object(
+        obj,
+        cx,
+        "uniqueScope",
+        &[("file", UniqueScope::File), ("search", UniqueScope::Search)],
+    )?;
+    Ok(Some(scope.unwrap_or(UniqueScope::Search)))
+}
+
+/// Parses the `binaryDetection` option.
+pub(crate) fn get_binary_detection<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<BinaryDetection> {
+    let mode = get_possible_enum_from_js_object(
+        obj,
+        cx,
+        "binaryDetection",
+        &[
+            ("none", BinaryDetection::None),
+            ("quit", BinaryDetection::Quit),
+            ("convert", BinaryDetection::Convert),
+        ],
+    )?;
+    Ok(mode.unwrap_or(BinaryDetection::None))
+}
+
+/// Parses the `startLine`/`endLine`, `startByte`/`endByte`, and `tailLines` options, only one of which can be used.
+pub(crate) fn get_file_region<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<Option<FileRegion>> {
+    let get = |cx: &mut _, key| -> NeonResult<Option<u64>> {
+        Ok(get_possible_int_from_js_object(obj, cx, key)?.map(|n| n as u64))
+    };
+    let lines = (get(cx, "startLine")?, get(cx, "endLine")?);
+    let bytes = (get(cx, "startByte")?, get(cx, "endByte")?);
+    if let Some(tail_lines) = get(cx, "tailLines")? {
+        if lines != (None, None) || bytes != (None, None) {
+            return cx.throw_error(
+                "tailLines can't be used with startLine, endLine, startByte, or endByte",
+            );
+        }
+        return Ok(Some(FileRegion::Tail { lines: tail_lines }));
+    }
+    match (lines, bytes) {
+        ((None, None), (None, None)) => Ok(None),
+        ((start, end), (None, None)) => Ok(Some(FileRegion::Lines {
+            start: start.unwrap_or(1),
+            end,
+        })),
+        ((None, None), (start, end)) => Ok(Some(FileRegion::Bytes {
+            start: start.unwrap_or(0),
+            end,
+        })),
+        _ => cx.throw_error("startLine and endLine can't be used with startByte or endByte"),
+    }
+}
+
+/// Parses the `lineFilter` option, an array of line numbers.
+pub(crate) fn get_line_filter<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<Option<Arc<BTreeSet<u64>>>> {
+    let lines = match get_option::<JsArray>(obj, cx, "lineFilter", "an array of numbers")? {
+        Some(lines) => lines.to_vec(cx)?,
+        None => return Ok(None),
+    };
+    let lines = lines
+        .into_iter()
+        .map(|line| match line.downcast::<JsNumber, _>(cx) {
+            Ok(line) => Ok(line.value(cx) as u64),
+            Err(_) => {
+                let actual = js_type_name(line, cx);
+                cx.throw_type_error(format!(
+                    "lineFilter can only contain numbers, not {}",
+                    actual
+                ))
+            }
+        })
+        .collect::<NeonResult<BTreeSet<_>>>()?;
+    Ok(Some(Arc::new(lines)))
+}
+
+/// Parses `customTypes`, each `{name, globs}`, adding them to ripgrep's built-in types, and selects the types
+/// named in `fileTypes` and negates those in `fileTypesNot`, like `rg --type-add`, `--type`, and `--type-not`.
+/// A type added more than once (or with a built-in type's name) has every one's globs.
+///
+/// Returns `None` if there's no `fileTypes` or `fileTypesNot`.
+pub(crate) fn get_file_types<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<Option<Types>> {
+    let mut builder = TypesBuilder::new();
+    builder.add_defaults();
+    if let Some(types) = get_option::<JsArray>(obj, cx, "customTypes", "an array")? {
+        for file_type in types.to_vec(cx)? {
+            let file_type = match file_type.downcast::<JsObject, _>(cx) {
+                Ok(file_type) => file_type,
+                Err(_) => {
+                    let actual = js_type_name(file_type, cx);
+                    return cx.throw_type_error(format!(
+                        "customTypes can only contain {{name, globs}} objects, not {}",
+                        actual
+                    ));
+                }
+            };
+            let name = get_string_from_js_object(file_type, cx, "name")?;
+            for glob in get_strings_from_js_object(file_type, cx, "globs")? {
+                if builder.add(&name, &glob).is_err() {
+                    return cx.throw_type_error(format!(
+                        "customTypes has \"{}\", but type names can only have letters and numbers, and can't be \"all\"",
+                        name
+                    ));
+                }
+            }
+        }
+    }
+
+    let file_types = get_file_type_names(obj, cx, "fileTypes", &builder)?;
+    let file_types_not = get_file_type_names(obj, cx, "fileTypesNot", &builder)?;
+    if file_types.is_none() && file_types_not.is_none() {
+        return Ok(None);
+    }
+    for name in file_types.iter().flatten() {
+        builder.select(name);
+    }
+    // Negated after the selections, so that a type in both is left out
+    for name in file_types_not.iter().flatten() {
+        builder.negate(name);
+    }
+    match builder.build() {
+        Ok(types) => Ok(Some(types)),
+        Err(error) => cx.throw_error(format!("invalid file type: {}", error)),
+    }
+}
+
+/// Gets the names in `fileTypes` or `fileTypesNot`, checking that each is a built-in type or one of the `customTypes`,
+/// or `None` if it's missing.
+pub(crate) fn get_file_type_names<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+    builder: &TypesBuilder,
+) -> NeonResult<Option<Vec<String>>> {
+    let names = match get_option::<JsValue>(obj, cx, key, "a string or an array of strings")? {
+        Some(names) => js_value_to_strings(names, cx, key)?,
+        None => return Ok(None),
+    };
+    let definitions = builder.definitions();
+    for name in &names {
+        // `all` stands for every type, built-in or not
+        if name != "all" && !definitions.iter().any(|def| def.name() == name) {
+            return cx.throw_type_error(format!(
+                "{} has \"{}\", which isn't a built-in type or one of the customTypes",
+                key, name
+            ));
+        }
+    }
+    Ok(Some(names))
+}
+
+/// Parses the `metadataFilter` option, which has `minSize`, `maxSize`, `modifiedAfterMs`, and `modifiedBeforeMs`.
+pub(crate) fn get_metadata_filter<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<Option<MetadataFilter>> {
+    let filter = match get_option::<JsObject>(obj, cx, "metadataFilter", "an object")? {
+        Some(filter) => filter,
+        None => return Ok(None),
+    };
+    let mut get_ms = |key| -> NeonResult<Option<f64>> {
+        let ms = get_option::<JsNumber>(filter, cx, key, "a number")?;
+        Ok(ms.map(|ms| ms.value(cx)))
+    };
+    let modified_after_ms = get_ms("modifiedAfterMs")?;
+    let modified_before_ms = get_ms("modifiedBeforeMs")?;
+    Ok(Some(MetadataFilter {
+        min_size: get_possible_int_from_js_object(filter, cx, "minSize")?.map(|size| size as u64),
+        max_size: get_possible_int_from_js_object(filter, cx, "maxSize")?.map(|size| size as u64),
+        modified_after_ms,
+        modified_before_ms,
+    }))
+}
+
+/// Parses the `highlight` option, which has `before` and `after` strings.
+pub(crate) fn get_highlight<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<Option<(String, String)>> {
+    let highlight = match get_option::<JsObject>(obj, cx, "highlight", "an object")? {
+        Some(highlight) => highlight,
+        None => return Ok(None),
+    };
+    Ok(Some((
+        get_string_from_js_object(highlight, cx, "before")?,
+        get_string_from_js_object(highlight, cx, "after")?,
+    )))
+}
+
+/// Parses the `columnEncoding` option.
+pub(crate) fn get_column_encoding<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<Option<ColumnEncoding>> {
+    let mode = get_possible_enum_from_js_object(
+        obj,
+        cx,
+        "columnEncoding",
+        &[
+            ("utf8", ColumnEncoding::Utf8),
+            ("utf16", ColumnEncoding::Utf16),
+            ("char", ColumnEncoding::Char),
+        ],
+    )?;
+    Ok(mode)
+}
+
+/// Parses the `outputEncoding` option.
+pub(crate) fn get_output_encoding<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<Option<OutputEncoding>> {
+    let mode = get_possible_enum_from_js_object(
+        obj,
+        cx,
+        "outputEncoding",
+        &[
+            ("utf8", OutputEncoding::Utf8),
+            ("utf16le", OutputEncoding::Utf16Le),
+            ("utf16be", OutputEncoding::Utf16Be),
+        ],
+    )?;
+    Ok(mode)
+}
+
+pub(crate) fn get_case_fold_mode<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<CaseFoldMode> {
+    let mode = get_possible_enum_from_js_object(
+        obj,
+        cx,
+        "caseFoldMode",
+        &[
+            ("default", CaseFoldMode::Default),
+            ("ascii", CaseFoldMode::Ascii),
+            ("simple", CaseFoldMode::Simple),
+        ],
+    )?;
+    Ok(mode.unwrap_or(CaseFoldMode::Default))
+}
+
+/// Parses the `countMode` option.
+pub(crate) fn get_count_mode<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<Option<CountMode>> {
+    let mode = get_possible_enum_from_js_object(
+        obj,
+        cx,
+        "countMode",
+        &[("lines", CountMode::Lines), ("matches", CountMode::Matches)],
+    )?;
+    Ok(mode)
+}
+
+/// Parses the `binaryOutput` option.
+pub(crate) fn get_binary_output<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<BinaryOutput> {
+    let mode = get_possible_enum_from_js_object(
+        obj,
+        cx,
+        "binaryOutput",
+        &[
+            ("text", BinaryOutput::Text),
+            ("hex", BinaryOutput::Hex),
+            ("lossy", BinaryOutput::Lossy),
+        ],
+    )?;
+    Ok(mode.unwrap_or(BinaryOutput::Text))
+}
+
+/// Parses the `contextMode` option.
+pub(crate) fn get_context_mode<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<ContextMode> {
+    let mode = get_possible_enum_from_js_object(
+        obj,
+        cx,
+        "contextMode",
+        &[
+            ("none", ContextMode::None),
+            ("separate", ContextMode::Separate),
+            ("inline", ContextMode::Inline),
+        ],
+    )?;
+    Ok(mode.unwrap_or(ContextMode::Separate))
+}
+
+/// Parses the options that configure the searcher and the rest of the search.
+pub(crate) fn get_searcher_options<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<SearcherOptions> {
+    let line_terminator = get_line_terminator(obj, cx)?;
+    let respect_gitignore = get_bool_or_default(obj, cx, "respectGitignore", false)?;
+    let line_filter = get_line_filter(obj, cx)?;
+    let file_types = get_file_types(obj, cx)?;
+    let lsp_mode = get_bool_or_default(obj, cx, "lspMode", false)?;
+
+    // TODO: make this a macro?
+    Ok(SearcherOptions {
+        line_terminator,
+        after_context: get_int_or_default(obj, cx, "afterContext", 0)?,
+        before_context: get_int_or_default(obj, cx, "beforeContext", 0)?,
+        multiline_search: get_bool_or_default(obj, cx, "multilineSearch", false)?,
+        invert_match: get_bool_or_default(obj, cx, "invertMatch", false)?,
+        // Matches can't be filtered by line without knowing their line numbers
+        include_line_numbers: get_bool_or_default(obj, cx, "includeLineNumbers", true)?
+            || line_filter.is_some()
+            || lsp_mode,
+        passthru: get_bool_or_default(obj, cx, "passthru", false)?,
+        heap_limit: get_possible_int_from_js_object(obj, cx, "heapLimit")?,
+        stop_on_nonmatch: get_bool_or_default(obj, cx, "stopOnNonmatch", false)?,
+        max_total_matches: get_possible_int_from_js_object(obj, cx, "maxTotalMatches")?,
+        max_files_searched: get_possible_int_from_js_object(obj, cx, "maxFilesSearched")?,
+        first_match_per_file: get_bool_or_default(obj, cx, "firstMatchPerFile", false)?,
+        timeout: get_possible_int_from_js_object(obj, cx, "timeoutMs")?
+            .map(|ms| Duration::from_millis(ms as u64)),
+        report_progress: false,
+        report_ignores: false,
+        progress_interval: Duration::from_millis(
+            get_possible_int_from_js_object(obj, cx, "progressIntervalMs")?.unwrap_or(50) as u64,
+        ),
+        file_name_globs: get_strings_from_js_object(obj, cx, "fileNameGlob")?,
+        file_types,
+        metadata_filter: get_metadata_filter(obj, cx)?,
+        max_concurrent_files: get_possible_int_from_js_object(obj, cx, "maxConcurrentFiles")?
+            .map(|max| max.max(1)),
+        unique: get_unique_scope(obj, cx)?,
+        binary_output: get_binary_output(obj, cx)?,
+        strict_utf8: get_bool_or_default(obj, cx, "strictUtf8", false)?,
+        invalid_char_replacement: get_possible_string_from_js_object(
+            obj,
+            cx,
+            "invalidCharReplacement",
+        )?
+        .unwrap_or_else(|| char::REPLACEMENT_CHARACTER.to_string()),
+        // With `nullData`, every record ends in a NUL, so none of them would count as text
+        binary_detection: if get_bool_or_default(obj, cx, "nullData", false)? {
+            BinaryDetection::None
+        } else {
+            get_binary_detection(obj, cx)?
+        },
+        count_mode: get_count_mode(obj, cx)?,
+        match_stats: get_bool_or_default(obj, cx, "matchStats", false)?,
+        word_list: None,
+        pattern_matchers: None,
+        include_captures: get_bool_or_default(obj, cx, "includeCaptures", false)?,
+        capture_names: Arc::new(Vec::new()),
+        highlight: get_highlight(obj, cx)?,
+        context_chars: get_possible_int_from_js_object(obj, cx, "contextChars")?,
+        header_lines: get_possible_int_from_js_object(obj, cx, "headerLines")?
+            .filter(|&lines| lines > 0),
+        heading_matcher: get_secondary_matcher(obj, cx, "headingPattern", line_terminator)?,
+        exclude_matcher: get_secondary_matcher(obj, cx, "excludePattern", line_terminator)?,
+        skip_file_matcher: get_secondary_matcher(obj, cx, "skipFilePattern", line_terminator)?,
+        required_literal: get_possible_string_from_js_object(obj, cx, "requiredLiteral")?
+            .filter(|literal| !literal.is_empty())
+            .map(|literal| memchr::memmem::Finder::new(literal.as_bytes()).into_owned()),
+        sub_matcher: get_secondary_matcher(obj, cx, "subPattern", line_terminator)?,
+        group_by_file: get_bool_or_default(obj, cx, "groupByFile", false)?,
+        order_by_file: get_bool_or_default(obj, cx, "orderByFile", false)?,
+        search_archives: get_bool_or_default(obj, cx, "searchArchives", false)?,
+        merge_adjacent: get_bool_or_default(obj, cx, "mergeAdjacent", false)?,
+        max_matches_per_group: get_possible_int_from_js_object(obj, cx, "maxMatchesPerGroup")?,
+        raw_bytes: false,
+        count_candidates: false,
+        report_file_boundaries: false,
+        context_mode: get_context_mode(obj, cx)?,
+        context_separator: get_possible_string_from_js_object(obj, cx, "contextSeparator")?,
+        null_separated: get_bool_or_default(obj, cx, "nullSeparated", false)?,
+        include_file_metadata: get_bool_or_default(obj, cx, "includeFileMetadata", false)?,
+        include_siblings: get_bool_or_default(obj, cx, "includeSiblings", false)?,
+        match_paths_only: get_bool_or_default(obj, cx, "matchPathsOnly", false)?,
+        dry_run: get_bool_or_default(obj, cx, "dryRun", false)?,
+        extract_matches: get_bool_or_default(obj, cx, "extractMatches", false)?,
+        trim: get_bool_or_default(obj, cx, "trim", false)?,
+        collapse_whitespace: get_bool_or_default(obj, cx, "collapseWhitespace", false)?,
+        keep_line_terminator: get_bool_or_default(obj, cx, "keepLineTerminator", true)?,
+        include_match_ranges: get_bool_or_default(obj, cx, "includeMatchRanges", false)?,
+        include_match_byte_range: get_bool_or_default(obj, cx, "includeMatchByteRange", false)?,
+        include_indent: get_bool_or_default(obj, cx, "includeIndent", false)?,
+        tab_width: get_possible_int_from_js_object(obj, cx, "tabWidth")?
+            .unwrap_or(4)
+            .max(1),
+        max_matches_per_line: get_possible_int_from_js_object(obj, cx, "maxMatchesPerLine")?,
+        callback_thread: get_bool_or_default(obj, cx, "callbackThread", true)?,
+        batch_size: get_possible_int_from_js_object(obj, cx, "batchSize")?
+            .unwrap_or(CALLBACK_BATCH_SIZE)
+            .max(1),
+        flush_interval: get_possible_int_from_js_object(obj, cx, "flushIntervalMs")?
+            .map(|ms| Duration::from_millis(ms as u64)),
+        parallel: get_bool_or_default(obj, cx, "parallel", true)?,
+        max_bytes_per_file: get_possible_int_from_js_object(obj, cx, "maxBytesPerFile")?
+            .map(|bytes| bytes as u64),
+        column_encoding: get_column_encoding(obj, cx)?,
+        output_encoding: get_output_encoding(obj, cx)?,
+        follow_symlinks: get_bool_or_default(obj, cx, "followSymlinks", false)?,
+        forward_slash_paths: get_bool_or_default(obj, cx, "forwardSlashPaths", false)?,
+        absolute_paths: get_bool_or_default(obj, cx, "absolutePaths", false)?,
+        report_link_path: get_bool_or_default(obj, cx, "reportLinkPath", false)?,
+        respect_gitignore,
+        respect_global_gitignore: get_bool_or_default(
+            obj,
+            cx,
+            "respectGlobalGitignore",
+            respect_gitignore,
+        )?,
+        region: get_file_region(obj, cx)?,
+        line_filter,
+        chunk_size: get_possible_int_from_js_object(obj, cx, "chunkSize")?
+            .filter(|&bytes| bytes > 0)
+            .map(|bytes| bytes as u64),
+        json_lines: get_bool_or_default(obj, cx, "jsonLines", false)?,
+        lsp_mode,
+        multiline_window: get_possible_int_from_js_object(obj, cx, "multilineWindow")?
+            .map(|lines| lines.max(1) as u64),
+    })
+}
+
+/// Compiles a secondary pattern option (like `headingPattern`), if it's set.
+///
+/// It's compiled with the same options as the search pattern, except that it's never multiline
+/// and isn't restricted to word boundaries or whole lines.
+pub(crate) fn get_secondary_matcher<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    key: &str,
+    line_terminator: LineTerminatorMode,
+) -> NeonResult<Option<Arc<SearchMatcher>>> {
+    let pattern = match get_possible_string_from_js_object(obj, cx, key)? {
+        Some(pattern) => pattern,
+        None => return Ok(None),
+    };
+    let mut matcher_opts = get_matcher_options(obj, cx, &pattern, line_terminator, false)?;
+    matcher_opts.word_boundaries_only = false;
+    matcher_opts.whole_line = false;
+    matcher_opts.prefix_anchor = false;
+    matcher_opts.fixed_strings = false;
+    match matcher_opts.to_matcher() {
+        Ok(matcher) => Ok(Some(Arc::new(matcher))),
+        Err(e) => {
+            let js_error = e.to_js_error(cx, format!("Rust Error in {}: {}", key, e))?;
+            cx.throw(js_error)
+        }
+    }
+}
+
+/// Gets a matcher from the `compiledPattern` option, or else compiles one from the pattern options.
+///
+/// Also sets the searcher options' `word_list` and `pattern_matchers`, which go along with the matcher.
+pub(crate) fn get_matcher<'a>(
+    obj: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+    searcher_opts: &mut SearcherOptions,
+) -> NeonResult<Arc<SearchMatcher>> {
+    let compiled_pattern = obj.get(cx, "compiledPattern")?;
+    if compiled_pattern.is_a::<JsUndefined, _>(cx) {
+        match compile_pattern_from_options(obj, cx)? {
+            Ok(compiled_pattern) => {
+                searcher_opts.word_list = compiled_pattern.word_list;
+                searcher_opts.pattern_matchers = compiled_pattern.pattern_matchers;
+                searcher_opts.capture_names = compiled_pattern.capture_names;
+                Ok(compiled_pattern.matcher)
+            }
+            Err(e) => {
+                let js_error = e.to_js_error(cx, format!("Rust Error: {}", e))?;
+                cx.throw(js_error)
+            }
+        }
+    } else {
+        let compiled_pattern = match compiled_pattern.downcast::<JsBox<CompiledPattern>, _>(cx) {
+            Ok(compiled_pattern) => compiled_pattern,
+            Err(_) => {
+                return cx.throw_type_error(
+                    "compiledPattern must be a handle returned by compilePattern",
+                )
+            }
+        };
+        if compiled_pattern.line_terminator != searcher_opts.line_terminator
+            || compiled_pattern.multi_line != searcher_opts.multiline_search
+        {
+            return cx.throw_error(
+                "compiledPattern was compiled with different lineTerminatorMode, crlf, nullData, or multilineSearch options than this search uses",
+            );
+        }
+        searcher_opts.word_list = compiled_pattern.word_list.clone();
+        searcher_opts.pattern_matchers = compiled_pattern.pattern_matchers.clone();
+        searcher_opts.capture_names = Arc::clone(&compiled_pattern.capture_names);
+        Ok(Arc::clone(&compiled_pattern.matcher))
+    }
+}
@@ -0,0 +1,1129 @@
+//! What a search finds, and passing it on to JavaScript
+
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    path::{Path, PathBuf},
+    str::Utf8Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use neon::prelude::*;
+use serde_json::{json, Value as JsonValue};
+
+use crate::{
+    error::RipgrepjsError,
+    options::{BinaryOutput, OutputEncoding, SearcherOptions},
+    sink::{strip_line_terminator, JSCallbackSink},
+};
+
+/// Where a capture group matched within a result's lines, for `includeCaptures`
+#[derive(Clone)]
+pub(crate) struct CaptureSpan {
+    pub(crate) index: usize,
+    pub(crate) name: Option<String>,
+    // In bytes unless there's a `columnEncoding`, like `MatchRange`
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) text: String,
+}
+
+/// Where the `subPattern` matched within a result's lines
+#[derive(Clone)]
+pub(crate) struct SubMatch {
+    // In bytes unless there's a `columnEncoding`, like `MatchRange`
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) text: String,
+}
+
+/// Where the pattern matched within a result's lines, in bytes unless there's a `columnEncoding`
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MatchRange {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    // Which match on its line this is, counting from 0
+    pub(crate) match_index: usize,
+    // How many times the pattern matched on the line this match starts on
+    pub(crate) total_on_line: usize,
+}
+
+/// A position in a file as the Language Server Protocol has it: a zero-based line, and UTF-16 code units into it
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LspPosition {
+    pub(crate) line: u64,
+    pub(crate) character: usize,
+}
+
+impl LspPosition {
+    fn to_js_object<'a>(self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+        let js_position = cx.empty_object();
+        let js_line = cx.number(self.line as f64);
+        js_position.set(cx, "line", js_line)?;
+        let js_character = cx.number(self.character as f64);
+        js_position.set(cx, "character", js_character)?;
+        Ok(js_position)
+    }
+}
+
+/// Where one match is, as an LSP `Location`, for `lspMode`
+pub(crate) struct LspLocation {
+    // The file's `file://` URI, or `None` for text that isn't in a file
+    pub(crate) uri: Option<Arc<str>>,
+    pub(crate) start: LspPosition,
+    pub(crate) end: LspPosition,
+}
+
+impl LspLocation {
+    fn into_js_object<'a>(self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+        let js_location = cx.empty_object();
+        let js_uri: Handle<JsValue> = match &self.uri {
+            Some(uri) => cx.string(&**uri).upcast(),
+            None => cx.null().upcast(),
+        };
+        js_location.set(cx, "uri", js_uri)?;
+        let js_range = cx.empty_object();
+        let js_start = self.start.to_js_object(cx)?;
+        js_range.set(cx, "start", js_start)?;
+        let js_end = self.end.to_js_object(cx)?;
+        js_range.set(cx, "end", js_end)?;
+        js_location.set(cx, "range", js_range)?;
+        Ok(js_location)
+    }
+}
+
+/// Makes a path absolute by joining it onto the current directory, if it isn't already.
+///
+/// Unlike `std::fs::canonicalize`, this works for paths that don't exist, and keeps symbolic links.
+pub(crate) fn absolute_path(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    std::env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Converts a path to a `file://` URI, made absolute against the current directory if it's relative.
+///
+/// Everything but unreserved characters and separators is percent-encoded, as VS Code does.
+pub(crate) fn file_uri(path: &Path) -> String {
+    let path = absolute_path(path)
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/");
+    let mut uri = String::from("file://");
+    // Windows paths start with a drive letter rather than a slash
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for &byte in path.as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// Formats bytes like `hexdump -C`: an offset, 16 bytes of hex, then the printable ASCII characters.
+pub(crate) fn hex_dump(bytes: &[u8], start_offset: u64) -> String {
+    let mut dump = String::new();
+    for (row, chunk) in bytes.chunks(16).enumerate() {
+        if row > 0 {
+            dump.push('\n');
+        }
+        dump.push_str(&format!("{:08x} ", start_offset + row as u64 * 16));
+        for column in 0..16 {
+            if column == 8 {
+                dump.push(' ');
+            }
+            match chunk.get(column) {
+                Some(byte) => dump.push_str(&format!(" {:02x}", byte)),
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str("  |");
+        for &byte in chunk {
+            dump.push(if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            });
+        }
+        dump.push('|');
+    }
+    dump
+}
+
+/// Like `String::from_utf8_lossy`, but with a configurable replacement for each invalid sequence.
+pub(crate) fn to_lossy_string(mut bytes: &[u8], replacement: &str) -> String {
+    let mut string = String::with_capacity(bytes.len());
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                string.push_str(valid);
+                return string;
+            }
+            Err(error) => {
+                let (valid, rest) = bytes.split_at(error.valid_up_to());
+                // `from_utf8` just checked that these bytes are valid, so this can't fail
+                string.push_str(std::str::from_utf8(valid).unwrap());
+                string.push_str(replacement);
+                // A sequence cut off at the end of the input has no length, but is invalid all the same
+                bytes = &rest[error.error_len().unwrap_or(rest.len())..];
+            }
+        }
+    }
+}
+
+/// Metadata about a searched file, for `includeFileMetadata`
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FileMetadata {
+    pub(crate) size: u64,
+    // Milliseconds since the Unix epoch, as JavaScript's `Date` would have it
+    pub(crate) modified_ms: Option<f64>,
+}
+
+impl FileMetadata {
+    pub(crate) fn new(metadata: &std::fs::Metadata) -> Self {
+        let modified_ms = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs_f64() * 1000.0);
+        Self {
+            size: metadata.len(),
+            modified_ms,
+        }
+    }
+}
+
+/// A match on its way to the JavaScript callback
+#[derive(Clone)]
+pub(crate) struct MatchResult {
+    // The order in which results were sent, across every thread in the search
+    pub(crate) seq: u64,
+    pub(crate) path: Option<Arc<str>>,
+    pub(crate) line_number: Option<u64>,
+    pub(crate) lines: Vec<Result<String, Utf8Error>>,
+    hex_dump: Option<String>,
+    // The matched bytes and where they start in the file, instead of `lines`, for `searchRaw`
+    raw: Option<(Vec<u8>, u64)>,
+    // Context lines, only reported with `contextMode: "inline"`
+    pub(crate) before: Option<Vec<String>>,
+    pub(crate) after: Option<Vec<String>>,
+    file_metadata: Option<FileMetadata>,
+    // Set if this is a context line rather than a match, with `contextMode: "separate"`,
+    // or to `"separator"` for a `contextSeparator`
+    pub(crate) context_kind: Option<&'static str>,
+    // With `passthru`, whether the lines are a match rather than passed through
+    pub(crate) matched: Option<bool>,
+    // Only found for matches, with `includeMatchRanges`; the flag is set if `maxMatchesPerLine` was hit
+    pub(crate) match_ranges: Option<(Vec<MatchRange>, bool)>,
+    // The first and last lines of a match, with `multilineSearch`
+    pub(crate) line_span: Option<(u64, u64)>,
+    // Where the first match starts and ends in the file, in bytes, for `includeMatchByteRange`
+    pub(crate) match_byte_range: Option<(u64, u64)>,
+    // The width of the first line's indentation, for `includeIndent`
+    pub(crate) indent: Option<usize>,
+    // The `wordList` term of the first match in the lines
+    pub(crate) matched_term: Option<String>,
+    // The indices of the `patterns` that match the lines
+    pub(crate) pattern_indices: Option<Vec<usize>>,
+    // The closest line before the match that matched `headingPattern`
+    pub(crate) heading: Option<String>,
+    // The first lines of the match's file, for `headerLines`
+    pub(crate) header_lines: Option<Arc<Vec<String>>>,
+    // The other files in the match's file's directory, for `includeSiblings`
+    pub(crate) siblings: Option<Arc<Vec<String>>>,
+    // The matched lines with `highlight`'s strings around each match
+    pub(crate) highlighted_line: Option<String>,
+    // The first match with up to `contextChars` characters either side of it
+    pub(crate) snippet: Option<String>,
+    // Where the first match starts in the first line, counting from 1, for `columnEncoding`
+    pub(crate) column: Option<usize>,
+    // Each of the pattern's matches, with a span for each capture group that took part, for `includeCaptures`
+    pub(crate) captures: Option<Vec<Vec<Option<CaptureSpan>>>>,
+    // Every match of `subPattern` within the lines
+    pub(crate) sub_matches: Option<Vec<SubMatch>>,
+    // The encoding to report `lines` in, if they aren't reported as strings
+    output_encoding: Option<OutputEncoding>,
+    // The matches in these lines, if they're a span of overlapping context windows, for `mergeAdjacent`;
+    // the flag is set if `maxMatchesPerGroup` was hit
+    merged_matches: Option<(Vec<MatchResult>, bool)>,
+}
+
+impl MatchResult {
+    /// Converts some matched (or context) lines to strings, falling back to a hex dump if configured.
+    ///
+    /// For `searchRaw`, the bytes are kept as they are instead.
+    pub(crate) fn new<'l>(
+        lines: impl Iterator<Item = &'l [u8]>,
+        bytes: &[u8],
+        absolute_byte_offset: u64,
+        line_number: Option<u64>,
+        sink: &JSCallbackSink,
+    ) -> Self {
+        // TODO: perf improvements possible here?
+        let line_terminator = sink.options.line_terminator.to_line_terminator();
+        // Left as bytes for JavaScript to decode, for `searchRaw`
+        let raw = if sink.options.raw_bytes {
+            let bytes = if sink.options.keep_line_terminator {
+                bytes
+            } else {
+                strip_line_terminator(line_terminator, bytes)
+            };
+            Some((bytes.to_vec(), absolute_byte_offset + sink.chunk_start))
+        } else {
+            None
+        };
+        let lines = if raw.is_some() {
+            Vec::new()
+        } else {
+            lines
+                .map(|line| {
+                    if sink.options.keep_line_terminator {
+                        line
+                    } else {
+                        strip_line_terminator(line_terminator, line)
+                    }
+                })
+                .map(|line| match std::str::from_utf8(line) {
+                    Ok(s) => Ok(sink.options.tidy_line(s.to_string())),
+                    Err(_) if sink.options.binary_output == BinaryOutput::Lossy => {
+                        let s = to_lossy_string(line, &sink.options.invalid_char_replacement);
+                        Ok(sink.options.tidy_line(s))
+                    }
+                    Err(e) => Err(e),
+                })
+                .collect::<Vec<_>>()
+        };
+        let hex_dump = match sink.options.binary_output {
+            BinaryOutput::Hex if lines.iter().any(Result::is_err) => {
+                Some(hex_dump(bytes, absolute_byte_offset + sink.chunk_start))
+            }
+            _ => None,
+        };
+
+        Self {
+            hex_dump,
+            raw,
+            matched: if sink.options.passthru {
+                Some(true)
+            } else {
+                None
+            },
+            ..Self::with_lines(
+                lines,
+                line_number.map(|line_number| line_number + sink.line_number_offset),
+                sink,
+            )
+        }
+    }
+
+    /// A result marking a gap between groups of context lines, for `contextSeparator`
+    pub(crate) fn separator(separator: &str, sink: &JSCallbackSink) -> Self {
+        Self {
+            context_kind: Some("separator"),
+            ..Self::with_lines(vec![Ok(separator.to_string())], None, sink)
+        }
+    }
+
+    /// A span of overlapping context windows and the matches in it, for `mergeAdjacent`
+    pub(crate) fn merged(
+        lines: Vec<String>,
+        line_number: Option<u64>,
+        matches: Vec<MatchResult>,
+        truncated: bool,
+        sink: &JSCallbackSink,
+    ) -> Self {
+        Self {
+            merged_matches: Some((matches, truncated)),
+            ..Self::with_lines(lines.into_iter().map(Ok).collect(), line_number, sink)
+        }
+    }
+
+    /// A result of just some lines from the sink's current file, which every other constructor starts from.
+    ///
+    /// Everything that's only reported for some results is left unset, for the caller (or `JSCallbackSink`) to fill in.
+    fn with_lines(
+        lines: Vec<Result<String, Utf8Error>>,
+        line_number: Option<u64>,
+        sink: &JSCallbackSink,
+    ) -> Self {
+        Self {
+            seq: 0, // assigned when sent
+            path: sink.path.clone(),
+            line_number,
+            lines,
+            hex_dump: None,
+            raw: None,
+            before: None,
+            after: None,
+            file_metadata: sink.file_metadata,
+            context_kind: None,
+            matched: None,
+            match_ranges: None,
+            line_span: None,
+            match_byte_range: None,
+            indent: None,
+            matched_term: None,
+            pattern_indices: None,
+            heading: None,
+            header_lines: None,
+            siblings: None,
+            highlighted_line: None,
+            snippet: None,
+            column: None,
+            captures: None,
+            sub_matches: None,
+            output_encoding: sink.options.output_encoding,
+            merged_matches: None,
+        }
+    }
+
+    pub(crate) fn into_js_object<'c>(self, cx: &mut impl Context<'c>) -> JsResult<'c, JsObject> {
+        let js_match_object = cx.empty_object();
+
+        let js_seq = cx.number(self.seq as f64);
+        js_match_object.set(cx, "seq", js_seq)?;
+
+        if let Some(path) = self.path {
+            let js_path = cx.string(&*path);
+            js_match_object.set(cx, "path", js_path)?;
+        }
+
+        if let Some(context_kind) = self.context_kind {
+            let js_context_kind = cx.string(context_kind);
+            js_match_object.set(cx, "contextKind", js_context_kind)?;
+        }
+
+        if let Some(matched) = self.matched {
+            let js_matched = cx.boolean(matched);
+            js_match_object.set(cx, "matched", js_matched)?;
+        }
+
+        if let Some(line_num) = self.line_number {
+            let js_line_num = cx.number(line_num as f64);
+            js_match_object.set(cx, "lineNumber", js_line_num)?;
+        }
+
+        if let Some((start, end)) = self.match_byte_range {
+            let js_range = cx.empty_object();
+            let js_start = cx.number(start as f64);
+            js_range.set(cx, "start", js_start)?;
+            let js_end = cx.number(end as f64);
+            js_range.set(cx, "end", js_end)?;
+            js_match_object.set(cx, "matchByteRange", js_range)?;
+        }
+
+        if let Some(indent) = self.indent {
+            let js_indent = cx.number(indent as f64);
+            js_match_object.set(cx, "indent", js_indent)?;
+        }
+
+        if let Some((start_line, end_line)) = self.line_span {
+            let js_start_line = cx.number(start_line as f64);
+            js_match_object.set(cx, "startLine", js_start_line)?;
+            let js_end_line = cx.number(end_line as f64);
+            js_match_object.set(cx, "endLine", js_end_line)?;
+        }
+
+        if let Some((bytes, byte_offset)) = self.raw {
+            let js_bytes = JsBuffer::external(cx, bytes);
+            js_match_object.set(cx, "bytes", js_bytes)?;
+            let js_byte_offset = cx.number(byte_offset as f64);
+            js_match_object.set(cx, "byteOffset", js_byte_offset)?;
+        } else if let Some(hex_dump) = self.hex_dump {
+            let js_hex_dump = cx.string(hex_dump);
+            js_match_object.set(cx, "hexDump", js_hex_dump)?;
+        } else {
+            let js_lines = cx.empty_array();
+            for (idx, line) in self.lines.into_iter().enumerate() {
+                let line = match line {
+                    Ok(s) => s,
+                    Err(e) => cx.throw_error(format!(
+                        "Error converting byte sequence to a string using UTF-8: {}",
+                        e
+                    ))?,
+                };
+                let js_line: Handle<JsValue> = match self.output_encoding {
+                    Some(encoding) => JsBuffer::external(cx, encoding.encode(&line)).upcast(),
+                    None => cx.string(line).upcast(),
+                };
+                js_lines.set(cx, idx as u32, js_line)?;
+            }
+            let key = match self.output_encoding {
+                Some(_) => "encodedLines",
+                None => "matchedLines",
+            };
+            js_match_object.set(cx, key, js_lines)?;
+        }
+
+        if let Some(matched_term) = self.matched_term {
+            let js_matched_term = cx.string(matched_term);
+            js_match_object.set(cx, "matchedTerm", js_matched_term)?;
+        }
+
+        if let Some(pattern_indices) = self.pattern_indices {
+            if let Some(&first) = pattern_indices.first() {
+                let js_pattern_index = cx.number(first as f64);
+                js_match_object.set(cx, "patternIndex", js_pattern_index)?;
+            }
+            let js_pattern_indices = cx.empty_array();
+            for (idx, pattern_index) in pattern_indices.into_iter().enumerate() {
+                let js_pattern_index = cx.number(pattern_index as f64);
+                js_pattern_indices.set(cx, idx as u32, js_pattern_index)?;
+            }
+            js_match_object.set(cx, "patternIndices", js_pattern_indices)?;
+        }
+
+        if let Some(column) = self.column {
+            let js_column = cx.number(column as f64);
+            js_match_object.set(cx, "column", js_column)?;
+        }
+
+        if let Some(highlighted_line) = self.highlighted_line {
+            let js_highlighted_line = cx.string(highlighted_line);
+            js_match_object.set(cx, "highlightedLine", js_highlighted_line)?;
+        }
+
+        if let Some(snippet) = self.snippet {
+            let js_snippet = cx.string(snippet);
+            js_match_object.set(cx, "snippet", js_snippet)?;
+        }
+
+        if let Some(heading) = self.heading {
+            let js_heading = cx.string(heading);
+            js_match_object.set(cx, "heading", js_heading)?;
+        }
+
+        if let Some(header_lines) = self.header_lines {
+            let js_header_lines = cx.empty_array();
+            for (idx, line) in header_lines.iter().enumerate() {
+                let js_line = cx.string(line);
+                js_header_lines.set(cx, idx as u32, js_line)?;
+            }
+            js_match_object.set(cx, "headerLines", js_header_lines)?;
+        }
+
+        if let Some(siblings) = self.siblings {
+            let js_siblings = cx.empty_array();
+            for (idx, sibling) in siblings.iter().enumerate() {
+                let js_sibling = cx.string(sibling);
+                js_siblings.set(cx, idx as u32, js_sibling)?;
+            }
+            js_match_object.set(cx, "siblings", js_siblings)?;
+        }
+
+        if let Some((ranges, truncated)) = self.match_ranges {
+            let js_ranges = cx.empty_array();
+            for (idx, range) in ranges.into_iter().enumerate() {
+                let js_range = cx.empty_object();
+                let js_start = cx.number(range.start as f64);
+                js_range.set(cx, "start", js_start)?;
+                let js_end = cx.number(range.end as f64);
+                js_range.set(cx, "end", js_end)?;
+                let js_match_index = cx.number(range.match_index as f64);
+                js_range.set(cx, "matchIndex", js_match_index)?;
+                let js_total = cx.number(range.total_on_line as f64);
+                js_range.set(cx, "totalMatchesOnLine", js_total)?;
+                js_ranges.set(cx, idx as u32, js_range)?;
+            }
+            js_match_object.set(cx, "matchRanges", js_ranges)?;
+            let js_truncated = cx.boolean(truncated);
+            js_match_object.set(cx, "matchRangesTruncated", js_truncated)?;
+        }
+
+        if let Some(captures) = self.captures {
+            let js_captures = cx.empty_array();
+            for (idx, groups) in captures.into_iter().enumerate() {
+                let js_groups = cx.empty_array();
+                for (group_idx, span) in groups.into_iter().enumerate() {
+                    let js_span = match span {
+                        Some(span) => {
+                            let js_span = cx.empty_object();
+                            let js_index = cx.number(span.index as f64);
+                            js_span.set(cx, "index", js_index)?;
+                            if let Some(name) = span.name {
+                                let js_name = cx.string(name);
+                                js_span.set(cx, "name", js_name)?;
+                            }
+                            let js_start = cx.number(span.start as f64);
+                            js_span.set(cx, "start", js_start)?;
+                            let js_end = cx.number(span.end as f64);
+                            js_span.set(cx, "end", js_end)?;
+                            let js_text = cx.string(span.text);
+                            js_span.set(cx, "text", js_text)?;
+                            js_span.upcast::<JsValue>()
+                        }
+                        None => cx.null().upcast(),
+                    };
+                    js_groups.set(cx, group_idx as u32, js_span)?;
+                }
+                js_captures.set(cx, idx as u32, js_groups)?;
+            }
+            js_match_object.set(cx, "captures", js_captures)?;
+        }
+
+        if let Some(sub_matches) = self.sub_matches {
+            let js_sub_matches = cx.empty_array();
+            for (idx, sub_match) in sub_matches.into_iter().enumerate() {
+                let js_sub_match = cx.empty_object();
+                let js_start = cx.number(sub_match.start as f64);
+                js_sub_match.set(cx, "start", js_start)?;
+                let js_end = cx.number(sub_match.end as f64);
+                js_sub_match.set(cx, "end", js_end)?;
+                let js_text = cx.string(sub_match.text);
+                js_sub_match.set(cx, "text", js_text)?;
+                js_sub_matches.set(cx, idx as u32, js_sub_match)?;
+            }
+            js_match_object.set(cx, "subMatches", js_sub_matches)?;
+        }
+
+        if let Some((merged_matches, truncated)) = self.merged_matches {
+            let js_matches = cx.empty_array();
+            for (idx, mut result) in merged_matches.into_iter().enumerate() {
+                result.seq = self.seq;
+                let js_match = result.into_js_object(cx)?;
+                js_matches.set(cx, idx as u32, js_match)?;
+            }
+            js_match_object.set(cx, "matches", js_matches)?;
+            let js_truncated = cx.boolean(truncated);
+            js_match_object.set(cx, "matchesTruncated", js_truncated)?;
+        }
+
+        if let Some(before) = self.before {
+            let js_before = strings_to_js_array(cx, before)?;
+            js_match_object.set(cx, "before", js_before)?;
+        }
+        if let Some(after) = self.after {
+            let js_after = strings_to_js_array(cx, after)?;
+            js_match_object.set(cx, "after", js_after)?;
+        }
+
+        if let Some(metadata) = self.file_metadata {
+            let js_size = cx.number(metadata.size as f64);
+            js_match_object.set(cx, "fileSize", js_size)?;
+            if let Some(modified_ms) = metadata.modified_ms {
+                let js_modified = cx.number(modified_ms);
+                js_match_object.set(cx, "modifiedMs", js_modified)?;
+            }
+        }
+
+        Ok(js_match_object)
+    }
+}
+
+pub(crate) fn strings_to_js_array<'c>(
+    cx: &mut impl Context<'c>,
+    strings: Vec<String>,
+) -> JsResult<'c, JsArray> {
+    let js_array = cx.empty_array();
+    for (idx, string) in strings.into_iter().enumerate() {
+        let js_string = cx.string(string);
+        js_array.set(cx, idx as u32, js_string)?;
+    }
+    Ok(js_array)
+}
+
+/// Something on its way to one of the JavaScript callbacks
+// Nearly everything sent is a match, so boxing them would just cost an allocation each
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Delivery {
+    Match(MatchResult),
+    /// A matching path and its `seq`, for `matchPathsOnly` (or a path that would be searched, for `dryRun`)
+    Path(u64, String),
+    /// An error that only affected one file, for `onError`
+    Error(RipgrepjsError),
+    /// A file that wasn't searched (or not all the way), and why, for `onSkip`
+    Skip(PathBuf, &'static str),
+    /// A file left out by its name (or a file or directory left out by a gitignore rule), where it was left out
+    /// from (an option, or the gitignore file with the rule), and the glob or rule it matched (if any), for `onIgnore`
+    Ignore(PathBuf, Cow<'static, str>, Option<String>),
+    /// Every result from one file and the group's `seq`, for `groupByFile`
+    Group(u64, Option<Arc<str>>, Vec<MatchResult>),
+    /// The path of a file that's about to be searched, for `onFileStart`
+    FileStart(Arc<str>),
+    /// The path of a file that's been searched, and how many matches it had, for `onFileEnd`
+    FileEnd(Arc<str>, u64),
+    /// How far the search has got, for `onProgress`
+    Progress(SearchProgress),
+    /// One message in the format of `rg --json`, for `jsonLines`
+    JsonLine(String),
+    /// Where one match is, for `lspMode`
+    Location(LspLocation),
+}
+
+impl Delivery {
+    /// Whether this goes to the `onMatch` callback (rather than `onError` or `onSkip`)
+    pub(crate) fn is_result(&self) -> bool {
+        matches!(
+            self,
+            Delivery::Match(_)
+                | Delivery::Path(..)
+                | Delivery::Group(..)
+                | Delivery::JsonLine(_)
+                | Delivery::Location(_)
+        )
+    }
+
+    /// Converts a result for the `onMatch` callback to its JavaScript value,
+    /// which is an object unless it's a `jsonLines` message.
+    ///
+    /// Returns `None` for deliveries that go to other callbacks.
+    pub(crate) fn into_js_result<'c>(
+        self,
+        cx: &mut impl Context<'c>,
+    ) -> NeonResult<Option<Handle<'c, JsValue>>> {
+        Ok(Some(match self {
+            Delivery::Match(result) => result.into_js_object(cx)?.upcast(),
+            Delivery::JsonLine(line) => cx.string(line).upcast(),
+            Delivery::Location(location) => location.into_js_object(cx)?.upcast(),
+            Delivery::Path(seq, path) => {
+                let js_result = cx.empty_object();
+                let js_seq = cx.number(seq as f64);
+                js_result.set(cx, "seq", js_seq)?;
+                let js_path = cx.string(path);
+                js_result.set(cx, "path", js_path)?;
+                js_result.upcast()
+            }
+            Delivery::Group(seq, path, results) => {
+                let js_group = cx.empty_object();
+                let js_seq = cx.number(seq as f64);
+                js_group.set(cx, "seq", js_seq)?;
+                if let Some(path) = path {
+                    let js_path = cx.string(&*path);
+                    js_group.set(cx, "path", js_path)?;
+                }
+                let js_matches = cx.empty_array();
+                for (idx, result) in results.into_iter().enumerate() {
+                    let js_match = result.into_js_object(cx)?;
+                    js_matches.set(cx, idx as u32, js_match)?;
+                }
+                js_group.set(cx, "matches", js_matches)?;
+                js_group.upcast()
+            }
+            Delivery::Error(_)
+            | Delivery::Skip(..)
+            | Delivery::Ignore(..)
+            | Delivery::FileStart(_)
+            | Delivery::FileEnd(..)
+            | Delivery::Progress(_) => return Ok(None),
+        }))
+    }
+
+    /// Passes this to the appropriate callback. Has to be run on the JavaScript thread.
+    fn deliver<'c>(self, cx: &mut impl Context<'c>, callbacks: &Callbacks) -> NeonResult<()> {
+        let (callback, argument) = match self {
+            Delivery::Match(_)
+            | Delivery::Path(..)
+            | Delivery::Group(..)
+            | Delivery::JsonLine(_)
+            | Delivery::Location(_) => {
+                let js_result = self
+                    .into_js_result(cx)?
+                    .expect("results convert to JavaScript values");
+                (&callbacks.on_match, js_result)
+            }
+            Delivery::Error(error) => match &callbacks.on_error {
+                Some(on_error) => (on_error, error.to_js_error(cx, error.to_string())?.upcast()),
+                None => return Ok(()),
+            },
+            Delivery::Skip(path, reason) => {
+                if let Some(on_skip) = &callbacks.on_skip {
+                    let js_path = cx.string(path.to_string_lossy());
+                    let js_reason = cx.string(reason);
+                    let null = cx.null();
+                    on_skip
+                        .to_inner(cx)
+                        .call(cx, null, vec![js_path, js_reason])?;
+                }
+                return Ok(());
+            }
+            Delivery::Ignore(path, source, rule) => {
+                if let Some(on_ignore) = &callbacks.on_ignore {
+                    let js_path: Handle<JsValue> = cx.string(path.to_string_lossy()).upcast();
+                    let js_rule = match rule {
+                        Some(rule) => cx.string(rule).upcast(),
+                        None => cx.null().upcast(),
+                    };
+                    let js_source = cx.string(&*source).upcast();
+                    let null = cx.null();
+                    on_ignore
+                        .to_inner(cx)
+                        .call(cx, null, vec![js_path, js_rule, js_source])?;
+                }
+                return Ok(());
+            }
+            Delivery::FileStart(path) => match &callbacks.on_file_start {
+                Some(on_file_start) => (on_file_start, cx.string(&*path).upcast()),
+                None => return Ok(()),
+            },
+            Delivery::FileEnd(path, matches) => {
+                if let Some(on_file_end) = &callbacks.on_file_end {
+                    let js_path: Handle<JsValue> = cx.string(&*path).upcast();
+                    let js_matches = cx.number(matches as f64).upcast();
+                    let null = cx.null();
+                    on_file_end
+                        .to_inner(cx)
+                        .call(cx, null, vec![js_path, js_matches])?;
+                }
+                return Ok(());
+            }
+            Delivery::Progress(progress) => match &callbacks.on_progress {
+                Some(on_progress) => (on_progress, progress.to_js_object(cx)?.upcast()),
+                None => return Ok(()),
+            },
+        };
+
+        let null = cx.null();
+        callback
+            .to_inner(cx)
+            .call(cx, null, vec![argument as Handle<JsValue>])?;
+        Ok(())
+    }
+}
+
+/// The JavaScript callbacks for a search
+#[derive(Clone)]
+pub(crate) struct Callbacks {
+    pub(crate) on_match: Arc<Root<JsFunction>>,
+    // Called with errors that only affect one file, and so don't stop the search
+    pub(crate) on_error: Option<Arc<Root<JsFunction>>>,
+    // Called with files that were left out of the search
+    pub(crate) on_skip: Option<Arc<Root<JsFunction>>>,
+    // Called with files that were left out by their names, and why, with `debugIgnores`
+    pub(crate) on_ignore: Option<Arc<Root<JsFunction>>>,
+    // Called with each file's path before it's searched
+    pub(crate) on_file_start: Option<Arc<Root<JsFunction>>>,
+    // Called with each file's path and number of matches after it's been searched
+    pub(crate) on_file_end: Option<Arc<Root<JsFunction>>>,
+    // Called every so often with how far the search has got
+    pub(crate) on_progress: Option<Arc<Root<JsFunction>>>,
+}
+
+/// How results get from the searching threads to the JavaScript thread
+#[derive(Clone)]
+pub(crate) enum ResultSender {
+    /// Each result is queued on the JavaScript thread by the thread that found it
+    Direct(Channel, Callbacks),
+    /// Results go to the callback thread, which queues them on the JavaScript thread in batches
+    CallbackThread(mpsc::Sender<Delivery>),
+    /// Results are held back to be sent later, for searching a file in chunks
+    Buffer(Arc<Mutex<Vec<Delivery>>>),
+    /// Results wait in a bounded queue until they're pulled from an `openSearch` handle,
+    /// blocking the search while it's full. The flag is set once the handle is closed.
+    Bounded(mpsc::SyncSender<Delivery>, Arc<AtomicBool>),
+}
+
+impl ResultSender {
+    /// Sends a result on its way.
+    ///
+    /// Returns `false` once nothing will receive results any more, so the search may as well stop.
+    pub(crate) fn send(&self, delivery: Delivery) -> bool {
+        match self {
+            ResultSender::Direct(channel, callbacks) => {
+                let callbacks = callbacks.clone();
+                channel.send(move |mut context| delivery.deliver(&mut context, &callbacks));
+            }
+            ResultSender::CallbackThread(sender) => {
+                // The callback thread only stops once every sender has been dropped, so this can't fail
+                let _ = sender.send(delivery);
+            }
+            ResultSender::Buffer(buffer) => buffer.lock().unwrap().push(delivery),
+            ResultSender::Bounded(sender, closed) => {
+                return !closed.load(Ordering::SeqCst) && sender.send(delivery).is_ok();
+            }
+        }
+        true
+    }
+}
+
+/// The most results passed to JavaScript in one trip to its thread, unless `batchSize` says otherwise
+pub(crate) const CALLBACK_BATCH_SIZE: usize = 256;
+
+/// Starts a thread that collects results from every searching thread
+/// and queues them on the JavaScript thread in batches of at most `batch_size`.
+///
+/// Each batch is whatever's ready, or with a `flush_interval`, whatever arrives within that long of its first result.
+/// Everything (including the start and end of each file) goes through the one queue, so batches never reorder anything.
+///
+/// It finishes once every `Sender` has been dropped and all of their results have been queued.
+pub(crate) fn spawn_callback_thread(
+    channel: Channel,
+    callbacks: Callbacks,
+    batch_size: usize,
+    flush_interval: Option<Duration>,
+) -> (mpsc::Sender<Delivery>, thread::JoinHandle<()>) {
+    let (sender, receiver) = mpsc::channel::<Delivery>();
+    let handle = thread::spawn(move || {
+        while let Ok(first) = receiver.recv() {
+            let mut batch = Vec::with_capacity(batch_size);
+            batch.push(first);
+            match flush_interval {
+                Some(flush_interval) => {
+                    let deadline = Instant::now() + flush_interval;
+                    while batch.len() < batch_size {
+                        let wait = deadline.saturating_duration_since(Instant::now());
+                        match receiver.recv_timeout(wait) {
+                            Ok(delivery) => batch.push(delivery),
+                            // Once the search is over, there's nothing to wait for, so the last batch goes right away
+                            Err(_) => break,
+                        }
+                    }
+                }
+                None => batch.extend(receiver.try_iter().take(batch_size - 1)),
+            }
+
+            let callbacks = callbacks.clone();
+            channel.send(move |mut context| {
+                for delivery in batch {
+                    delivery.deliver(&mut context, &callbacks)?;
+                }
+                Ok(())
+            });
+        }
+    });
+    (sender, handle)
+}
+
+/// Statistics about a completed search, passed to the `onComplete` callback
+pub(crate) struct SearchSummary {
+    pub(crate) files_with_matches: usize,
+    // Whether anything matched at all
+    pub(crate) had_matches: bool,
+    // The paths of every file with a match, each followed by a NUL byte, for `nullSeparated`
+    pub(crate) matched_files: Option<String>,
+    // The count for each file with any matches (by its reported path), and the total, for `countMode`
+    pub(crate) counts: Option<(Vec<(String, u64)>, u64)>,
+    // The statistics for each file with any matches (by its reported path), and the total, for `matchStats`
+    pub(crate) match_stats: Option<(Vec<(String, MatchStats)>, MatchStats)>,
+    // Whether the search was stopped by `timeoutMs` before it finished
+    pub(crate) timed_out: bool,
+    // Whether the search was stopped by `cancelSearch` (or by nothing listening any more) before it finished
+    pub(crate) cancelled: bool,
+    // Whether the search was stopped by `maxFilesSearched` before it finished
+    pub(crate) limit_reached: bool,
+    pub(crate) bytes_searched: u64,
+    // The number of files that would have been searched, for `dryRun`
+    pub(crate) candidate_files: Option<u64>,
+    // Every error that stopped a file or directory from being searched
+    pub(crate) errors: Vec<RipgrepjsError>,
+    // Whether any error that only affected one file was passed to `onError`
+    pub(crate) error_reported: bool,
+    pub(crate) elapsed: Duration,
+    // The final `summary` message, for `jsonLines`
+    pub(crate) json_summary: Option<String>,
+}
+
+impl SearchSummary {
+    /// The exit code `grep` would have: 2 if there were any errors, otherwise 0 if anything matched and 1 if not
+    fn exit_code(&self) -> u8 {
+        if !self.errors.is_empty() || self.error_reported {
+            2
+        } else if self.had_matches {
+            0
+        } else {
+            1
+        }
+    }
+
+    pub(crate) fn to_js_object<'a>(&self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+        let js_summary = cx.empty_object();
+        let files_with_matches = cx.number(self.files_with_matches as f64);
+        js_summary.set(cx, "filesWithMatches", files_with_matches)?;
+        let js_had_matches = cx.boolean(self.had_matches);
+        js_summary.set(cx, "hadMatches", js_had_matches)?;
+        let js_exit_code = cx.number(self.exit_code());
+        js_summary.set(cx, "exitCode", js_exit_code)?;
+        if let Some(matched_files) = &self.matched_files {
+            let js_matched_files = cx.string(matched_files);
+            js_summary.set(cx, "matchedFiles", js_matched_files)?;
+        }
+        if let Some((file_counts, total)) = &self.counts {
+            let js_counts = cx.empty_object();
+            for (path, count) in file_counts {
+                let js_count = cx.number(*count as f64);
+                js_counts.set(cx, path.as_str(), js_count)?;
+            }
+            js_summary.set(cx, "counts", js_counts)?;
+            let js_total = cx.number(*total as f64);
+            js_summary.set(cx, "totalCount", js_total)?;
+        }
+        if let Some((file_stats, total)) = &self.match_stats {
+            let js_file_stats = cx.empty_object();
+            for (path, stats) in file_stats {
+                let js_stats = stats.to_js_object(cx)?;
+                js_file_stats.set(cx, path.as_str(), js_stats)?;
+            }
+            js_summary.set(cx, "matchStats", js_file_stats)?;
+            let js_total = total.to_js_object(cx)?;
+            js_summary.set(cx, "totalMatchStats", js_total)?;
+        }
+        let js_timed_out = cx.boolean(self.timed_out);
+        js_summary.set(cx, "timedOut", js_timed_out)?;
+        let js_cancelled = cx.boolean(self.cancelled);
+        js_summary.set(cx, "cancelled", js_cancelled)?;
+        let js_limit_reached = cx.boolean(self.limit_reached);
+        js_summary.set(cx, "limitReached", js_limit_reached)?;
+        let js_bytes_searched = cx.number(self.bytes_searched as f64);
+        js_summary.set(cx, "bytesSearched", js_bytes_searched)?;
+        if let Some(candidate_files) = self.candidate_files {
+            let js_candidate_files = cx.number(candidate_files as f64);
+            js_summary.set(cx, "candidateFiles", js_candidate_files)?;
+        }
+        let js_errors = cx.empty_array();
+        for (idx, error) in self.errors.iter().enumerate() {
+            let js_error = error.to_js_error(cx, error.to_string())?;
+            js_errors.set(cx, idx as u32, js_error)?;
+        }
+        js_summary.set(cx, "errors", js_errors)?;
+        let js_elapsed_ms = cx.number(self.elapsed.as_secs_f64() * 1000.0);
+        js_summary.set(cx, "elapsedMs", js_elapsed_ms)?;
+        if let Some(json_summary) = &self.json_summary {
+            let js_json_summary = cx.string(json_summary);
+            js_summary.set(cx, "jsonSummary", js_json_summary)?;
+        }
+        Ok(js_summary)
+    }
+}
+
+/// How far a search has got, passed to the `onProgress` callback
+pub(crate) struct SearchProgress {
+    pub(crate) files_searched: u64,
+    pub(crate) bytes_searched: u64,
+    pub(crate) elapsed: Duration,
+}
+
+impl SearchProgress {
+    fn to_js_object<'a>(&self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+        let js_progress = cx.empty_object();
+        let js_files_searched = cx.number(self.files_searched as f64);
+        js_progress.set(cx, "filesSearched", js_files_searched)?;
+        let js_bytes_searched = cx.number(self.bytes_searched as f64);
+        js_progress.set(cx, "bytesSearched", js_bytes_searched)?;
+        let js_elapsed_ms = cx.number(self.elapsed.as_secs_f64() * 1000.0);
+        js_progress.set(cx, "elapsedMs", js_elapsed_ms)?;
+        Ok(js_progress)
+    }
+}
+
+/// Matching lines added up like `wc` would, for `matchStats`
+#[derive(Clone, Copy, Default)]
+pub(crate) struct MatchStats {
+    lines: u64,
+    // Including line terminators
+    bytes: u64,
+    // Runs of non-whitespace
+    words: u64,
+}
+
+impl MatchStats {
+    /// Adds a matching line, with and without its line terminator
+    pub(crate) fn add_line(&mut self, line: &[u8], text: &[u8]) {
+        self.lines += 1;
+        self.bytes += line.len() as u64;
+        self.words += text
+            .split(u8::is_ascii_whitespace)
+            .filter(|word| !word.is_empty())
+            .count() as u64;
+    }
+
+    pub(crate) fn add(&mut self, other: MatchStats) {
+        self.lines += other.lines;
+        self.bytes += other.bytes;
+        self.words += other.words;
+    }
+
+    fn to_js_object<'a>(self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+        let js_stats = cx.empty_object();
+        let js_lines = cx.number(self.lines as f64);
+        js_stats.set(cx, "lines", js_lines)?;
+        let js_bytes = cx.number(self.bytes as f64);
+        js_stats.set(cx, "bytes", js_bytes)?;
+        let js_words = cx.number(self.words as f64);
+        js_stats.set(cx, "words", js_words)?;
+        Ok(js_stats)
+    }
+}
+
+/// Statistics in the format of `rg --json`, for a file's `end` message or the search's `summary`
+#[derive(Default)]
+pub(crate) struct JsonStats {
+    pub(crate) elapsed: Duration,
+    pub(crate) searches: u64,
+    pub(crate) searches_with_match: u64,
+    pub(crate) bytes_searched: u64,
+    pub(crate) bytes_printed: u64,
+    pub(crate) matched_lines: u64,
+    pub(crate) matches: u64,
+}
+
+impl JsonStats {
+    pub(crate) fn add(&mut self, other: &JsonStats) {
+        self.elapsed += other.elapsed;
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.bytes_searched += other.bytes_searched;
+        self.bytes_printed += other.bytes_printed;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+    }
+
+    pub(crate) fn to_json(&self) -> JsonValue {
+        json!({
+            "elapsed": json_duration(self.elapsed),
+            "searches": self.searches,
+            "searches_with_match": self.searches_with_match,
+            "bytes_searched": self.bytes_searched,
+            "bytes_printed": self.bytes_printed,
+            "matched_lines": self.matched_lines,
+            "matches": self.matches,
+        })
+    }
+}
+
+/// A duration as `rg --json` has it
+pub(crate) fn json_duration(duration: Duration) -> JsonValue {
+    json!({
+        "secs": duration.as_secs(),
+        "nanos": duration.subsec_nanos(),
+        "human": format!("{:0.6}s", duration.as_secs_f64()),
+    })
+}
+
+/// Text from a file as `rg --json` has it: `text` if it's UTF-8, or otherwise base64-encoded `bytes`
+pub(crate) fn json_text(bytes: &[u8]) -> JsonValue {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => json!({ "text": text }),
+        Err(_) => json!({ "bytes": base64::encode(bytes) }),
+    }
+}
+
+/// Joins paths like `rg --files-with-matches --null`, with a NUL byte after each one.
+///
+/// Unlike newlines, NUL can't appear in a path, so this is safe to split even with unusual filenames.
+pub(crate) fn null_separated_paths(
+    paths: &HashSet<PathBuf>,
+    searcher_opts: &SearcherOptions,
+) -> String {
+    let mut paths = paths
+        .iter()
+        .map(|path| searcher_opts.reported_path(path))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut joined = String::new();
+    for path in paths {
+        joined.push_str(&path);
+        joined.push('\0');
+    }
+    joined
+}
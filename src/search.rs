@@ -0,0 +1,1377 @@
+//! Searching files: the state shared by a search's threads, and reading each file the way the options ask for
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Instant,
+};
+
+use grep::{
+    matcher::Matcher,
+    searcher::{Searcher, SearcherBuilder},
+};
+use ignore::gitignore::Gitignore;
+use rayon::prelude::*;
+use serde_json::json;
+
+use crate::{
+    archive::ArchiveKind,
+    error::RipgrepjsError,
+    handles::CacheLookup,
+    matcher::SearchMatcher,
+    options::{FileNameFilter, FileRegion, SearcherOptions},
+    results::{
+        json_duration, null_separated_paths, Delivery, FileMetadata, JsonStats, MatchStats,
+        ResultSender, SearchProgress, SearchSummary,
+    },
+    sink::{read_header_lines, HeadingTracker, JSCallbackSink, WindowSink},
+    walk::Blob,
+};
+
+/// State shared between every sink taking part in one search
+pub(crate) struct SharedSearchState {
+    // Number of matches left before the search should stop
+    remaining_matches: Option<AtomicUsize>,
+    // Matched text that has already been reported, for `uniqueScope: "search"`
+    pub(crate) seen_matches: Mutex<HashSet<Vec<u8>>>,
+    // The `seq` number to give the next result sent to JavaScript
+    pub(crate) next_seq: AtomicU64,
+    // Set to stop the search early, e.g. when the `timeoutMs` deadline passes or `cancelSearch` is called,
+    // and shared with the search's `SearchToken`
+    cancelled: Arc<AtomicBool>,
+    // Set along with `cancelled` when the `timeoutMs` deadline passes
+    timed_out: AtomicBool,
+    // Set along with `cancelled` when an error stops the search, like a match that isn't valid UTF-8 with `strictUtf8`
+    failed: AtomicBool,
+    // Number of files left to search before the search should stop, for `maxFilesSearched`
+    remaining_files: Option<AtomicUsize>,
+    // Set once a file is left out because `maxFilesSearched` has been reached
+    file_limit_reached: AtomicBool,
+    // Set by the first match claimed, for the summary's `hadMatches`
+    had_matches: AtomicBool,
+    // Set once an error is passed to `onError`, for the summary's `exitCode`
+    pub(crate) error_reported: AtomicBool,
+    // Every file's statistics added together, for `jsonLines`' `summary` message
+    pub(crate) json_stats: Mutex<JsonStats>,
+    // The names of the files in each directory looked up so far, for `includeSiblings`
+    // (`None` if it couldn't be read)
+    directory_files: Mutex<HashMap<PathBuf, Option<Arc<Vec<String>>>>>,
+}
+
+impl SharedSearchState {
+    fn new(searcher_opts: &SearcherOptions, cancelled: Arc<AtomicBool>) -> Self {
+        Self {
+            remaining_matches: searcher_opts.max_total_matches.map(AtomicUsize::new),
+            seen_matches: Mutex::new(HashSet::new()),
+            next_seq: AtomicU64::new(0),
+            cancelled,
+            timed_out: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+            remaining_files: searcher_opts.max_files_searched.map(AtomicUsize::new),
+            file_limit_reached: AtomicBool::new(false),
+            had_matches: AtomicBool::new(false),
+            error_reported: AtomicBool::new(false),
+            json_stats: Mutex::new(JsonStats::default()),
+            directory_files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Lists the files (not directories) in a directory by name, in order, for `includeSiblings`.
+    ///
+    /// Each directory is only read once per search, however many of its files match.
+    pub(crate) fn directory_files(&self, directory: &Path) -> Option<Arc<Vec<String>>> {
+        if let Some(files) = self.directory_files.lock().unwrap().get(directory) {
+            return files.clone();
+        }
+        // Read without holding the lock, so other threads aren't held up by it
+        let files = std::fs::read_dir(directory).ok().map(|entries| {
+            let mut files: Vec<String> = entries
+                .filter_map(Result::ok)
+                .filter(|entry| {
+                    entry.file_type().is_ok_and(|file_type| {
+                        file_type.is_file() || (file_type.is_symlink() && entry.path().is_file())
+                    })
+                })
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect();
+            files.sort();
+            Arc::new(files)
+        });
+        self.directory_files
+            .lock()
+            .unwrap()
+            .entry(directory.to_path_buf())
+            .or_insert(files)
+            .clone()
+    }
+
+    /// Stops the search as soon as every thread notices.
+    pub(crate) fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Stops the search because its `timeoutMs` deadline has passed.
+    pub(crate) fn time_out(&self) {
+        self.timed_out.store(true, Ordering::SeqCst);
+        self.cancel();
+    }
+
+    /// Stops the search because of an error that's in the summary's `errors`.
+    pub(crate) fn fail(&self) {
+        self.failed.store(true, Ordering::SeqCst);
+        self.cancel();
+    }
+
+    /// Claims a match from the `max_total_matches` budget, before it's reported.
+    ///
+    /// Returns `None` if there are no matches left to report, or otherwise whether there will be any left after this one.
+    /// Claiming before sending means that concurrent sinks can never collectively report too many matches,
+    /// but the cap is only approximate under parallelism: which matches are reported depends on which threads
+    /// claim them first, so it varies from run to run.
+    pub(crate) fn claim_match(&self) -> Option<bool> {
+        if self.is_cancelled() {
+            return None;
+        }
+        let claimed = match &self.remaining_matches {
+            Some(remaining) => remaining
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .ok()
+                .map(|previous| previous > 1),
+            None => Some(true),
+        };
+        if claimed.is_some() {
+            self.had_matches.store(true, Ordering::Relaxed);
+        }
+        claimed
+    }
+
+    /// Claims a file from the `max_files_searched` budget, before it's searched.
+    ///
+    /// Returns `false` once there are none left, which stops the search.
+    fn claim_file(&self) -> bool {
+        let claimed = match &self.remaining_files {
+            Some(remaining) => remaining
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok(),
+            None => true,
+        };
+        if !claimed {
+            self.file_limit_reached.store(true, Ordering::SeqCst);
+        }
+        claimed
+    }
+
+    /// Returns `true` if the search has run out of matches or files, or been cancelled.
+    pub(crate) fn should_stop(&self) -> bool {
+        if self.is_cancelled() || self.file_limit_reached.load(Ordering::SeqCst) {
+            return true;
+        }
+        match &self.remaining_matches {
+            Some(remaining) => remaining.load(Ordering::SeqCst) == 0,
+            None => false,
+        }
+    }
+}
+
+/// Counts the bytes read through it, for the summary's `bytesSearched`
+pub(crate) struct CountingReader<R> {
+    inner: R,
+    pub(crate) bytes_read: u64,
+    // If set, occurrences of this byte are counted in `lines`, for numbering the lines of chunks
+    line_terminator: Option<u8>,
+    lines: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            bytes_read: 0,
+            line_terminator: None,
+            lines: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes_read += read as u64;
+        if let Some(line_terminator) = self.line_terminator {
+            self.lines += buf[..read]
+                .iter()
+                .filter(|&&byte| byte == line_terminator)
+                .count() as u64;
+        }
+        Ok(read)
+    }
+}
+
+/// Finds where to split a file into chunks of at least `chunk_size` bytes, for `chunkSize`.
+///
+/// Each chunk starts just after a line terminator, so no line is split across two of them.
+/// Returns the offsets of the start of each chunk, followed by `len`.
+pub(crate) fn chunk_boundaries(
+    path: &Path,
+    len: u64,
+    chunk_size: u64,
+    line_terminator: u8,
+) -> std::io::Result<Vec<u64>> {
+    let mut boundaries = vec![0];
+    if len > chunk_size {
+        let mut file = std::fs::File::open(path)?;
+        let mut boundary = chunk_size;
+        while boundary < len {
+            let position = next_line_start(&mut file, boundary, line_terminator)?;
+            if position >= len {
+                break;
+            }
+            boundaries.push(position);
+            boundary = position + chunk_size;
+        }
+    }
+    boundaries.push(len);
+    Ok(boundaries)
+}
+
+/// Finds the start of the first line that starts at or after `offset`, or the end of the file if there isn't one.
+pub(crate) fn next_line_start(
+    file: &mut (impl Read + Seek),
+    offset: u64,
+    line_terminator: u8,
+) -> std::io::Result<u64> {
+    if offset == 0 {
+        return Ok(0);
+    }
+    // Start at the byte before, in case a line ends exactly at the offset
+    let mut position = offset - 1;
+    file.seek(SeekFrom::Start(position))?;
+    let mut buf = [0; 8192];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            return Ok(position);
+        }
+        match buf[..read].iter().position(|&byte| byte == line_terminator) {
+            Some(index) => return Ok(position + index as u64 + 1),
+            None => position += read as u64,
+        }
+    }
+}
+
+/// Reads lines from the start of a file until `stop` returns `true`, given the offset just after a line
+/// and how many lines end there. Returns the offset and count it stopped at, or the end of the file.
+pub(crate) fn scan_lines(
+    file: &mut (impl Read + Seek),
+    line_terminator: u8,
+    mut stop: impl FnMut(u64, u64) -> bool,
+) -> std::io::Result<(u64, u64)> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut buf = [0; 8192];
+    let mut position = 0;
+    let mut lines = 0;
+    if stop(position, lines) {
+        return Ok((position, lines));
+    }
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            return Ok((position, lines));
+        }
+        for (index, &byte) in buf[..read].iter().enumerate() {
+            if byte == line_terminator {
+                lines += 1;
+                let offset = position + index as u64 + 1;
+                if stop(offset, lines) {
+                    return Ok((offset, lines));
+                }
+            }
+        }
+        position += read as u64;
+    }
+}
+
+/// Counts the line terminators before `offset`, reading only that much of the file, so that line numbers
+/// in a byte region (from `startByte` or `tailLines`) are still counted from the start of the file.
+pub(crate) fn count_lines_before(
+    file: &mut (impl Read + Seek),
+    offset: u64,
+    line_terminator: u8,
+) -> std::io::Result<u64> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader = (&mut *file).take(offset);
+    let mut buf = vec![0; 64 * 1024];
+    let mut lines = 0;
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            return Ok(lines);
+        }
+        lines += memchr::memchr_iter(line_terminator, &buf[..read]).count() as u64;
+    }
+}
+
+/// Finds where the last `lines` lines of a file start, for `tailLines`, reading backwards from its end
+/// so that a large file's start is never read. A file with fewer lines than that is searched from its start.
+pub(crate) fn tail_start(
+    file: &mut (impl Read + Seek),
+    lines: u64,
+    line_terminator: u8,
+) -> std::io::Result<u64> {
+    let length = file.seek(SeekFrom::End(0))?;
+    if lines == 0 {
+        return Ok(length);
+    }
+    let mut buf = vec![0; 64 * 1024];
+    let mut remaining = lines;
+    let mut end = length;
+    while end > 0 {
+        let start = end.saturating_sub(buf.len() as u64);
+        let chunk = &mut buf[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        for index in memchr::memrchr_iter(line_terminator, chunk) {
+            let offset = start + index as u64;
+            // A terminator at the very end of the file ends the last line, rather than starting another
+            if offset + 1 == length {
+                continue;
+            }
+            remaining -= 1;
+            if remaining == 0 {
+                return Ok(offset + 1);
+            }
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// Stops reading after a number of line terminators, for `endLine`
+pub(crate) struct LineLimitedReader<R> {
+    inner: R,
+    remaining_lines: Option<u64>,
+    line_terminator: u8,
+}
+
+impl<R: Read> Read for LineLimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining_lines = match &mut self.remaining_lines {
+            Some(0) => return Ok(0),
+            Some(remaining_lines) => remaining_lines,
+            None => return self.inner.read(buf),
+        };
+        let read = self.inner.read(buf)?;
+        for (index, &byte) in buf[..read].iter().enumerate() {
+            if byte == self.line_terminator {
+                *remaining_lines -= 1;
+                if *remaining_lines == 0 {
+                    return Ok(index + 1);
+                }
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// What searching one chunk of a file found, for `chunkSize`
+pub(crate) struct SearchedChunk {
+    results: Vec<Delivery>,
+    lines: u64,
+    reported_match: bool,
+    count: u64,
+    match_stats: MatchStats,
+    quit_on_binary: bool,
+}
+
+/// Sends the results of a file's chunks in order, as each chunk and all the ones before it are done
+pub(crate) struct ChunkMerger {
+    // The index of the next chunk to send the results of
+    next: usize,
+    // Chunks that are done, but are waiting for an earlier one
+    pending: BTreeMap<usize, SearchedChunk>,
+    // Lines in every chunk sent so far, which later chunks' line numbers are offset by
+    lines_before: u64,
+    reported_match: bool,
+    count: u64,
+    match_stats: MatchStats,
+    // Set once a chunk stops on binary data, since the rest of the file wouldn't have been searched
+    quit_on_binary: bool,
+}
+
+/// Passes files' results on in the order the files started being searched, for `orderByFile`
+pub(crate) struct FileOrder {
+    // The index the next file to start being searched gets
+    next_index: AtomicU64,
+    releaser: Mutex<FileReleaser>,
+}
+
+/// The files that are done, but waiting for an earlier one, for `orderByFile`
+pub(crate) struct FileReleaser {
+    // The index of the next file to send the results of
+    next: u64,
+    pending: BTreeMap<u64, Vec<Delivery>>,
+}
+
+/// Counts down the files that can still be opened, for `maxConcurrentFiles`
+pub(crate) struct FileSemaphore {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+impl FileSemaphore {
+    fn new(max_open: usize) -> Self {
+        Self {
+            available: Mutex::new(max_open),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Waits until another file can be opened, returning a guard that makes room again once it's dropped
+    fn acquire(&self) -> FilePermit<'_> {
+        let mut available = self.available.lock().unwrap();
+        while *available == 0 {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= 1;
+        FilePermit { semaphore: self }
+    }
+}
+
+/// Room for one open file, from `FileSemaphore::acquire`
+pub(crate) struct FilePermit<'a> {
+    semaphore: &'a FileSemaphore,
+}
+
+impl Drop for FilePermit<'_> {
+    fn drop(&mut self) {
+        *self.semaphore.available.lock().unwrap() += 1;
+        self.semaphore.released.notify_one();
+    }
+}
+
+/// A file that's only opened once something needs to read it, after waiting for room to open it with
+/// `maxConcurrentFiles`. Everything that reads the file (from `skipFilePattern` to the search itself)
+/// shares the one descriptor.
+pub(crate) struct LazyFile<'a> {
+    path: &'a Path,
+    open_files: Option<&'a FileSemaphore>,
+    // The file comes first so that it's closed before its permit makes room for another
+    opened: Option<(Arc<std::fs::File>, Option<FilePermit<'a>>)>,
+}
+
+impl<'a> LazyFile<'a> {
+    fn new(path: &'a Path, open_files: Option<&'a FileSemaphore>) -> Self {
+        Self {
+            path,
+            open_files,
+            opened: None,
+        }
+    }
+
+    /// Opens the file if it isn't open yet, and rewinds it to its start
+    fn open(&mut self) -> std::io::Result<&Arc<std::fs::File>> {
+        if self.opened.is_none() {
+            let permit = self.open_files.map(FileSemaphore::acquire);
+            self.opened = Some((Arc::new(std::fs::File::open(self.path)?), permit));
+        }
+        let file = &self.opened.as_ref().unwrap().0;
+        (&**file).seek(SeekFrom::Start(0))?;
+        Ok(file)
+    }
+}
+
+/// What a file that hasn't been read yet is read from: the file itself, or a blob's contents
+pub(crate) enum FileContents<'f, 'a> {
+    OnDisk(&'f mut LazyFile<'a>),
+    Blob(&'f [u8]),
+}
+
+/// State shared by every thread taking part in a search
+pub(crate) struct DirectorySearch<'a> {
+    pub(crate) searcher_opts: &'a SearcherOptions,
+    pub(crate) matcher: &'a SearchMatcher,
+    pub(crate) results: ResultSender,
+    // Whether there's an `onSkip` callback to tell about skipped files
+    report_skips: bool,
+    pub(crate) shared: SharedSearchState,
+    // Only files whose names pass this are searched
+    pub(crate) file_name_filter: Option<FileNameFilter>,
+    // Every file that has had at least one match reported
+    matched_files: Mutex<HashSet<PathBuf>>,
+    // The count for each file with any matches, for `countMode`
+    counts: Mutex<Vec<(PathBuf, u64)>>,
+    // The statistics of each file with any matches, for `matchStats`
+    match_stats: Mutex<Vec<(PathBuf, MatchStats)>>,
+    // Canonical paths of every directory searched so far, so that overlapping roots aren't searched twice
+    pub(crate) visited_directories: Mutex<HashSet<PathBuf>>,
+    // Canonical paths of every file searched so far, with `followSymlinks`, so that a file that's
+    // also reachable through a link is only searched once
+    pub(crate) visited_files: Mutex<HashSet<PathBuf>>,
+    // The rules from `core.excludesFile` or `~/.config/git/ignore`, for `respectGlobalGitignore`
+    pub(crate) global_gitignore: Option<Gitignore>,
+    // The bytes read from every file searched, which stops short of files' ends when searching stops early
+    pub(crate) bytes_searched: AtomicU64,
+    // Errors that stopped a file or directory from being searched, which don't stop the rest of the search
+    pub(crate) errors: Mutex<Vec<RipgrepjsError>>,
+    // Limits how many files are open at once, for `maxConcurrentFiles`
+    open_files: Option<FileSemaphore>,
+    // The number of files searched so far, for `onProgress`
+    files_searched: AtomicU64,
+    // The number of files that would have been searched, for `dryRun`
+    candidate_files: AtomicU64,
+    // When progress was last reported, in milliseconds since `started`
+    last_progress_ms: AtomicU64,
+    started: Instant,
+    // The files already searched by a `research` cache, so that unchanged ones aren't searched again
+    pub(crate) cache_lookup: Option<CacheLookup<'a>>,
+    // Holds back files' results until every file that started before them is done, for `orderByFile`
+    file_order: Option<FileOrder>,
+}
+
+impl<'a> DirectorySearch<'a> {
+    pub(crate) fn new(
+        searcher_opts: &'a SearcherOptions,
+        file_name_filter: Option<FileNameFilter>,
+        matcher: &'a SearchMatcher,
+        results: ResultSender,
+        report_skips: bool,
+        cancelled: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            file_name_filter,
+            shared: SharedSearchState::new(searcher_opts, cancelled),
+            searcher_opts,
+            matcher,
+            results,
+            report_skips,
+            matched_files: Mutex::new(HashSet::new()),
+            counts: Mutex::new(Vec::new()),
+            match_stats: Mutex::new(Vec::new()),
+            visited_files: Mutex::new(HashSet::new()),
+            // Like git, a global gitignore that can't be read (or has bad lines) is passed over
+            global_gitignore: searcher_opts
+                .respect_global_gitignore
+                .then(|| Gitignore::global().0),
+            bytes_searched: AtomicU64::new(0),
+            errors: Mutex::new(Vec::new()),
+            open_files: searcher_opts.max_concurrent_files.map(FileSemaphore::new),
+            files_searched: AtomicU64::new(0),
+            candidate_files: AtomicU64::new(0),
+            last_progress_ms: AtomicU64::new(0),
+            started: Instant::now(),
+            visited_directories: Mutex::new(HashSet::new()),
+            cache_lookup: None,
+            file_order: if searcher_opts.order_by_file {
+                Some(FileOrder {
+                    next_index: AtomicU64::new(0),
+                    releaser: Mutex::new(FileReleaser {
+                        next: 0,
+                        pending: BTreeMap::new(),
+                    }),
+                })
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Summarizes the search, once it's finished
+    pub(crate) fn summary(&self) -> SearchSummary {
+        let matched_files = self.matched_files.lock().unwrap();
+        SearchSummary {
+            files_with_matches: matched_files.len(),
+            had_matches: self.shared.had_matches.load(Ordering::Relaxed),
+            matched_files: if self.searcher_opts.null_separated {
+                Some(null_separated_paths(&matched_files, self.searcher_opts))
+            } else {
+                None
+            },
+            counts: self.searcher_opts.count_mode.map(|_| {
+                let mut counts = self
+                    .counts
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(path, count)| (self.searcher_opts.reported_path(path), *count))
+                    .collect::<Vec<_>>();
+                counts.sort();
+                let total = counts.iter().map(|(_, count)| count).sum();
+                (counts, total)
+            }),
+            match_stats: if self.searcher_opts.match_stats {
+                let mut file_stats = self
+                    .match_stats
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(path, stats)| (self.searcher_opts.reported_path(path), *stats))
+                    .collect::<Vec<_>>();
+                file_stats.sort_by(|(a, _), (b, _)| a.cmp(b));
+                let mut total = MatchStats::default();
+                for (_, stats) in &file_stats {
+                    total.add(*stats);
+                }
+                Some((file_stats, total))
+            } else {
+                None
+            },
+            timed_out: self.shared.timed_out.load(Ordering::SeqCst),
+            cancelled: self.shared.is_cancelled()
+                && !self.shared.timed_out.load(Ordering::SeqCst)
+                && !self.shared.failed.load(Ordering::SeqCst),
+            limit_reached: self.shared.file_limit_reached.load(Ordering::SeqCst),
+            bytes_searched: self.bytes_searched.load(Ordering::Relaxed),
+            candidate_files: if self.searcher_opts.dry_run {
+                Some(self.candidate_files.load(Ordering::Relaxed))
+            } else {
+                None
+            },
+            errors: std::mem::take(&mut *self.errors.lock().unwrap()),
+            error_reported: self.shared.error_reported.load(Ordering::Relaxed),
+            elapsed: self.started.elapsed(),
+            json_summary: if self.searcher_opts.json_lines {
+                let message = json!({
+                    "type": "summary",
+                    "data": {
+                        "elapsed_total": json_duration(self.started.elapsed()),
+                        "stats": self.shared.json_stats.lock().unwrap().to_json(),
+                    },
+                });
+                Some(message.to_string())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Makes a new sink reporting to this search's callback
+    pub(crate) fn sink(&self) -> JSCallbackSink<'_> {
+        JSCallbackSink::new(
+            self.results.clone(),
+            self.searcher_opts,
+            &self.shared,
+            self.matcher,
+        )
+    }
+
+    /// Passes an error that didn't stop the search to the `onError` callback, if there is one
+    pub(crate) fn report_error(&self, error: RipgrepjsError) {
+        self.shared.error_reported.store(true, Ordering::Relaxed);
+        self.results.send(Delivery::Error(error));
+    }
+
+    /// Records an error that stopped a file or directory from being searched, for the summary's `errors`.
+    ///
+    /// Errors are collected rather than returned so that one unreadable file doesn't stop every other thread.
+    pub(crate) fn record_error(&self, path: PathBuf, error: RipgrepjsError) {
+        let error = match error {
+            RipgrepjsError::InFile(..) => error,
+            error => RipgrepjsError::InFile(path, Box::new(error)),
+        };
+        self.errors.lock().unwrap().push(error);
+    }
+
+    /// Passes the search's progress to `onProgress` after a file has been searched, unless it was last passed
+    /// less than `progress_interval` ago.
+    ///
+    /// Every thread calls this, but only the first to find that the interval has passed gets to report,
+    /// so the JavaScript thread gets at most one update per interval however quickly files are searched.
+    fn report_progress(&self) {
+        if !self.searcher_opts.report_progress {
+            return;
+        }
+        let files_searched = self.files_searched.fetch_add(1, Ordering::Relaxed) + 1;
+        let elapsed = self.started.elapsed();
+        let now_ms = elapsed.as_millis() as u64;
+        let last_ms = self.last_progress_ms.load(Ordering::Relaxed);
+        if now_ms < last_ms + self.searcher_opts.progress_interval.as_millis() as u64 {
+            return;
+        }
+        // Another thread got there first if this fails
+        if self
+            .last_progress_ms
+            .compare_exchange(last_ms, now_ms, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.results.send(Delivery::Progress(SearchProgress {
+                files_searched,
+                bytes_searched: self.bytes_searched.load(Ordering::Relaxed),
+                elapsed,
+            }));
+        }
+    }
+
+    /// Passes a file that was left out of the search to the `onSkip` callback, if there is one
+    pub(crate) fn report_skip(&self, path: PathBuf, reason: &'static str) {
+        if self.report_skips {
+            self.results.send(Delivery::Skip(path, reason));
+        }
+    }
+
+    /// Passes a file that was left out by its name to the `onIgnore` callback, if there is one, with why
+    fn report_ignore(&self, path: &Path) {
+        if !self.searcher_opts.report_ignores {
+            return;
+        }
+        let (source, rule) = match (&self.file_name_filter, path.file_name()) {
+            (Some(filter), Some(name)) => filter.why_excluded(name),
+            _ => ("fileNameGlob", None),
+        };
+        self.results
+            .send(Delivery::Ignore(path.to_path_buf(), source.into(), rule));
+    }
+
+    /// Reports a file if its path matches the pattern, without reading it, for `matchPathsOnly`
+    fn match_path(&self, sink: &JSCallbackSink, path: PathBuf) -> Result<(), RipgrepjsError> {
+        let path_string = path.to_string_lossy();
+        if !self.matcher.is_match(path_string.as_bytes())? {
+            return Ok(());
+        }
+        if self.shared.claim_match().is_some() {
+            sink.send_path(self.searcher_opts.reported_path(&path));
+            self.matched_files.lock().unwrap().insert(path);
+        }
+        Ok(())
+    }
+
+    /// Whether any line of a file (or of a blob's contents, if they're given) matches the `skipFilePattern`.
+    ///
+    /// Reading stops at the first line that does.
+    fn contains_skip_pattern(
+        &self,
+        skip_file_matcher: &SearchMatcher,
+        contents: &mut FileContents,
+    ) -> Result<bool, RipgrepjsError> {
+        let mut searcher = SearcherBuilder::new()
+            .line_terminator(self.searcher_opts.line_terminator.to_line_terminator())
+            .build();
+        let mut found = false;
+        let sink = grep::searcher::sinks::Bytes(|_, _| {
+            found = true;
+            Ok(false)
+        });
+        match contents {
+            FileContents::Blob(data) => searcher.search_slice(skip_file_matcher, data, sink)?,
+            FileContents::OnDisk(file) => {
+                searcher.search_file(skip_file_matcher, file.open()?, sink)?
+            }
+        }
+        Ok(found)
+    }
+
+    /// Whether a file (or a blob's contents, if they're given) contains the `requiredLiteral`.
+    ///
+    /// The file is read in pieces, only as far as the first occurrence (and `maxBytesPerFile`).
+    fn contains_required_literal(
+        &self,
+        finder: &memchr::memmem::Finder,
+        contents: &mut FileContents,
+    ) -> Result<bool, RipgrepjsError> {
+        let max_bytes = self.searcher_opts.max_bytes_per_file.unwrap_or(u64::MAX);
+        let file = match contents {
+            FileContents::Blob(data) => {
+                let len = (data.len() as u64).min(max_bytes) as usize;
+                return Ok(finder.find(&data[..len]).is_some());
+            }
+            FileContents::OnDisk(file) => file.open()?,
+        };
+
+        let mut file = (&**file).take(max_bytes);
+        // Each read keeps the end of the last one, in case the literal is split between them
+        let overlap = finder.needle().len() - 1;
+        let mut buf = vec![0; (64 * 1024).max(overlap * 2)];
+        let mut filled = 0;
+        loop {
+            let read = file.read(&mut buf[filled..])?;
+            if read == 0 {
+                return Ok(false);
+            }
+            filled += read;
+            if finder.find(&buf[..filled]).is_some() {
+                return Ok(true);
+            }
+            let kept = filled.min(overlap);
+            buf.copy_within(filled - kept..filled, 0);
+            filled = kept;
+        }
+    }
+
+    /// Deals with a file that doesn't need to be searched: one filtered out by its name, its metadata
+    /// (unless it's a blob, whose contents are given), `skipFilePattern`, or `requiredLiteral`, or by `maxFilesSearched`,
+    /// or one whose path is all that's reported, with `dryRun` or `matchPathsOnly`.
+    ///
+    /// Returns whether the file still needs to be searched.
+    fn handle_unread_file(
+        &self,
+        sink: &JSCallbackSink,
+        path: &Path,
+        mut contents: FileContents,
+    ) -> Result<bool, RipgrepjsError> {
+        if !self.should_search_file(path) {
+            self.report_ignore(path);
+            self.report_skip(path.to_path_buf(), "ignored");
+            return Ok(false);
+        }
+        if let (Some(metadata_filter), FileContents::OnDisk(_)) =
+            (&self.searcher_opts.metadata_filter, &contents)
+        {
+            let metadata = FileMetadata::new(&std::fs::metadata(path)?);
+            if !metadata_filter.matches(&metadata) {
+                self.report_skip(path.to_path_buf(), "metadata");
+                return Ok(false);
+            }
+        }
+        if let Some(skip_file_matcher) = &self.searcher_opts.skip_file_matcher {
+            if self.contains_skip_pattern(skip_file_matcher, &mut contents)? {
+                self.report_skip(path.to_path_buf(), "skipPattern");
+                return Ok(false);
+            }
+        }
+        if let Some(finder) = &self.searcher_opts.required_literal {
+            // Files without it can't have any matches, so they're left out as if they'd been searched.
+            // With `invertMatch`, they'd match on every line instead, so they can't be left out,
+            // and `matchPathsOnly` matches paths rather than contents.
+            if !self.searcher_opts.dry_run
+                && !self.searcher_opts.invert_match
+                && !self.searcher_opts.match_paths_only
+                && !self.contains_required_literal(finder, &mut contents)?
+            {
+                return Ok(false);
+            }
+        }
+        if !self.shared.claim_file() {
+            return Ok(false);
+        }
+        if self.searcher_opts.dry_run {
+            self.candidate_files.fetch_add(1, Ordering::Relaxed);
+            if !self.searcher_opts.count_candidates {
+                sink.send_path(self.searcher_opts.reported_path(path));
+            }
+            return Ok(false);
+        }
+        if self.searcher_opts.match_paths_only {
+            self.match_path(sink, path.to_path_buf())?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Searches a single file, unless it's filtered out by its name or metadata
+    pub(crate) fn search_file(
+        &self,
+        searcher: &mut Searcher,
+        sink: &mut JSCallbackSink,
+        path: PathBuf,
+    ) -> Result<(), RipgrepjsError> {
+        let mut file = LazyFile::new(&path, self.open_files.as_ref());
+        if !self.handle_unread_file(sink, &path, FileContents::OnDisk(&mut file))? {
+            return Ok(());
+        }
+        if let Some(cache_lookup) = &self.cache_lookup {
+            if cache_lookup.is_unchanged(&path)? {
+                return Ok(());
+            }
+        }
+
+        if self.searcher_opts.search_archives {
+            match ArchiveKind::of(&path) {
+                Some(ArchiveKind::Unsupported) => {
+                    self.report_skip(path.clone(), "compressedArchive");
+                    return Ok(());
+                }
+                Some(kind) => {
+                    return self.search_archive(searcher, sink, file.open()?, &path, kind)
+                }
+                None => {}
+            }
+        }
+
+        sink.start_file(&path);
+        if self.searcher_opts.include_file_metadata {
+            // Stat each file once here, rather than once per match
+            match std::fs::metadata(&path) {
+                Ok(metadata) => sink.file_metadata = Some(FileMetadata::new(&metadata)),
+                Err(error) => {
+                    return Err(RipgrepjsError::InFile(path.clone(), Box::new(error.into())))
+                }
+            }
+        }
+        if self.searcher_opts.heading_matcher.is_some() {
+            match file.open() {
+                Ok(file) => sink.heading = Some(HeadingTracker::new(Arc::clone(file))),
+                Err(error) => {
+                    return Err(RipgrepjsError::InFile(path.clone(), Box::new(error.into())))
+                }
+            }
+        }
+        if self.searcher_opts.include_siblings {
+            sink.siblings_of = Some(path.as_path().into());
+        }
+        if let Some(count) = self.searcher_opts.header_lines {
+            let header_lines = file.open().and_then(|file| {
+                read_header_lines(BufReader::new(&**file), count, self.searcher_opts)
+            });
+            match header_lines {
+                Ok(lines) => sink.header_lines = Some(Arc::new(lines)),
+                Err(error) => {
+                    return Err(RipgrepjsError::InFile(path.clone(), Box::new(error.into())))
+                }
+            }
+        }
+        let result = self.in_file_order(sink, |sink| match self.searcher_opts.chunk_size {
+            // Each chunk is read through its own descriptor, but chunks are never used with `maxConcurrentFiles`
+            Some(chunk_size) if self.searcher_opts.can_search_in_chunks() => {
+                self.search_in_chunks(sink, &path, chunk_size)
+            }
+            _ => match file.open() {
+                Ok(file) => self.search_whole_file(searcher, sink, file),
+                Err(error) => Err(error.into()),
+            },
+        });
+        // The heading tracker shares the file, which has to be closed before its permit is released
+        sink.heading = None;
+        drop(file);
+        self.finish_file(sink, path, result)
+    }
+
+    /// Runs `search` on a file that's just started being searched, holding its results back with `orderByFile`
+    /// until every file that started before it has been sent on
+    pub(crate) fn in_file_order(
+        &self,
+        sink: &mut JSCallbackSink,
+        search: impl FnOnce(&mut JSCallbackSink) -> Result<(), RipgrepjsError>,
+    ) -> Result<(), RipgrepjsError> {
+        let file_order = match &self.file_order {
+            Some(file_order) => file_order,
+            None => return search(sink),
+        };
+        let index = file_order.next_index.fetch_add(1, Ordering::SeqCst);
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let results =
+            std::mem::replace(&mut sink.results, ResultSender::Buffer(Arc::clone(&buffer)));
+        let result = search(sink);
+        sink.results = results;
+
+        // Even a file that failed has to be released, or every file after it would wait forever
+        let releaser = &mut *file_order.releaser.lock().unwrap();
+        releaser
+            .pending
+            .insert(index, std::mem::take(&mut *buffer.lock().unwrap()));
+        while let Some(deliveries) = releaser.pending.remove(&releaser.next) {
+            releaser.next += 1;
+            for delivery in deliveries {
+                // Buffered results are numbered as they're sent on, so that their numbers stay in order
+                let delivery = match delivery {
+                    Delivery::Match(mut result) => {
+                        result.seq = self.shared.next_seq.fetch_add(1, Ordering::SeqCst);
+                        Delivery::Match(result)
+                    }
+                    Delivery::Group(_, path, results) => Delivery::Group(
+                        self.shared.next_seq.fetch_add(1, Ordering::SeqCst),
+                        path,
+                        results,
+                    ),
+                    delivery => delivery,
+                };
+                if !self.results.send(delivery) {
+                    self.shared.cancel();
+                }
+            }
+        }
+        result
+    }
+
+    /// Searches a blob's contents as if they were in a file at its path, for `searchBlobs`.
+    ///
+    /// Options that need to read the file again (`headingPattern`, `includeFileMetadata`, and the region
+    /// and chunk options) don't apply.
+    pub(crate) fn search_blob(
+        &self,
+        searcher: &mut Searcher,
+        sink: &mut JSCallbackSink,
+        blob: &Blob,
+    ) -> Result<(), RipgrepjsError> {
+        if !self.handle_unread_file(sink, &blob.path, FileContents::Blob(&blob.data))? {
+            return Ok(());
+        }
+
+        sink.start_file(&blob.path);
+        if let Some(count) = self.searcher_opts.header_lines {
+            sink.header_lines = Some(Arc::new(read_header_lines(
+                &blob.data[..],
+                count,
+                self.searcher_opts,
+            )?));
+        }
+        let len = match self.searcher_opts.max_bytes_per_file {
+            Some(max_bytes) => (blob.data.len() as u64).min(max_bytes) as usize,
+            None => blob.data.len(),
+        };
+        let data = &blob.data[..len];
+        let result = self.in_file_order(sink, |sink| {
+            searcher.search_slice(self.matcher, data, &mut *sink)
+        });
+        self.bytes_searched
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        self.finish_file(sink, blob.path.clone(), result)
+    }
+
+    /// Reports how searching a file went, and records it if it matched
+    pub(crate) fn finish_file(
+        &self,
+        sink: &JSCallbackSink,
+        path: PathBuf,
+        result: Result<(), RipgrepjsError>,
+    ) -> Result<(), RipgrepjsError> {
+        self.report_progress();
+        if let Err(error) = result {
+            let error = match error {
+                // Like a match that isn't valid UTF-8 with `strictUtf8`, which says which file it was in itself
+                RipgrepjsError::InFile(..) => error,
+                error => RipgrepjsError::InFile(path.clone(), Box::new(error)),
+            };
+            // Running out of memory on one file shouldn't stop the others
+            // from being searched
+            if error.code() == Some("HEAP_LIMIT") {
+                self.report_error(error);
+                self.report_skip(path.clone(), "tooLarge");
+            } else {
+                return Err(error);
+            }
+        } else if sink.quit_on_binary {
+            self.report_skip(path.clone(), "binary");
+        }
+        if sink.reported_match {
+            if self.searcher_opts.count_mode.is_some() && sink.count > 0 {
+                self.counts.lock().unwrap().push((path.clone(), sink.count));
+            }
+            if self.searcher_opts.match_stats {
+                self.match_stats
+                    .lock()
+                    .unwrap()
+                    .push((path.clone(), sink.match_stats));
+            }
+            self.matched_files.lock().unwrap().insert(path);
+        }
+        Ok(())
+    }
+
+    /// Searches a file from start to finish on the current thread
+    fn search_whole_file(
+        &self,
+        searcher: &mut Searcher,
+        sink: &mut JSCallbackSink,
+        file: &std::fs::File,
+    ) -> Result<(), RipgrepjsError> {
+        let mut file = file;
+        let line_terminator = self
+            .searcher_opts
+            .line_terminator
+            .to_line_terminator()
+            .as_byte();
+        let (start, lines_before, length, line_limit) = match self.searcher_opts.region {
+            None => (0, 0, u64::MAX, None),
+            Some(FileRegion::Lines { start, end }) => {
+                let skipped = start.saturating_sub(1);
+                let (offset, _) =
+                    scan_lines(&mut file, line_terminator, |_, lines| lines >= skipped)?;
+                let line_limit = end.map(|end| (end + 1).saturating_sub(start.max(1)));
+                (offset, skipped, u64::MAX, line_limit)
+            }
+            Some(FileRegion::Bytes { start, end }) => {
+                let start = next_line_start(&mut file, start, line_terminator)?;
+                let end = match end {
+                    Some(end) => next_line_start(&mut file, end, line_terminator)?,
+                    None => u64::MAX,
+                };
+                // Counting lines means reading everything before the region, so it's skipped if it's not needed
+                let lines_before = if self.searcher_opts.include_line_numbers {
+                    count_lines_before(&mut file, start, line_terminator)?
+                } else {
+                    0
+                };
+                (start, lines_before, end.saturating_sub(start), None)
+            }
+            Some(FileRegion::Tail { lines }) => {
+                let start = tail_start(&mut file, lines, line_terminator)?;
+                let lines_before = if self.searcher_opts.include_line_numbers {
+                    count_lines_before(&mut file, start, line_terminator)?
+                } else {
+                    0
+                };
+                (start, lines_before, u64::MAX, None)
+            }
+        };
+        file.seek(SeekFrom::Start(start))?;
+        sink.chunk_start = start;
+        sink.line_number_offset = lines_before;
+
+        let max_bytes = self.searcher_opts.max_bytes_per_file.unwrap_or(u64::MAX);
+        let mut reader = CountingReader::new(LineLimitedReader {
+            inner: file.take(length).take(max_bytes),
+            remaining_lines: line_limit,
+            line_terminator,
+        });
+        let result = match self.searcher_opts.multiline_window {
+            Some(window) if self.searcher_opts.multiline_search => {
+                self.search_in_windows(searcher, sink, &mut reader, window)
+            }
+            _ => searcher.search_reader(self.matcher, &mut reader, &mut *sink),
+        };
+        self.bytes_searched
+            .fetch_add(reader.bytes_read, Ordering::Relaxed);
+        result
+    }
+
+    /// Searches a file `window` lines at a time, for `multilineWindow`, so that only up to twice that many
+    /// lines are ever in memory at once.
+    ///
+    /// Each window overlaps the next by `window` lines, and only reports matches starting before the overlap,
+    /// so every match spanning up to `window` lines is found exactly once.
+    fn search_in_windows<R: Read>(
+        &self,
+        searcher: &mut Searcher,
+        sink: &mut JSCallbackSink,
+        reader: R,
+        window: u64,
+    ) -> Result<(), RipgrepjsError> {
+        let line_terminator = self
+            .searcher_opts
+            .line_terminator
+            .to_line_terminator()
+            .as_byte();
+        let mut reader = BufReader::new(reader);
+        let mut buffer = Vec::new();
+        // Where each line in `buffer` ends
+        let mut line_ends = VecDeque::new();
+        let mut window_sink = WindowSink {
+            inner: sink,
+            owned_end: 0,
+            started: false,
+            last: false,
+            stopped: false,
+        };
+        loop {
+            while !window_sink.last && line_ends.len() < (window * 2) as usize {
+                if reader.read_until(line_terminator, &mut buffer)? == 0 {
+                    window_sink.last = true;
+                } else {
+                    line_ends.push_back(buffer.len());
+                }
+            }
+            // Matches starting after the window's own lines are left for the next window, unless there isn't one
+            let owned_lines = (window as usize).min(line_ends.len());
+            let owned_bytes = match owned_lines {
+                0 => 0,
+                lines => line_ends[lines - 1],
+            };
+            window_sink.owned_end = if window_sink.last {
+                u64::MAX
+            } else {
+                owned_bytes as u64
+            };
+            searcher.search_slice(self.matcher, &buffer, &mut window_sink)?;
+            if window_sink.last || window_sink.stopped {
+                return Ok(());
+            }
+
+            buffer.drain(..owned_bytes);
+            line_ends.drain(..owned_lines);
+            for end in line_ends.iter_mut() {
+                *end -= owned_bytes;
+            }
+            window_sink.inner.chunk_start += owned_bytes as u64;
+            window_sink.inner.line_number_offset += owned_lines as u64;
+        }
+    }
+
+    /// Searches a file in line-aligned chunks in parallel, for `chunkSize`.
+    ///
+    /// Each chunk's results are held back until every chunk before it has been sent,
+    /// so they arrive in the same order (and with the same line numbers) as they would from `search_whole_file`.
+    fn search_in_chunks(
+        &self,
+        sink: &mut JSCallbackSink,
+        path: &Path,
+        chunk_size: u64,
+    ) -> Result<(), RipgrepjsError> {
+        let mut len = std::fs::metadata(path)?.len();
+        if let Some(max_bytes) = self.searcher_opts.max_bytes_per_file {
+            len = len.min(max_bytes);
+        }
+        let line_terminator = self
+            .searcher_opts
+            .line_terminator
+            .to_line_terminator()
+            .as_byte();
+        let boundaries = chunk_boundaries(path, len, chunk_size, line_terminator)?;
+
+        let merger = Mutex::new(ChunkMerger {
+            next: 0,
+            pending: BTreeMap::new(),
+            lines_before: 0,
+            reported_match: false,
+            count: 0,
+            match_stats: MatchStats::default(),
+            quit_on_binary: false,
+        });
+        let file_metadata = sink.file_metadata;
+        let header_lines = sink.header_lines.clone();
+        let siblings_of = sink.siblings_of.clone();
+        let reported_path = sink.path.clone();
+        boundaries.par_windows(2).enumerate().try_for_each(
+            |(index, bounds)| -> Result<(), RipgrepjsError> {
+                let buffer = Arc::new(Mutex::new(Vec::new()));
+                let mut chunk_sink = JSCallbackSink::new(
+                    ResultSender::Buffer(Arc::clone(&buffer)),
+                    self.searcher_opts,
+                    &self.shared,
+                    self.matcher,
+                );
+                chunk_sink.file_metadata = file_metadata;
+                chunk_sink.header_lines = header_lines.clone();
+                chunk_sink.siblings_of = siblings_of.clone();
+                chunk_sink.path = reported_path.clone();
+                chunk_sink.chunk_start = bounds[0];
+
+                let mut file = std::fs::File::open(path)?;
+                file.seek(SeekFrom::Start(bounds[0]))?;
+                let mut reader = CountingReader::new(file.take(bounds[1] - bounds[0]));
+                if self.searcher_opts.include_line_numbers {
+                    reader.line_terminator = Some(line_terminator);
+                }
+                let result = self.searcher_opts.to_searcher().search_reader(
+                    self.matcher,
+                    &mut reader,
+                    &mut chunk_sink,
+                );
+                self.bytes_searched
+                    .fetch_add(reader.bytes_read, Ordering::Relaxed);
+                result?;
+
+                let chunk = SearchedChunk {
+                    results: std::mem::take(&mut *buffer.lock().unwrap()),
+                    lines: reader.lines,
+                    reported_match: chunk_sink.reported_match,
+                    count: chunk_sink.count,
+                    match_stats: chunk_sink.match_stats,
+                    quit_on_binary: chunk_sink.quit_on_binary,
+                };
+                let merger = &mut *merger.lock().unwrap();
+                merger.pending.insert(index, chunk);
+                while let Some(chunk) = merger.pending.remove(&merger.next) {
+                    merger.next += 1;
+                    if merger.quit_on_binary {
+                        continue;
+                    }
+                    for delivery in chunk.results {
+                        let sent = match delivery {
+                            Delivery::Match(mut result) => {
+                                result.line_number = result
+                                    .line_number
+                                    .map(|line_number| line_number + merger.lines_before);
+                                result.seq = self.shared.next_seq.fetch_add(1, Ordering::SeqCst);
+                                self.results.send(Delivery::Match(result))
+                            }
+                            Delivery::Error(error) => self.results.send(Delivery::Error(
+                                error.offset_line_number(merger.lines_before),
+                            )),
+                            delivery => self.results.send(delivery),
+                        };
+                        if !sent {
+                            self.shared.cancel();
+                        }
+                    }
+                    merger.lines_before += chunk.lines;
+                    merger.reported_match |= chunk.reported_match;
+                    merger.count += chunk.count;
+                    merger.match_stats.add(chunk.match_stats);
+                    merger.quit_on_binary = chunk.quit_on_binary;
+                }
+                Ok(())
+            },
+        )?;
+
+        let merger = merger.into_inner().unwrap();
+        sink.reported_match = merger.reported_match;
+        sink.count = merger.count;
+        sink.match_stats = merger.match_stats;
+        sink.quit_on_binary = merger.quit_on_binary;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a file for a test to the temporary directory, returning its path
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ripgrepjs-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn chunks_end_after_a_line_terminator() {
+        let path = temp_file("chunks.txt", b"aaaa\nbbbb\ncccc\ndddd");
+        // The nominal boundaries are 7 bytes after the last one, moved to the end of their lines
+        assert_eq!(chunk_boundaries(&path, 19, 7, b'\n').unwrap(), [0, 10, 19]);
+        // A line ending right at a nominal boundary ends the chunk there
+        assert_eq!(
+            chunk_boundaries(&path, 19, 5, b'\n').unwrap(),
+            [0, 5, 10, 15, 19]
+        );
+        // Only lines ending in the line terminator count
+        assert_eq!(chunk_boundaries(&path, 19, 5, b'c').unwrap(), [0, 11, 19]);
+        // A file no bigger than a chunk is one chunk
+        assert_eq!(chunk_boundaries(&path, 19, 19, b'\n').unwrap(), [0, 19]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_line_longer_than_a_chunk_is_never_split() {
+        let path = temp_file("long-line.txt", b"xxxxxxxxxx\nyy\n");
+        assert_eq!(chunk_boundaries(&path, 14, 3, b'\n').unwrap(), [0, 11, 14]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Where `tail_start` finds the last `lines` lines of `contents` start
+    fn tail_of(contents: &[u8], lines: u64) -> u64 {
+        tail_start(&mut std::io::Cursor::new(contents), lines, b'\n').unwrap()
+    }
+
+    #[test]
+    fn tail_start_counts_lines_back_from_the_end() {
+        assert_eq!(tail_of(b"one\ntwo\nthree\n", 1), 8);
+        assert_eq!(tail_of(b"one\ntwo\nthree\n", 2), 4);
+        // The last line doesn't need a terminator to count
+        assert_eq!(tail_of(b"one\ntwo\nthree", 1), 8);
+        assert_eq!(tail_of(b"one\ntwo\n\n", 1), 8);
+        // Fewer lines than were asked for means searching everything, and none means searching nothing
+        assert_eq!(tail_of(b"one\ntwo\nthree\n", 3), 0);
+        assert_eq!(tail_of(b"one\ntwo\nthree\n", 10), 0);
+        assert_eq!(tail_of(b"", 1), 0);
+        assert_eq!(tail_of(b"one\ntwo\nthree\n", 0), 14);
+    }
+
+    #[test]
+    fn tail_start_finds_lines_across_reads() {
+        // Lines long enough that reading backwards takes several reads, which split some of them
+        let line = [b"x".repeat(40 * 1024), b"\n".to_vec()].concat();
+        let contents = line.repeat(5);
+        for lines in 1..5 {
+            assert_eq!(tail_of(&contents, lines), (line.len() as u64) * (5 - lines));
+        }
+        assert_eq!(tail_of(&contents, 5), 0);
+    }
+}
@@ -0,0 +1,1222 @@
+//! The `Sink` that turns what `grep` finds in a file into results
+
+use std::{
+    collections::{HashSet, VecDeque},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    str::Utf8Error,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+
+use grep::{
+    matcher::{Captures, LineTerminator, Matcher},
+    searcher::{Searcher, SinkContext, SinkContextKind, SinkFinish, SinkMatch},
+};
+use serde_json::{json, Value as JsonValue};
+
+use crate::{
+    error::RipgrepjsError,
+    matcher::SearchMatcher,
+    options::{
+        BinaryDetection, BinaryOutput, ColumnEncoding, ContextMode, CountMode, SearcherOptions,
+        UniqueScope,
+    },
+    results::{
+        file_uri, json_text, to_lossy_string, CaptureSpan, Delivery, FileMetadata, JsonStats,
+        LspLocation, LspPosition, MatchRange, MatchResult, MatchStats, ResultSender, SubMatch,
+    },
+    search::SharedSearchState,
+};
+
+/// Sink that executes a JavaScript callback on each match
+///
+/// Every file is searched on its own, so nothing (context, matches, or deduplication with `uniqueScope: "file"`)
+/// carries over from one file to the next; see `start_file`.
+pub(crate) struct JSCallbackSink<'a> {
+    // Sends a match towards the JavaScript thread so that it can be passed to the callback
+    pub(crate) results: ResultSender,
+    pub(crate) options: &'a SearcherOptions,
+    shared: &'a SharedSearchState,
+    // Used to find match ranges within matched lines, for `includeMatchRanges`
+    matcher: &'a SearchMatcher,
+    // Set whenever a match is reported, so that the caller can tell which files matched
+    pub(crate) reported_match: bool,
+    // Matched text that has already been reported from the current file, for `uniqueScope: "file"`
+    seen_in_file: HashSet<Vec<u8>>,
+    // The last `before_context` lines seen, for `contextMode: "inline"`
+    recent_lines: VecDeque<String>,
+    // Matches that are held back until their after-context has been seen
+    awaiting_after_context: VecDeque<MatchResult>,
+    // Every line in the current span of overlapping context windows, and the matches in it, for `mergeAdjacent`
+    merged_lines: Vec<String>,
+    merged_matches: Vec<MatchResult>,
+    // Set if matches in the current span were left out for `maxMatchesPerGroup`
+    merged_matches_truncated: bool,
+    // Where the first match's lines start in `merged_lines`
+    merged_first_match: usize,
+    // Metadata of the file being searched, for `includeFileMetadata`
+    pub(crate) file_metadata: Option<FileMetadata>,
+    // Set if the searcher stopped reading the last file because it looked binary
+    pub(crate) quit_on_binary: bool,
+    // The error for a match that isn't valid UTF-8, with `strictUtf8`, until it's returned to the searcher
+    invalid_utf8: Option<RipgrepjsError>,
+    // What's been counted in the current file, for `countMode`
+    pub(crate) count: u64,
+    // The current file's matching lines added up, for `matchStats`
+    pub(crate) match_stats: MatchStats,
+    // Reads the current file alongside the searcher to find headings, for `headingPattern`
+    pub(crate) heading: Option<HeadingTracker>,
+    // The first lines of the current file, shared by each of its matches, for `headerLines`
+    pub(crate) header_lines: Option<Arc<Vec<String>>>,
+    // The file being searched, whose siblings are looked up on its first match, for `includeSiblings`
+    pub(crate) siblings_of: Option<Arc<Path>>,
+    // The other files in the current file's directory, shared by each of its matches, once they've been looked up
+    siblings: Option<Arc<Vec<String>>>,
+    // Where in the file the searcher started reading, when searching a chunk or region of it
+    pub(crate) chunk_start: u64,
+    // The number of lines before where the searcher started reading, for `startLine` and `startByte`
+    pub(crate) line_number_offset: u64,
+    // The path of the file being searched, as it's reported
+    pub(crate) path: Option<Arc<str>>,
+    // Results from the current file that are held back until it's finished, for `groupByFile`
+    file_group: Vec<MatchResult>,
+    // The number of matches reported from the current file, for `onFileEnd`
+    file_matches: u64,
+    // Statistics for the current file's `end` message, for `jsonLines`
+    json_file: JsonStats,
+    // When the current file started being searched, for `jsonLines`
+    json_started: Option<Instant>,
+    // Set once the current file's `begin` message has been sent, which is only once it has a result
+    json_begun: bool,
+    // The current file's URI, for `lspMode`
+    lsp_uri: Option<Arc<str>>,
+}
+
+impl<'a> JSCallbackSink<'a> {
+    /// on_match JS function signature: `(results: {seq: number, matchedLines: string[], lineNumber?: number}) => void;`
+    ///
+    /// `matchedLines` is an array of lines that matchsed the search pattern.
+    /// It should have length 1 unless multiline searching is enabled.
+    ///
+    /// `seq` counts up from 0 in the order results are sent, across every thread in the search.
+    pub(crate) fn new(
+        results: ResultSender,
+        options: &'a SearcherOptions,
+        shared: &'a SharedSearchState,
+        matcher: &'a SearchMatcher,
+    ) -> Self {
+        Self {
+            results,
+            options,
+            shared,
+            matcher,
+            reported_match: false,
+            seen_in_file: HashSet::new(),
+            recent_lines: VecDeque::new(),
+            awaiting_after_context: VecDeque::new(),
+            merged_lines: Vec::new(),
+            merged_matches: Vec::new(),
+            merged_matches_truncated: false,
+            merged_first_match: 0,
+            file_metadata: None,
+            quit_on_binary: false,
+            invalid_utf8: None,
+            count: 0,
+            match_stats: MatchStats::default(),
+            heading: None,
+            header_lines: None,
+            siblings_of: None,
+            siblings: None,
+            chunk_start: 0,
+            line_number_offset: 0,
+            path: None,
+            file_group: Vec::new(),
+            file_matches: 0,
+            json_file: JsonStats::default(),
+            json_started: None,
+            json_begun: false,
+            lsp_uri: None,
+        }
+    }
+
+    /// Forgets everything about the last file searched, before searching another.
+    ///
+    /// Each thread reuses one sink for every file it searches, so anything per-file that isn't reset here
+    /// (or, for state only the searcher can update, in `begin`) would leak from one file into the next.
+    /// This also covers searching in chunks, where the searcher never uses this sink itself.
+    pub(crate) fn start_file(&mut self, path: &Path) {
+        self.reported_match = false;
+        self.path = Some(self.options.reported_path(path).into());
+        if self.options.lsp_mode {
+            self.lsp_uri = Some(file_uri(path).into());
+        }
+        self.file_metadata = None;
+        self.heading = None;
+        self.header_lines = None;
+        self.siblings_of = None;
+        self.siblings = None;
+        self.chunk_start = 0;
+        self.line_number_offset = 0;
+        self.quit_on_binary = false;
+        self.count = 0;
+        self.match_stats = MatchStats::default();
+    }
+
+    /// The other files in the current file's directory, for `includeSiblings`, looked up on the file's first match.
+    fn siblings(&mut self) -> Option<Arc<Vec<String>>> {
+        if self.siblings.is_none() {
+            let path = self.siblings_of.take()?;
+            let name = path.file_name()?.to_string_lossy();
+            let files = self.shared.directory_files(path.parent()?)?;
+            self.siblings = Some(Arc::new(
+                files
+                    .iter()
+                    .filter(|file| **file != name)
+                    .cloned()
+                    .collect(),
+            ));
+        }
+        self.siblings.clone()
+    }
+
+    /// Checks whether this matched text has been reported before, recording it if not.
+    fn is_duplicate(&mut self, searcher: &Searcher, bytes: &[u8]) -> bool {
+        self.is_duplicate_text(without_line_terminator(searcher, bytes))
+    }
+
+    /// Like `is_duplicate`, but for text that doesn't end with a line terminator.
+    fn is_duplicate_text(&mut self, text: &[u8]) -> bool {
+        let key = text.to_vec();
+        match self.options.unique {
+            Some(UniqueScope::File) => !self.seen_in_file.insert(key),
+            Some(UniqueScope::Search) => !self.shared.seen_matches.lock().unwrap().insert(key),
+            None => false,
+        }
+    }
+
+    /// Reports each (non-empty) match in some matched lines as a result of its own, for `extractMatches`.
+    ///
+    /// Each one counts as a match towards `maxTotalMatches`, and has the line number of the line it starts on.
+    fn extract_matches(
+        &mut self,
+        searcher: &Searcher,
+        matched: &SinkMatch,
+    ) -> Result<bool, RipgrepjsError> {
+        let bytes = without_line_terminator(searcher, matched.bytes());
+        let mut ranges = Vec::new();
+        self.matcher.find_iter(bytes, |range| {
+            if !range.is_empty() {
+                ranges.push(range);
+            }
+            true
+        })?;
+
+        let line_terminator = searcher.line_terminator().as_byte();
+        for range in ranges {
+            let text = &bytes[range];
+            if self.is_duplicate_text(text) {
+                continue;
+            }
+            let keep_searching = match self.shared.claim_match() {
+                Some(keep_searching) => keep_searching && !self.options.first_match_per_file,
+                None => return Ok(false),
+            };
+            self.reported_match = true;
+            self.file_matches += 1;
+
+            // Matches after the first line (in multiline mode) are numbered by the line they start on
+            let line_number = matched.line_number().map(|line_number| {
+                let lines_before = bytes[..range.start()]
+                    .iter()
+                    .filter(|&&byte| byte == line_terminator)
+                    .count();
+                line_number + lines_before as u64
+            });
+            let result = MatchResult::new(
+                std::iter::once(text),
+                text,
+                matched.absolute_byte_offset() + range.start() as u64,
+                line_number,
+                self,
+            );
+            self.send(result);
+            if !keep_searching {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Cuts some matched lines down to their first match and up to `chars` characters either side of it,
+    /// for `contextChars`. Each end that was cut off is marked with `…`.
+    ///
+    /// Returns `None` if the pattern doesn't match in them, as with `invertMatch`.
+    fn snippet(
+        &self,
+        searcher: &Searcher,
+        matched: &SinkMatch,
+        chars: usize,
+    ) -> Result<Option<String>, RipgrepjsError> {
+        let bytes = without_line_terminator(searcher, matched.bytes());
+        let first_match = match self.matcher.find(bytes)? {
+            Some(first_match) => first_match,
+            None => return Ok(None),
+        };
+        let replacement = &self.options.invalid_char_replacement;
+        let before = to_lossy_string(&bytes[..first_match.start()], replacement);
+        let after = to_lossy_string(&bytes[first_match.end()..], replacement);
+
+        let mut snippet = String::new();
+        let cut_before = before.chars().count().saturating_sub(chars);
+        if cut_before > 0 {
+            snippet.push('…');
+        }
+        snippet.extend(before.chars().skip(cut_before));
+        snippet.push_str(&to_lossy_string(&bytes[first_match], replacement));
+        let mut after = after.chars();
+        snippet.extend(after.by_ref().take(chars));
+        if after.next().is_some() {
+            snippet.push('…');
+        }
+        Ok(Some(snippet))
+    }
+
+    /// Puts `before` and `after` around every match in some matched lines, for `highlight`.
+    ///
+    /// Touching matches are highlighted together, so the strings are never nested or left empty.
+    fn highlight(
+        &self,
+        searcher: &Searcher,
+        matched: &SinkMatch,
+        before: &str,
+        after: &str,
+    ) -> Result<String, RipgrepjsError> {
+        let bytes = if self.options.keep_line_terminator {
+            matched.bytes()
+        } else {
+            without_line_terminator(searcher, matched.bytes())
+        };
+        let mut ranges: Vec<std::ops::Range<usize>> = Vec::new();
+        if !self.options.invert_match {
+            self.matcher
+                .find_iter(without_line_terminator(searcher, bytes), |range| {
+                    match ranges.last_mut() {
+                        Some(last) if range.start() <= last.end => {
+                            last.end = last.end.max(range.end())
+                        }
+                        _ if range.is_empty() => {}
+                        _ => ranges.push(range.start()..range.end()),
+                    }
+                    true
+                })?;
+        }
+
+        let replacement = &self.options.invalid_char_replacement;
+        let mut highlighted = String::new();
+        let mut position = 0;
+        for range in ranges {
+            highlighted.push_str(&to_lossy_string(&bytes[position..range.start], replacement));
+            highlighted.push_str(before);
+            highlighted.push_str(&to_lossy_string(&bytes[range.clone()], replacement));
+            highlighted.push_str(after);
+            position = range.end;
+        }
+        highlighted.push_str(&to_lossy_string(&bytes[position..], replacement));
+        Ok(self.options.tidy_line(highlighted))
+    }
+
+    /// Finds where each capture group matched, for every match within some matched lines, for `includeCaptures`.
+    ///
+    /// Offsets are relative to the start of the first line, like `find_match_ranges`'s.
+    fn find_captures(
+        &self,
+        searcher: &Searcher,
+        matched: &SinkMatch,
+    ) -> Result<Vec<Vec<Option<CaptureSpan>>>, RipgrepjsError> {
+        let bytes = without_line_terminator(searcher, matched.bytes());
+        let offset = |byte_offset| match self.options.column_encoding {
+            Some(encoding) => encoding.offset_in(bytes, byte_offset),
+            None => byte_offset,
+        };
+        let mut captures = self.matcher.new_captures()?;
+        let mut all_captures = Vec::new();
+        self.matcher
+            .captures_iter(bytes, &mut captures, |captures| {
+                let groups = (0..captures.len())
+                    .map(|index| {
+                        captures.get(index).map(|range| CaptureSpan {
+                            index,
+                            name: self.options.capture_names.get(index).cloned().flatten(),
+                            start: offset(range.start()),
+                            end: offset(range.end()),
+                            text: to_lossy_string(
+                                &bytes[range],
+                                &self.options.invalid_char_replacement,
+                            ),
+                        })
+                    })
+                    .collect();
+                all_captures.push(groups);
+                true
+            })?;
+        Ok(all_captures)
+    }
+
+    /// Finds every match of the `subPattern` within some matched lines.
+    ///
+    /// Offsets are relative to the start of the first line, like `find_match_ranges`'s.
+    fn find_sub_matches(
+        &self,
+        searcher: &Searcher,
+        matched: &SinkMatch,
+        sub_matcher: &SearchMatcher,
+    ) -> Result<Vec<SubMatch>, RipgrepjsError> {
+        let bytes = without_line_terminator(searcher, matched.bytes());
+        let offset = |byte_offset| match self.options.column_encoding {
+            Some(encoding) => encoding.offset_in(bytes, byte_offset),
+            None => byte_offset,
+        };
+        let mut sub_matches = Vec::new();
+        sub_matcher.find_iter(bytes, |range| {
+            sub_matches.push(SubMatch {
+                start: offset(range.start()),
+                end: offset(range.end()),
+                text: to_lossy_string(&bytes[range], &self.options.invalid_char_replacement),
+            });
+            true
+        })?;
+        Ok(sub_matches)
+    }
+
+    /// Finds where the pattern matches within some matched lines, for `includeMatchRanges`.
+    ///
+    /// Offsets are relative to the start of the first line. At most `max_matches_per_line` ranges
+    /// are kept from each line (though the rest are still counted towards the line's total);
+    /// the second return value is whether any were left out.
+    fn find_match_ranges(
+        &self,
+        searcher: &Searcher,
+        matched: &SinkMatch,
+    ) -> (Vec<MatchRange>, bool) {
+        let bytes = without_line_terminator(searcher, matched.bytes());
+        let line_ends = matched
+            .lines()
+            .scan(0, |end, line| {
+                *end += line.len();
+                Some(*end)
+            })
+            .collect::<Vec<_>>();
+
+        let mut ranges = Vec::new();
+        let mut truncated = false;
+        let mut line = 0;
+        // How many times the pattern matched on each line, including matches past `max_matches_per_line`
+        let mut matches_per_line = vec![0; line_ends.len()];
+        // The line each kept range starts on
+        let mut range_lines = Vec::new();
+        let _ = self.matcher.find_iter(bytes, |range| {
+            while line + 1 < line_ends.len() && range.start() >= line_ends[line] {
+                line += 1;
+            }
+            matches_per_line[line] += 1;
+            let match_index = matches_per_line[line] - 1;
+            match self.options.max_matches_per_line {
+                Some(max) if match_index >= max => truncated = true,
+                _ => {
+                    let (start, end) = match self.options.column_encoding {
+                        Some(encoding) => (
+                            encoding.offset_in(bytes, range.start()),
+                            encoding.offset_in(bytes, range.end()),
+                        ),
+                        None => (range.start(), range.end()),
+                    };
+                    ranges.push(MatchRange {
+                        start,
+                        end,
+                        match_index,
+                        total_on_line: 0,
+                    });
+                    range_lines.push(line);
+                }
+            }
+            true
+        });
+        // Each line's total is only known once every match on it has been found
+        for (range, line) in ranges.iter_mut().zip(range_lines) {
+            range.total_on_line = matches_per_line[line];
+        }
+        (ranges, truncated)
+    }
+}
+
+/// Measures a line's leading spaces and tabs in columns, with each tab reaching the next multiple of `tab_width`.
+pub(crate) fn indent_width(line: &[u8], tab_width: usize) -> usize {
+    let mut width = 0;
+    for byte in line {
+        match byte {
+            b' ' => width += 1,
+            b'\t' => width += tab_width - width % tab_width,
+            _ => break,
+        }
+    }
+    width
+}
+
+/// Strips the searcher's line terminator from the end of some matched bytes, if it's there.
+pub(crate) fn without_line_terminator<'b>(searcher: &Searcher, bytes: &'b [u8]) -> &'b [u8] {
+    strip_line_terminator(searcher.line_terminator(), bytes)
+}
+
+/// Strips a line terminator from the end of some bytes, if it's there.
+pub(crate) fn strip_line_terminator(line_terminator: LineTerminator, bytes: &[u8]) -> &[u8] {
+    if line_terminator.is_crlf() && bytes.ends_with(b"\r\n") {
+        &bytes[..bytes.len() - 2]
+    } else if bytes.last() == Some(&line_terminator.as_byte()) {
+        &bytes[..bytes.len() - 1]
+    } else {
+        bytes
+    }
+}
+
+/// Reads the first `count` lines of a file (or fewer, if it's shorter), for `headerLines`.
+///
+/// The lines are converted like reported lines, but never keep their line terminators.
+pub(crate) fn read_header_lines(
+    mut reader: impl BufRead,
+    count: usize,
+    options: &SearcherOptions,
+) -> std::io::Result<Vec<String>> {
+    let line_terminator = options.line_terminator.to_line_terminator();
+    let mut lines = Vec::with_capacity(count);
+    let mut line = Vec::new();
+    while lines.len() < count {
+        line.clear();
+        if reader.read_until(line_terminator.as_byte(), &mut line)? == 0 {
+            break;
+        }
+        lines.push(options.tidy_line(to_lossy_string(
+            strip_line_terminator(line_terminator, &line),
+            &options.invalid_char_replacement,
+        )));
+    }
+    Ok(lines)
+}
+
+/// Finds the closest "heading" line before each match in a file, for `headingPattern`.
+///
+/// The searcher only shows the sink the lines that match (and their context), so this reads
+/// the file again separately. Matches arrive in order, so it only ever has to read forward.
+pub(crate) struct HeadingTracker {
+    reader: BufReader<PositionedReader>,
+    // The offset of the start of `next_line`
+    position: u64,
+    // A line that's been read but not yet passed, since a match starts in it
+    next_line: Vec<u8>,
+    last_heading: Option<String>,
+}
+
+impl HeadingTracker {
+    pub(crate) fn new(file: Arc<std::fs::File>) -> Self {
+        Self {
+            reader: BufReader::new(PositionedReader { file, position: 0 }),
+            position: 0,
+            next_line: Vec::new(),
+            last_heading: None,
+        }
+    }
+
+    /// Returns the last heading line that ends at or before `offset`.
+    fn heading_before(
+        &mut self,
+        searcher: &Searcher,
+        matcher: &SearchMatcher,
+        offset: u64,
+        replacement: &str,
+    ) -> Result<Option<String>, RipgrepjsError> {
+        loop {
+            if self.next_line.is_empty()
+                && self
+                    .reader
+                    .read_until(searcher.line_terminator().as_byte(), &mut self.next_line)?
+                    == 0
+            {
+                break;
+            }
+            if self.position + self.next_line.len() as u64 > offset {
+                break;
+            }
+            let line = without_line_terminator(searcher, &self.next_line);
+            if matcher.is_match(line)? {
+                self.last_heading = Some(to_lossy_string(line, replacement));
+            }
+            self.position += self.next_line.len() as u64;
+            self.next_line.clear();
+        }
+        Ok(self.last_heading.clone())
+    }
+}
+
+/// Reads a file from its own position, putting the file's position back after each read, so that
+/// it can read a file while it's being searched through the same descriptor
+pub(crate) struct PositionedReader {
+    file: Arc<std::fs::File>,
+    position: u64,
+}
+
+impl Read for PositionedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut file = &*self.file;
+        let resume_at = file.stream_position()?;
+        file.seek(SeekFrom::Start(self.position))?;
+        let read = file.read(buf);
+        file.seek(SeekFrom::Start(resume_at))?;
+        let read = read?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<'a> JSCallbackSink<'a> {
+    /// Sends a match to the JavaScript callback, or holds it back until the end of the file with `groupByFile`
+    fn send(&mut self, mut result: MatchResult) {
+        if self.options.binary_output == BinaryOutput::Text {
+            if let Some(error) = result.lines.iter().find_map(|line| line.as_ref().err()) {
+                let error = self.invalid_utf8_error(*error, result.line_number);
+                if self.options.strict_utf8 {
+                    // Returned from the searcher's next call to the sink, which stops the search
+                    self.invalid_utf8.get_or_insert(error);
+                    self.shared.fail();
+                } else {
+                    self.shared.error_reported.store(true, Ordering::Relaxed);
+                    if !self.results.send(Delivery::Error(error)) {
+                        self.shared.cancel();
+                    }
+                }
+                return;
+            }
+        }
+        // Buffered results are numbered once they're sent on, so that their numbers stay in order
+        if !matches!(self.results, ResultSender::Buffer(_)) {
+            result.seq = self.shared.next_seq.fetch_add(1, Ordering::SeqCst);
+        }
+        if self.options.group_by_file {
+            self.file_group.push(result);
+        } else if !self.results.send(Delivery::Match(result)) {
+            self.shared.cancel();
+        }
+    }
+
+    /// The error for a match that isn't valid UTF-8, along with its file and line, which is reported instead of it
+    fn invalid_utf8_error(&self, error: Utf8Error, line_number: Option<u64>) -> RipgrepjsError {
+        let mut error = RipgrepjsError::StringConversion(error);
+        if let Some(line_number) = line_number {
+            error = RipgrepjsError::AtLine(line_number, Box::new(error));
+        }
+        if let Some(path) = &self.path {
+            error = RipgrepjsError::InFile(PathBuf::from(&**path), Box::new(error));
+        }
+        error
+    }
+
+    /// Returns the error for a match that isn't valid UTF-8 with `strictUtf8`, if there was one,
+    /// so that the searcher stops and the error ends up in the summary's `errors`.
+    fn check_invalid_utf8(&mut self) -> Result<(), RipgrepjsError> {
+        match self.invalid_utf8.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends one message for `jsonLines`, counting it towards the file's `bytes_printed`
+    fn send_json(&mut self, message: JsonValue) {
+        let line = message.to_string();
+        // Counting the newline it'll be written with
+        self.json_file.bytes_printed += line.len() as u64 + 1;
+        if !self.results.send(Delivery::JsonLine(line)) {
+            self.shared.cancel();
+        }
+    }
+
+    /// The current file's path, as `jsonLines` messages have it
+    fn json_path(&self) -> JsonValue {
+        match &self.path {
+            Some(path) => json!({ "text": &**path }),
+            None => JsonValue::Null,
+        }
+    }
+
+    /// Sends a `match` or `context` message for `jsonLines`, after the file's `begin` message if it's the first.
+    fn send_json_lines(
+        &mut self,
+        kind: &str,
+        bytes: &[u8],
+        absolute_byte_offset: u64,
+        line_number: Option<u64>,
+        submatches: Vec<JsonValue>,
+    ) {
+        if !self.json_begun {
+            self.json_begun = true;
+            let path = self.json_path();
+            self.send_json(json!({ "type": "begin", "data": { "path": path } }));
+        }
+        let message = json!({
+            "type": kind,
+            "data": {
+                "path": self.json_path(),
+                "lines": json_text(bytes),
+                "line_number": line_number.map(|line_number| line_number + self.line_number_offset),
+                "absolute_offset": absolute_byte_offset + self.chunk_start,
+                "submatches": submatches,
+            },
+        });
+        self.send_json(message);
+    }
+
+    /// Sends a `match` message for `jsonLines`, with every match of the pattern in the lines as a submatch.
+    fn send_json_match(
+        &mut self,
+        searcher: &Searcher,
+        matched: &SinkMatch,
+    ) -> Result<(), RipgrepjsError> {
+        let mut submatches = Vec::new();
+        if !self.options.invert_match {
+            let bytes = without_line_terminator(searcher, matched.bytes());
+            self.matcher.find_iter(bytes, |range| {
+                if !range.is_empty() {
+                    submatches.push(json!({
+                        "match": json_text(&bytes[range]),
+                        "start": range.start(),
+                        "end": range.end(),
+                    }));
+                }
+                true
+            })?;
+        }
+        self.json_file.matched_lines += matched.lines().count() as u64;
+        self.json_file.matches += submatches.len() as u64;
+        self.send_json_lines(
+            "match",
+            matched.bytes(),
+            matched.absolute_byte_offset(),
+            matched.line_number(),
+            submatches,
+        );
+        Ok(())
+    }
+
+    /// Sends a location for each match of the pattern in the lines, for `lspMode`.
+    ///
+    /// Inverted matches have nothing in them that matched, so the whole line is its location.
+    fn send_lsp_locations(
+        &mut self,
+        searcher: &Searcher,
+        matched: &SinkMatch,
+    ) -> Result<(), RipgrepjsError> {
+        let bytes = without_line_terminator(searcher, matched.bytes());
+        let mut ranges = Vec::new();
+        if self.options.invert_match {
+            ranges.push(0..bytes.len());
+        } else {
+            self.matcher.find_iter(bytes, |range| {
+                ranges.push(range.start()..range.end());
+                true
+            })?;
+        }
+
+        let terminator = searcher.line_terminator().as_byte();
+        let first_line = matched.line_number().unwrap_or(1) - 1 + self.line_number_offset;
+        let position = |offset: usize| {
+            let line_start = memchr::memrchr(terminator, &bytes[..offset]).map_or(0, |end| end + 1);
+            LspPosition {
+                line: first_line
+                    + memchr::memchr_iter(terminator, &bytes[..line_start]).count() as u64,
+                character: ColumnEncoding::Utf16
+                    .offset_in(&bytes[line_start..], offset - line_start),
+            }
+        };
+        for range in ranges {
+            let location = LspLocation {
+                uri: self.lsp_uri.clone(),
+                start: position(range.start),
+                end: position(range.end),
+            };
+            if !self.results.send(Delivery::Location(location)) {
+                self.shared.cancel();
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the file's `end` message for `jsonLines`, if it had a `begin` message,
+    /// and adds its statistics to the search's.
+    fn finish_json(&mut self, finish: &SinkFinish) {
+        self.json_file.elapsed = self
+            .json_started
+            .map_or(Duration::ZERO, |started| started.elapsed());
+        self.json_file.searches = 1;
+        self.json_file.searches_with_match = u64::from(self.json_file.matched_lines > 0);
+        self.json_file.bytes_searched = finish.byte_count();
+        if self.json_begun {
+            let message = json!({
+                "type": "end",
+                "data": {
+                    "path": self.json_path(),
+                    "binary_offset": finish.binary_byte_offset(),
+                    "stats": self.json_file.to_json(),
+                },
+            });
+            self.send_json(message);
+        }
+        self.shared.json_stats.lock().unwrap().add(&self.json_file);
+    }
+
+    /// Sends every result held back from the current file as one group, for `groupByFile`
+    fn send_file_group(&mut self) {
+        if self.file_group.is_empty() {
+            return;
+        }
+        // Like matches, buffered groups are numbered once they're sent on
+        let seq = if matches!(self.results, ResultSender::Buffer(_)) {
+            0
+        } else {
+            self.shared.next_seq.fetch_add(1, Ordering::SeqCst)
+        };
+        let results = std::mem::take(&mut self.file_group);
+        if !self
+            .results
+            .send(Delivery::Group(seq, self.path.clone(), results))
+        {
+            self.shared.cancel();
+        }
+    }
+
+    /// Sends a path to the JavaScript callback, for `matchPathsOnly` and `dryRun`
+    pub(crate) fn send_path(&self, path: String) {
+        let seq = self.shared.next_seq.fetch_add(1, Ordering::SeqCst);
+        if !self.results.send(Delivery::Path(seq, path)) {
+            self.shared.cancel();
+        }
+    }
+
+    /// Records a line for `contextMode: "inline"`, whether it matched or not.
+    ///
+    /// Each line goes into the after-context of every match still waiting for some,
+    /// and is remembered as potential before-context for upcoming matches.
+    fn record_inline_context_line(&mut self, mut line: &[u8]) {
+        if !self.options.keep_line_terminator {
+            line = strip_line_terminator(self.options.line_terminator.to_line_terminator(), line);
+        }
+        let line = self.options.tidy_line(to_lossy_string(
+            line,
+            &self.options.invalid_char_replacement,
+        ));
+        if self.merges_adjacent() {
+            self.merged_lines.push(line);
+            return;
+        }
+
+        for result in self.awaiting_after_context.iter_mut() {
+            if let Some(after) = &mut result.after {
+                after.push(line.clone());
+            }
+        }
+        while let Some(result) = self.awaiting_after_context.front() {
+            match &result.after {
+                Some(after) if after.len() < self.options.after_context => break,
+                _ => {
+                    let result = self.awaiting_after_context.pop_front().unwrap();
+                    self.send(result);
+                }
+            }
+        }
+
+        if self.options.before_context > 0 {
+            if self.recent_lines.len() == self.options.before_context {
+                self.recent_lines.pop_front();
+            }
+            self.recent_lines.push_back(line);
+        }
+    }
+
+    /// Sends every match that's still waiting for after-context, and forgets any before-context.
+    ///
+    /// Called wherever the context window is interrupted, since lines past that point aren't adjacent.
+    fn flush_inline_context(&mut self) {
+        while let Some(result) = self.awaiting_after_context.pop_front() {
+            self.send(result);
+        }
+        self.recent_lines.clear();
+
+        let lines = std::mem::take(&mut self.merged_lines);
+        let matches = std::mem::take(&mut self.merged_matches);
+        let truncated = std::mem::take(&mut self.merged_matches_truncated);
+        // Spans without any matches are only passed-through lines
+        if let Some(first_match) = matches.first() {
+            let line_number = first_match
+                .line_number
+                .map(|line_number| line_number - self.merged_first_match as u64);
+            let result = MatchResult::merged(lines, line_number, matches, truncated, self);
+            self.send(result);
+        }
+    }
+
+    /// Whether matches are combined into spans, for `mergeAdjacent`.
+    ///
+    /// Without context lines, nothing ever separates the spans, so each match is reported on its own.
+    fn merges_adjacent(&self) -> bool {
+        self.options.merge_adjacent
+            && (self.options.before_context > 0
+                || self.options.after_context > 0
+                || self.options.passthru)
+    }
+}
+
+impl<'a> grep::searcher::Sink for JSCallbackSink<'a> {
+    type Error = RipgrepjsError;
+
+    /// Resets the per-file state that's only updated while the searcher is running (see `start_file`).
+    fn begin(&mut self, _: &Searcher) -> Result<bool, Self::Error> {
+        self.seen_in_file.clear();
+        // Left over if the last file failed partway through; a finished file's are flushed by `finish`
+        self.recent_lines.clear();
+        self.awaiting_after_context.clear();
+        self.merged_lines.clear();
+        self.merged_matches.clear();
+        self.merged_matches_truncated = false;
+        self.file_group.clear();
+        self.quit_on_binary = false;
+        self.count = 0;
+        self.match_stats = MatchStats::default();
+        self.file_matches = 0;
+        if self.options.json_lines {
+            self.json_file = JsonStats::default();
+            self.json_started = Some(Instant::now());
+            self.json_begun = false;
+        }
+        if self.options.report_file_boundaries {
+            if let Some(path) = &self.path {
+                self.results.send(Delivery::FileStart(Arc::clone(path)));
+            }
+        }
+        Ok(true)
+    }
+
+    fn matched(&mut self, searcher: &Searcher, matched: &SinkMatch) -> Result<bool, Self::Error> {
+        if let (Some(line_filter), Some(line_number)) =
+            (&self.options.line_filter, matched.line_number())
+        {
+            let line_number = line_number + self.line_number_offset;
+            if !line_filter.contains(&line_number) {
+                // Nothing after the last line in the filter can be reported, so the rest of the file is skipped
+                let lines_left = line_filter.last().is_some_and(|&last| line_number < last);
+                return Ok(lines_left);
+            }
+        }
+        if let Some(exclude_matcher) = &self.options.exclude_matcher {
+            if exclude_matcher.is_match(without_line_terminator(searcher, matched.bytes()))? {
+                return Ok(true);
+            }
+        }
+        // Counting takes precedence, since there's nothing to report for each match
+        if self.options.extract_matches
+            && !self.options.is_counting()
+            && !self.options.json_lines
+            && !self.options.lsp_mode
+        {
+            let keep_searching = self.extract_matches(searcher, matched)?;
+            self.check_invalid_utf8()?;
+            return Ok(keep_searching);
+        }
+        if self.is_duplicate(searcher, matched.bytes()) {
+            return Ok(true);
+        }
+
+        let keep_searching = match self.shared.claim_match() {
+            // The rest of the file is skipped after its first match with `firstMatchPerFile`
+            Some(keep_searching) => keep_searching && !self.options.first_match_per_file,
+            None => return Ok(false),
+        };
+        self.reported_match = true;
+        self.file_matches += 1;
+
+        if self.options.json_lines {
+            self.send_json_match(searcher, matched)?;
+            return Ok(keep_searching);
+        }
+        if self.options.lsp_mode {
+            self.send_lsp_locations(searcher, matched)?;
+            return Ok(keep_searching);
+        }
+        if self.options.is_counting() {
+            if let Some(count_mode) = self.options.count_mode {
+                self.count += match count_mode {
+                    // Inverted matches are lines that don't match, so there's nothing in them to count
+                    CountMode::Matches if !self.options.invert_match => {
+                        let mut occurrences = 0;
+                        let bytes = without_line_terminator(searcher, matched.bytes());
+                        self.matcher.find_iter(bytes, |_| {
+                            occurrences += 1;
+                            true
+                        })?;
+                        occurrences
+                    }
+                    _ => 1,
+                };
+            }
+            if self.options.match_stats {
+                for line in matched.lines() {
+                    self.match_stats
+                        .add_line(line, without_line_terminator(searcher, line));
+                }
+            }
+            return Ok(keep_searching);
+        }
+
+        let mut result = MatchResult::new(
+            matched.lines(),
+            matched.bytes(),
+            matched.absolute_byte_offset(),
+            matched.line_number(),
+            self,
+        );
+        if self.options.include_match_ranges {
+            result.match_ranges = Some(self.find_match_ranges(searcher, matched));
+        }
+        result.header_lines = self.header_lines.clone();
+        if self.options.include_siblings {
+            result.siblings = self.siblings();
+        }
+        if self.options.include_match_byte_range && !self.options.invert_match {
+            let bytes = without_line_terminator(searcher, matched.bytes());
+            if let Some(first_match) = self.matcher.find(bytes)? {
+                let start = matched.absolute_byte_offset() + self.chunk_start;
+                result.match_byte_range = Some((
+                    start + first_match.start() as u64,
+                    start + first_match.end() as u64,
+                ));
+            }
+        }
+        if self.options.include_indent {
+            let first_line = matched.lines().next().unwrap_or_default();
+            result.indent = Some(indent_width(first_line, self.options.tab_width));
+        }
+        if let (true, Some(start)) = (self.options.multiline_search, result.line_number) {
+            let lines = matched.lines().count() as u64;
+            result.line_span = Some((start, start + lines.saturating_sub(1)));
+        }
+        if let (Some(word_list), false) = (&self.options.word_list, self.options.invert_match) {
+            let bytes = without_line_terminator(searcher, matched.bytes());
+            if let Some(first_match) = self.matcher.find(bytes)? {
+                let text = String::from_utf8_lossy(&bytes[first_match]);
+                result.matched_term = Some(match word_list.term_for(&text) {
+                    Some(term) => term.to_string(),
+                    None => text.into_owned(),
+                });
+            }
+        }
+        if let (Some(pattern_matchers), false) =
+            (&self.options.pattern_matchers, self.options.invert_match)
+        {
+            let bytes = without_line_terminator(searcher, matched.bytes());
+            let mut pattern_indices = Vec::new();
+            for (index, pattern_matcher) in pattern_matchers.iter().enumerate() {
+                if pattern_matcher.is_match(bytes)? {
+                    pattern_indices.push(index);
+                }
+            }
+            result.pattern_indices = Some(pattern_indices);
+        }
+        if let Some((before, after)) = &self.options.highlight {
+            result.highlighted_line = Some(self.highlight(searcher, matched, before, after)?);
+        }
+        if let (Some(chars), false) = (self.options.context_chars, self.options.invert_match) {
+            result.snippet = self.snippet(searcher, matched, chars)?;
+        }
+        if self.options.include_captures && !self.options.invert_match {
+            result.captures = Some(self.find_captures(searcher, matched)?);
+        }
+        if let Some(sub_matcher) = &self.options.sub_matcher {
+            result.sub_matches = Some(self.find_sub_matches(searcher, matched, sub_matcher)?);
+        }
+        if let (Some(encoding), false) = (self.options.column_encoding, self.options.invert_match) {
+            let bytes = without_line_terminator(searcher, matched.bytes());
+            if let Some(first_match) = self.matcher.find(bytes)? {
+                result.column = Some(encoding.offset_in(bytes, first_match.start()) + 1);
+            }
+        }
+        if let (Some(tracker), Some(heading_matcher)) =
+            (&mut self.heading, &self.options.heading_matcher)
+        {
+            result.heading = tracker.heading_before(
+                searcher,
+                heading_matcher,
+                matched.absolute_byte_offset() + self.chunk_start,
+                &self.options.invalid_char_replacement,
+            )?;
+        }
+
+        if self.options.context_mode == ContextMode::Inline && self.merges_adjacent() {
+            if self.merged_matches.is_empty() {
+                self.merged_first_match = self.merged_lines.len();
+            }
+            for line in matched.lines() {
+                self.record_inline_context_line(line);
+            }
+            match self.options.max_matches_per_group {
+                Some(max)
+                    if !self.merged_matches.is_empty() && self.merged_matches.len() >= max =>
+                {
+                    self.merged_matches_truncated = true;
+                }
+                _ => self.merged_matches.push(result),
+            }
+        } else if self.options.context_mode == ContextMode::Inline {
+            result.before = Some(self.recent_lines.iter().cloned().collect());
+            result.after = Some(Vec::new());
+            // Earlier matches still waiting for after-context get this match's lines too
+            for line in matched.lines() {
+                self.record_inline_context_line(line);
+            }
+            if self.options.after_context > 0 {
+                self.awaiting_after_context.push_back(result);
+            } else {
+                self.send(result);
+            }
+        } else {
+            self.send(result);
+        }
+
+        self.check_invalid_utf8()?;
+        Ok(keep_searching)
+    }
+
+    fn context(&mut self, _: &Searcher, context: &SinkContext) -> Result<bool, Self::Error> {
+        // Locations are only of matches
+        if self.options.lsp_mode {
+            return Ok(true);
+        }
+        if self.options.json_lines {
+            self.send_json_lines(
+                "context",
+                context.bytes(),
+                context.absolute_byte_offset(),
+                context.line_number(),
+                Vec::new(),
+            );
+            return Ok(true);
+        }
+        match self.options.context_mode {
+            ContextMode::None => {}
+            ContextMode::Separate => {
+                let bytes = context.bytes();
+                let mut result = MatchResult::new(
+                    std::iter::once(bytes),
+                    bytes,
+                    context.absolute_byte_offset(),
+                    context.line_number(),
+                    self,
+                );
+                result.context_kind = Some(match context.kind() {
+                    SinkContextKind::Before => "before",
+                    SinkContextKind::After => "after",
+                    SinkContextKind::Other => "other",
+                });
+                if self.options.passthru {
+                    result.matched = Some(false);
+                }
+                self.send(result);
+            }
+            ContextMode::Inline => self.record_inline_context_line(context.bytes()),
+        }
+        self.check_invalid_utf8()?;
+        Ok(true)
+    }
+
+    fn context_break(&mut self, _: &Searcher) -> Result<bool, Self::Error> {
+        // `rg --json` doesn't have separators, and neither do locations
+        if self.options.json_lines || self.options.lsp_mode {
+            return Ok(true);
+        }
+        if self.options.context_mode == ContextMode::Inline {
+            self.flush_inline_context();
+        }
+        if let Some(separator) = &self.options.context_separator {
+            if self.options.context_mode != ContextMode::None {
+                let result = MatchResult::separator(separator, self);
+                self.send(result);
+            }
+        }
+        self.check_invalid_utf8()?;
+        Ok(true)
+    }
+
+    fn finish(&mut self, _: &Searcher, finish: &SinkFinish) -> Result<(), Self::Error> {
+        self.flush_inline_context();
+        self.check_invalid_utf8()?;
+        self.send_file_group();
+        if self.options.json_lines {
+            self.finish_json(finish);
+        }
+        if self.options.report_file_boundaries {
+            if let Some(path) = &self.path {
+                self.results
+                    .send(Delivery::FileEnd(Arc::clone(path), self.file_matches));
+            }
+        }
+        self.quit_on_binary = self.options.binary_detection == BinaryDetection::Quit
+            && finish.binary_byte_offset().is_some();
+        Ok(())
+    }
+}
+
+/// Passes the matches from one window of a file to the file's sink, for `multilineWindow`.
+///
+/// The sink sees the windows as one search: it's only told when the first one begins and the last one finishes.
+pub(crate) struct WindowSink<'s, 'a> {
+    pub(crate) inner: &'s mut JSCallbackSink<'a>,
+    // Matches starting at or after this offset in the window are left for the next one
+    pub(crate) owned_end: u64,
+    // Set once the first window has begun
+    pub(crate) started: bool,
+    // Set for the file's last window
+    pub(crate) last: bool,
+    // Set if the sink asked for the search to stop, so later windows aren't searched
+    pub(crate) stopped: bool,
+}
+
+impl grep::searcher::Sink for WindowSink<'_, '_> {
+    type Error = RipgrepjsError;
+
+    fn begin(&mut self, searcher: &Searcher) -> Result<bool, Self::Error> {
+        if self.started {
+            return Ok(true);
+        }
+        self.started = true;
+        self.inner.begin(searcher)
+    }
+
+    fn matched(&mut self, searcher: &Searcher, matched: &SinkMatch) -> Result<bool, Self::Error> {
+        if matched.absolute_byte_offset() >= self.owned_end {
+            // The next window will report this, and anything after it
+            return Ok(false);
+        }
+        let keep_searching = self.inner.matched(searcher, matched)?;
+        self.stopped = !keep_searching;
+        Ok(keep_searching)
+    }
+
+    fn finish(&mut self, searcher: &Searcher, finish: &SinkFinish) -> Result<(), Self::Error> {
+        // Binary data stops the whole file, just as it would have without windows
+        if self.inner.options.binary_detection == BinaryDetection::Quit
+            && finish.binary_byte_offset().is_some()
+        {
+            self.stopped = true;
+        }
+        if self.last || self.stopped {
+            self.inner.finish(searcher, finish)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,608 @@
+//! Walking directory trees for the files to search in them, and the `.gitignore` rules that apply there
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use grep::searcher::Searcher;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use rayon::prelude::*;
+
+use crate::{
+    error::RipgrepjsError, results::Delivery, search::DirectorySearch, sink::JSCallbackSink,
+};
+
+impl DirectorySearch<'_> {
+    /// Checks whether a file (found in a directory) hasn't been searched yet, recording it if not.
+    ///
+    /// This only matters with `followSymlinks`; otherwise there's only one way to reach each file.
+    fn is_first_visit(&self, path: &Path) -> Result<bool, RipgrepjsError> {
+        if !self.searcher_opts.follow_symlinks {
+            return Ok(true);
+        }
+        let canonical_path = std::fs::canonicalize(path)?;
+        Ok(self.visited_files.lock().unwrap().insert(canonical_path))
+    }
+
+    /// Searches whatever a symbolic link found in a directory points to, for `followSymlinks`.
+    ///
+    /// Broken links are passed to `onError` without stopping the search.
+    fn search_symlink(
+        &self,
+        searcher: &mut Searcher,
+        sink: &mut JSCallbackSink,
+        link: PathBuf,
+    ) -> Result<Option<PathBuf>, RipgrepjsError> {
+        let (metadata, target) = match std::fs::metadata(&link)
+            .and_then(|metadata| Ok((metadata, std::fs::canonicalize(&link)?)))
+        {
+            Ok(found) => found,
+            Err(error) => {
+                self.report_error(RipgrepjsError::InFile(link, Box::new(error.into())));
+                return Ok(None);
+            }
+        };
+        let path = if self.searcher_opts.report_link_path {
+            link
+        } else {
+            target
+        };
+        if metadata.is_file() {
+            if self.is_first_visit(&path)? {
+                self.search_file(searcher, sink, path)?;
+            }
+            Ok(None)
+        } else if metadata.is_dir() {
+            Ok(Some(path))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Searches an entry found in a directory: a file, or (with `followSymlinks`) a link.
+    ///
+    /// Returns the directory to walk into if it's a directory (or a link to one), rather than walking it here.
+    fn search_entry(
+        &self,
+        searcher: &mut Searcher,
+        sink: &mut JSCallbackSink,
+        entry: &std::fs::DirEntry,
+    ) -> Result<Option<PathBuf>, RipgrepjsError> {
+        let file_type = entry.file_type()?;
+        if file_type.is_file() {
+            if self.is_first_visit(&entry.path())? {
+                self.search_file(searcher, sink, entry.path())?;
+            }
+            Ok(None)
+        } else if file_type.is_symlink() && self.searcher_opts.follow_symlinks {
+            self.search_symlink(searcher, sink, entry.path())
+        } else if file_type.is_dir() {
+            Ok(Some(entry.path()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Checks a file's name against the `fileNameGlob`, `fileTypes`, and `fileTypesNot` options
+    pub(crate) fn should_search_file(&self, path: &Path) -> bool {
+        match (&self.file_name_filter, path.file_name()) {
+            (Some(filter), Some(name)) => filter.is_match(name),
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+
+    /// Whether directories' entries are checked against any gitignore rules while walking
+    fn uses_gitignores(&self) -> bool {
+        self.searcher_opts.respect_gitignore || self.global_gitignore.is_some()
+    }
+
+    /// Adds the rules from a directory's own `.gitignore` (if it has one) to those of the directories above it,
+    /// for `respectGitignore`. `directory` has to be absolute, like every path matched against the rules.
+    ///
+    /// Lines that can't be parsed are passed to `onError`, and the rest of the file still applies.
+    fn add_gitignore(
+        &self,
+        above: Option<Arc<GitignoreStack>>,
+        directory: &Path,
+    ) -> Option<Arc<GitignoreStack>> {
+        let path = directory.join(".gitignore");
+        if !self.searcher_opts.respect_gitignore || !path.is_file() {
+            return above;
+        }
+        let mut builder = GitignoreBuilder::new(directory);
+        if let Some(error) = builder.add(&path) {
+            self.report_error(RipgrepjsError::InFile(
+                path.clone(),
+                Box::new(RipgrepjsError::Gitignore(error)),
+            ));
+        }
+        match builder.build() {
+            Ok(gitignore) => Some(Arc::new(GitignoreStack { gitignore, above })),
+            Err(error) => {
+                self.report_error(RipgrepjsError::InFile(
+                    path,
+                    Box::new(RipgrepjsError::Gitignore(error)),
+                ));
+                above
+            }
+        }
+    }
+
+    /// The rules from every `.gitignore` above a directory that's the root of a search, for `respectGitignore`
+    fn gitignores_above(&self, root: &Path) -> Result<Option<Arc<GitignoreStack>>, RipgrepjsError> {
+        if !self.searcher_opts.respect_gitignore {
+            return Ok(None);
+        }
+        let root = std::path::absolute(root)?;
+        let mut ancestors = root.ancestors().skip(1).collect::<Vec<_>>();
+        ancestors.reverse();
+        Ok(ancestors.into_iter().fold(None, |above, directory| {
+            self.add_gitignore(above, directory)
+        }))
+    }
+
+    /// Checks an entry found in a directory against the gitignore rules that apply there, leaving it out
+    /// (and passing the rule that did to `onIgnore`) if they ignore it. `directory` is the absolute path
+    /// of the directory it was found in.
+    fn is_gitignored(
+        &self,
+        gitignores: Option<&GitignoreStack>,
+        directory: &Path,
+        entry: &std::fs::DirEntry,
+    ) -> bool {
+        let path = directory.join(entry.file_name());
+        let is_dir = match entry.file_type() {
+            Ok(file_type) if file_type.is_symlink() => {
+                self.searcher_opts.follow_symlinks && path.is_dir()
+            }
+            Ok(file_type) => file_type.is_dir(),
+            Err(_) => false,
+        };
+        let matched = gitignore_match(gitignores, self.global_gitignore.as_ref(), &path, is_dir);
+        let glob = match matched {
+            ignore::Match::Ignore(glob) => glob,
+            _ => return false,
+        };
+        if self.searcher_opts.report_ignores {
+            let source = match glob.from() {
+                Some(from) => Cow::Owned(from.to_string_lossy().into_owned()),
+                None => Cow::Borrowed("gitignore"),
+            };
+            self.results.send(Delivery::Ignore(
+                entry.path(),
+                source,
+                Some(glob.original().to_string()),
+            ));
+        }
+        self.report_skip(entry.path(), "ignored");
+        true
+    }
+}
+
+/// The `.gitignore` rules that apply in a directory being walked, for `respectGitignore`: those of the nearest
+/// directory (at or above it) with a `.gitignore`, then those of each directory with one further up
+pub(crate) struct GitignoreStack {
+    gitignore: Gitignore,
+    above: Option<Arc<GitignoreStack>>,
+}
+
+/// Finds the rule that decides whether a path is ignored, if any does.
+///
+/// `.gitignore` files take precedence over the global gitignore, and nearer ones over those further up,
+/// so that a `!` rule in one can bring back what another ignored.
+pub(crate) fn gitignore_match<'g>(
+    gitignores: Option<&'g GitignoreStack>,
+    global_gitignore: Option<&'g Gitignore>,
+    path: &Path,
+    is_dir: bool,
+) -> ignore::Match<&'g ignore::gitignore::Glob> {
+    let mut layer = gitignores;
+    while let Some(GitignoreStack { gitignore, above }) = layer {
+        let matched = gitignore.matched(path, is_dir);
+        if !matched.is_none() {
+            return matched;
+        }
+        layer = above.as_deref();
+    }
+    match global_gitignore {
+        Some(global_gitignore) => global_gitignore.matched(path, is_dir),
+        None => ignore::Match::None,
+    }
+}
+
+/// The most items waiting in a walk's queue at once.
+///
+/// Workers that find more while it's full deal with them themselves, so however big the tree is,
+/// only this many entries (plus each worker's own directories) are ever waiting in memory.
+pub(crate) const WALK_QUEUE_CAPACITY: usize = 1024;
+
+/// Something for the workers walking a directory tree to do
+pub(crate) enum WalkItem {
+    /// A directory to list
+    Directory(PathBuf, Option<Arc<GitignoreStack>>),
+    /// An entry found in a directory: a file to search, a directory to list, or (with `followSymlinks`) a link
+    Entry(std::fs::DirEntry, Option<Arc<GitignoreStack>>),
+}
+
+/// The work shared by the workers walking a directory tree.
+///
+/// Each worker takes items until the queue is empty, then stops, and queueing an item starts another
+/// worker if there are fewer than one per thread. Workers never wait for each other, so one can't get stuck
+/// waiting on a worker further down its own thread's stack (which Rayon does while a file is searched in chunks).
+pub(crate) struct WalkQueue {
+    // The items waiting, and how many workers are running
+    state: Mutex<(Vec<WalkItem>, usize)>,
+    max_workers: usize,
+}
+
+impl WalkQueue {
+    fn new(max_workers: usize) -> Self {
+        Self {
+            state: Mutex::new((Vec::new(), 0)),
+            max_workers,
+        }
+    }
+
+    /// Queues an item for the next free worker, or gives it back if the queue is full
+    fn push<'s>(
+        &'s self,
+        scope: &rayon::Scope<'s>,
+        search: &'s DirectorySearch,
+        item: WalkItem,
+    ) -> Result<(), WalkItem> {
+        let mut state = self.state.lock().unwrap();
+        let (items, workers) = &mut *state;
+        if items.len() >= WALK_QUEUE_CAPACITY {
+            return Err(item);
+        }
+        items.push(item);
+        if *workers < self.max_workers {
+            *workers += 1;
+            scope.spawn(move |scope| walk_worker(scope, self, search));
+        }
+        Ok(())
+    }
+
+    /// Takes the most recently queued item, or counts the worker as stopped if there aren't any
+    fn pop(&self) -> Option<WalkItem> {
+        let mut state = self.state.lock().unwrap();
+        let (items, workers) = &mut *state;
+        let item = items.pop();
+        if item.is_none() {
+            *workers -= 1;
+        }
+        item
+    }
+}
+
+/// Walks a directory tree, searching files as they're found rather than listing every directory first.
+///
+/// Directories and their entries go through a bounded `WalkQueue`, which a worker on each of the thread pool's
+/// threads takes them from, searching files and queueing the directories it finds.
+pub(crate) fn search_directory_inner<P>(
+    path: P,
+    search: &DirectorySearch,
+) -> Result<(), RipgrepjsError>
+where
+    P: AsRef<Path>,
+{
+    let gitignores = search.gitignores_above(path.as_ref())?;
+    if !search.searcher_opts.parallel {
+        let mut searcher = search.searcher_opts.to_searcher();
+        return search_directory_serial(
+            path.as_ref(),
+            gitignores,
+            search,
+            &mut searcher,
+            &mut search.sink(),
+        );
+    }
+    let queue = WalkQueue::new(rayon::current_num_threads());
+    let root = WalkItem::Directory(path.as_ref().to_path_buf(), gitignores);
+    rayon::scope(|scope| {
+        // The queue starts out empty, so this can't be given back
+        let _ = queue.push(scope, search, root);
+    });
+    Ok(())
+}
+
+/// Walks a directory on the current thread, depth-first and in order of file name, for `parallel: false`
+pub(crate) fn search_directory_serial(
+    directory: &Path,
+    gitignores: Option<Arc<GitignoreStack>>,
+    search: &DirectorySearch,
+    searcher: &mut Searcher,
+    sink: &mut JSCallbackSink,
+) -> Result<(), RipgrepjsError> {
+    let canonical_path = std::fs::canonicalize(directory)?;
+    if !search
+        .visited_directories
+        .lock()
+        .unwrap()
+        .insert(canonical_path)
+    {
+        return Ok(());
+    }
+
+    let absolute_directory = std::path::absolute(directory)?;
+    let gitignores = search.add_gitignore(gitignores, &absolute_directory);
+    let mut entries = std::fs::read_dir(directory)?
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+    entries.sort_by_key(std::fs::DirEntry::file_name);
+    for entry in entries {
+        if search.shared.should_stop() {
+            break;
+        }
+        if search.uses_gitignores()
+            && search.is_gitignored(gitignores.as_deref(), &absolute_directory, &entry)
+        {
+            continue;
+        }
+        match search.search_entry(searcher, sink, &entry) {
+            Ok(Some(subdirectory)) => {
+                let subdirectory_gitignores = gitignores.clone();
+                if let Err(error) = search_directory_serial(
+                    &subdirectory,
+                    subdirectory_gitignores,
+                    search,
+                    searcher,
+                    sink,
+                ) {
+                    search.record_error(subdirectory, error);
+                }
+            }
+            Ok(None) => {}
+            Err(error) => search.record_error(entry.path(), error),
+        }
+    }
+    Ok(())
+}
+
+/// Takes items from a walk's queue until it's empty
+pub(crate) fn walk_worker<'s>(
+    scope: &rayon::Scope<'s>,
+    queue: &'s WalkQueue,
+    search: &'s DirectorySearch,
+) {
+    let mut searcher = search.searcher_opts.to_searcher();
+    let mut sink = search.sink();
+    // Directories found while the queue was full, which this worker lists itself
+    let mut own_directories = Vec::new();
+    loop {
+        let item = match own_directories.pop() {
+            Some((directory, gitignores)) => WalkItem::Directory(directory, gitignores),
+            None => match queue.pop() {
+                Some(item) => item,
+                None => return,
+            },
+        };
+        // Don't bother opening any more files once the match limit is hit, but still empty the queue
+        if search.shared.should_stop() {
+            continue;
+        }
+        match item {
+            WalkItem::Directory(directory, gitignores) => {
+                let listed = list_directory(
+                    scope,
+                    queue,
+                    search,
+                    &mut searcher,
+                    &mut sink,
+                    &mut own_directories,
+                    &directory,
+                    gitignores,
+                );
+                if let Err(error) = listed {
+                    search.record_error(directory, error);
+                }
+            }
+            WalkItem::Entry(entry, gitignores) => walk_entry(
+                scope,
+                queue,
+                search,
+                &mut searcher,
+                &mut sink,
+                &mut own_directories,
+                &entry,
+                gitignores,
+            ),
+        }
+    }
+}
+
+/// Queues each entry in a directory that isn't gitignored, or searches it straight away if the queue is full
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn list_directory<'s>(
+    scope: &rayon::Scope<'s>,
+    queue: &'s WalkQueue,
+    search: &'s DirectorySearch,
+    searcher: &mut Searcher,
+    sink: &mut JSCallbackSink,
+    own_directories: &mut Vec<(PathBuf, Option<Arc<GitignoreStack>>)>,
+    directory: &Path,
+    gitignores: Option<Arc<GitignoreStack>>,
+) -> Result<(), RipgrepjsError> {
+    let canonical_path = std::fs::canonicalize(directory)?;
+    if !search
+        .visited_directories
+        .lock()
+        .unwrap()
+        .insert(canonical_path)
+    {
+        return Ok(());
+    }
+
+    let absolute_directory = std::path::absolute(directory)?;
+    let gitignores = search.add_gitignore(gitignores, &absolute_directory);
+    for entry in std::fs::read_dir(directory)? {
+        if search.shared.should_stop() {
+            break;
+        }
+        if let Ok(entry) = entry {
+            if search.uses_gitignores()
+                && search.is_gitignored(gitignores.as_deref(), &absolute_directory, &entry)
+            {
+                continue;
+            }
+            let item = WalkItem::Entry(entry, gitignores.clone());
+            if let Err(WalkItem::Entry(entry, gitignores)) = queue.push(scope, search, item) {
+                walk_entry(
+                    scope,
+                    queue,
+                    search,
+                    searcher,
+                    sink,
+                    own_directories,
+                    &entry,
+                    gitignores,
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Searches an entry found while walking, queueing any directory it leads to
+/// (or keeping it for the worker to list itself, if the queue is full)
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn walk_entry<'s>(
+    scope: &rayon::Scope<'s>,
+    queue: &'s WalkQueue,
+    search: &'s DirectorySearch,
+    searcher: &mut Searcher,
+    sink: &mut JSCallbackSink,
+    own_directories: &mut Vec<(PathBuf, Option<Arc<GitignoreStack>>)>,
+    entry: &std::fs::DirEntry,
+    gitignores: Option<Arc<GitignoreStack>>,
+) {
+    match search.search_entry(searcher, sink, entry) {
+        Ok(Some(directory)) => {
+            if let Err(WalkItem::Directory(directory, gitignores)) =
+                queue.push(scope, search, WalkItem::Directory(directory, gitignores))
+            {
+                own_directories.push((directory, gitignores));
+            }
+        }
+        Ok(None) => {}
+        Err(error) => search.record_error(entry.path(), error),
+    }
+}
+
+/// Searches exactly the given files, without traversing any directories.
+///
+/// Paths that don't exist or aren't files are passed to `onError` rather than stopping the search.
+pub(crate) fn search_file_list_inner<P>(
+    files: &[P],
+    search: &DirectorySearch,
+) -> Result<(), RipgrepjsError>
+where
+    P: AsRef<Path> + Sync,
+{
+    files.par_iter().for_each_init(
+        || (search.searcher_opts.to_searcher(), search.sink()),
+        |(searcher, sink), file| {
+            if search.shared.should_stop() {
+                return;
+            }
+            let path = file.as_ref().to_path_buf();
+            match std::fs::metadata(&path) {
+                Ok(metadata) if metadata.is_file() => {
+                    if let Err(error) = search.search_file(searcher, sink, path.clone()) {
+                        search.record_error(path, error);
+                    }
+                }
+                Ok(_) => {
+                    let error = std::io::Error::other("not a file");
+                    search.report_error(RipgrepjsError::InFile(path, Box::new(error.into())));
+                }
+                Err(error) => {
+                    search.report_error(RipgrepjsError::InFile(path, Box::new(error.into())));
+                }
+            }
+        },
+    );
+    Ok(())
+}
+
+/// A file's contents passed in from JavaScript, with the path they're reported as, for `searchBlobs`
+pub(crate) struct Blob {
+    pub(crate) path: PathBuf,
+    pub(crate) data: Vec<u8>,
+}
+
+/// Searches blobs in parallel, like `search_file_list_inner` does files
+pub(crate) fn search_blob_list(
+    blobs: &[Blob],
+    search: &DirectorySearch,
+) -> Result<(), RipgrepjsError> {
+    blobs.par_iter().for_each_init(
+        || (search.searcher_opts.to_searcher(), search.sink()),
+        |(searcher, sink), blob| {
+            if search.shared.should_stop() {
+                return;
+            }
+            if let Err(error) = search.search_blob(searcher, sink, blob) {
+                search.record_error(blob.path.clone(), error);
+            }
+        },
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds the rules for a `.gitignore` with the given lines in the directory `root`
+    fn gitignore(root: &str, lines: &[&str]) -> Gitignore {
+        let mut builder = GitignoreBuilder::new(root);
+        for line in lines {
+            builder.add_line(None, line).unwrap();
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn nearer_gitignores_take_precedence_over_further_and_global_ones() {
+        let global_gitignore = gitignore("/", &["*.swp"]);
+        let sub = GitignoreStack {
+            gitignore: gitignore("/tree/sub", &["!kept.swp", "!kept.log"]),
+            above: Some(Arc::new(GitignoreStack {
+                gitignore: gitignore("/tree", &["*.log"]),
+                above: None,
+            })),
+        };
+        let tree = sub.above.as_deref();
+        let ignored = |gitignores, global_gitignore, path| {
+            gitignore_match(gitignores, global_gitignore, Path::new(path), false).is_ignore()
+        };
+
+        assert!(!ignored(tree, Some(&global_gitignore), "/tree/notes.txt"));
+        assert!(ignored(
+            tree,
+            Some(&global_gitignore),
+            "/tree/notes.txt.swp"
+        ));
+        assert!(!ignored(tree, None, "/tree/notes.txt.swp"));
+        assert!(ignored(
+            Some(&sub),
+            Some(&global_gitignore),
+            "/tree/sub/app.log"
+        ));
+        // `!` rules bring back what the global gitignore and further `.gitignore` files leave out
+        assert!(!ignored(
+            Some(&sub),
+            Some(&global_gitignore),
+            "/tree/sub/kept.swp"
+        ));
+        assert!(!ignored(
+            Some(&sub),
+            Some(&global_gitignore),
+            "/tree/sub/kept.log"
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+const assert = require('assert');
+const path = require('path');
+const zlib = require('zlib');
+const {test, search} = require('./helpers');
+
+/** One entry in a tar archive: its header, then its contents padded to a whole block */
+function tarEntry(name, contents, type = '0') {
+    const header = Buffer.alloc(512);
+    header.write(name.slice(0, 100), 0);
+    header.write('0000644\0', 100);
+    header.write('0000000\0', 108);
+    header.write('0000000\0', 116);
+    header.write(contents.length.toString(8).padStart(11, '0') + '\0', 124);
+    header.write('00000000000\0', 136);
+    header.write(' '.repeat(8), 148);
+    header.write(type, 156);
+    header.write('ustar  \0', 257);
+    const checksum = header.reduce((sum, byte) => sum + byte, 0);
+    header.write(checksum.toString(8).padStart(6, '0') + '\0 ', 148);
+    return Buffer.concat([header, contents, Buffer.alloc((512 - contents.length % 512) % 512)]);
+}
+
+/** A tar archive of the given files, with GNU long name entries for names that don't fit in a header */
+function tar(files) {
+    const entries = [];
+    for (const [name, contents] of Object.entries(files)) {
+        if (name.length > 100) entries.push(tarEntry('././@LongLink', Buffer.from(name + '\0'), 'L'));
+        entries.push(tarEntry(name, Buffer.from(contents)));
+    }
+    return Buffer.concat([...entries, Buffer.alloc(1024)]);
+}
+
+/** A zip archive of the given files, deflated, with directories as entries of their own */
+function zip(files) {
+    const local = [];
+    const central = [];
+    let offset = 0;
+    for (const [name, contents] of Object.entries(files)) {
+        const data = Buffer.from(contents);
+        const compressed = name.endsWith('/') ? data : zlib.deflateRawSync(data);
+        const method = name.endsWith('/') ? 0 : 8;
+        const fields = Buffer.alloc(26);
+        fields.writeUInt16LE(20, 0);
+        fields.writeUInt16LE(method, 4);
+        fields.writeUInt32LE(zlib.crc32(data), 10);
+        fields.writeUInt32LE(compressed.length, 14);
+        fields.writeUInt32LE(data.length, 18);
+        fields.writeUInt16LE(Buffer.byteLength(name), 22);
+        const header = Buffer.concat([Buffer.from([0x50, 0x4b, 3, 4]), fields, Buffer.from(name)]);
+        local.push(header, compressed);
+
+        const entry = Buffer.alloc(46);
+        entry.writeUInt32LE(0x02014b50, 0);
+        entry.writeUInt16LE(20, 4);
+        fields.copy(entry, 6);
+        entry.writeUInt32LE(offset, 42);
+        central.push(entry, Buffer.from(name));
+        offset += header.length + compressed.length;
+    }
+    const directory = Buffer.concat(central);
+    const end = Buffer.alloc(22);
+    end.writeUInt32LE(0x06054b50, 0);
+    end.writeUInt16LE(central.length / 2, 8);
+    end.writeUInt16LE(central.length / 2, 10);
+    end.writeUInt32LE(directory.length, 12);
+    end.writeUInt32LE(offset, 16);
+    return Buffer.concat([...local, directory, end]);
+}
+
+const longName = `${'nested'.repeat(20)}/deep.txt`;
+const members = {'a.txt': 'needle in a tar\n', [longName]: 'long needle\n'};
+const files = {
+    'plain.tar': tar(members),
+    'packed.tgz': zlib.gzipSync(tar(members)),
+    'packed.zip': zip({'dir/': '', 'dir/b.txt': 'needle in a zip\n'}),
+    'other.tar.xz': 'needle',
+    'outside.txt': 'needle outside\n',
+};
+
+test('searchArchives searches the members of tar, gzipped tar and zip archives', files, async directory => {
+    const skips = [];
+    const {results} = await search({
+        pattern: 'needle',
+        searchArchives: true,
+        onSkip: (file, reason) => skips.push([path.relative(directory, file), reason]),
+    }, directory);
+    assert.deepStrictEqual(
+        results.map(result => [path.relative(directory, result.path), result.matchedLines]).sort(),
+        [
+            ['outside.txt', ['needle outside\n']],
+            ['packed.tgz!a.txt', ['needle in a tar\n']],
+            [`packed.tgz!${longName}`, ['long needle\n']],
+            ['packed.zip!dir/b.txt', ['needle in a zip\n']],
+            ['plain.tar!a.txt', ['needle in a tar\n']],
+            [`plain.tar!${longName}`, ['long needle\n']],
+        ]
+    );
+    assert.deepStrictEqual(skips, [['other.tar.xz', 'compressedArchive']]);
+});
+
+test('archives are searched as they are without searchArchives', files, async directory => {
+    const {results} = await search({pattern: 'needle'}, directory);
+    assert.deepStrictEqual(
+        [...new Set(results.map(result => path.relative(directory, result.path)))].sort(),
+        ['other.tar.xz', 'outside.txt', 'plain.tar']
+    );
+});
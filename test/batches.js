@@ -0,0 +1,57 @@
+const assert = require('assert');
+const {test, bindings} = require('./helpers');
+
+const files = Object.fromEntries(Array.from({length: 100}, (_, i) => [`file${i}.txt`, 'needle\n'.repeat(50)]));
+
+/**
+ * Searches, resolving to the size of each batch of results passed to the callback, as well as the results.
+ * Each batch is passed on in one go, so no promise callbacks run in the middle of one.
+ */
+function searchInBatches(options, directory) {
+    return new Promise(resolve => {
+        const results = [];
+        const batches = [];
+        let inBatch = false;
+        bindings.multithreadedSearchDirectory(
+            {...options, onComplete: summary => resolve({results, batches, summary})},
+            directory,
+            result => {
+                if (!inBatch) {
+                    inBatch = true;
+                    batches.push(0);
+                    queueMicrotask(() => {
+                        inBatch = false;
+                    });
+                }
+                batches[batches.length - 1]++;
+                results.push(result);
+            }
+        );
+    });
+}
+
+test('results are passed to JavaScript in batches of at most batchSize', files, async directory => {
+    for (const batchSize of [1, 7, 256]) {
+        const {results, batches} = await searchInBatches({pattern: 'needle', batchSize}, directory);
+        assert.strictEqual(results.length, 5000);
+        assert.ok(batches.every(size => size <= batchSize), `batches of ${batches.join(', ')}`);
+    }
+});
+
+test('flushIntervalMs fills batches up without reordering results', files, async directory => {
+    const {results, batches} = await searchInBatches(
+        {pattern: 'needle', batchSize: 100, flushIntervalMs: 60 * 1000},
+        directory
+    );
+    assert.deepStrictEqual(batches, Array(50).fill(100));
+    assert.deepStrictEqual(results.map(result => result.seq), results.map((_, i) => i));
+});
+
+const oneMatch = {'a.txt': 'needle\n'};
+
+test('the last batch isn\'t held up for flushIntervalMs once the search is over', oneMatch, async directory => {
+    const start = Date.now();
+    const {batches} = await searchInBatches({pattern: 'needle', flushIntervalMs: 60 * 1000}, directory);
+    assert.deepStrictEqual(batches, [1]);
+    assert.ok(Date.now() - start < 30 * 1000);
+});
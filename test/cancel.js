@@ -0,0 +1,41 @@
+const assert = require('assert');
+const {test, bindings} = require('./helpers');
+
+// Enough files that a search is still running when it's cancelled straight after starting
+const files = Object.fromEntries(Array.from({length: 2000}, (_, i) => [`file${i}.txt`, 'needle\n'.repeat(50)]));
+
+/** Starts a search, returning its token along with a promise of its results and summary. */
+function start(options, directory) {
+    const results = [];
+    let token;
+    const done = new Promise(resolve => {
+        token = bindings.multithreadedSearchDirectory(
+            {...options, onComplete: summary => resolve({results, summary})},
+            directory,
+            result => results.push(result)
+        );
+    });
+    return {token, done};
+}
+
+test('cancelSearch stops only the search whose token it\'s given', files, async directory => {
+    const cancelled = start({pattern: 'needle'}, directory);
+    const other = start({pattern: 'needle'}, directory);
+    bindings.cancelSearch(cancelled.token);
+
+    const {results, summary} = await cancelled.done;
+    assert.strictEqual(summary.cancelled, true);
+    assert.ok(results.length < 2000 * 50, `${results.length} results`);
+
+    const finished = await other.done;
+    assert.strictEqual(finished.summary.cancelled, false);
+    assert.strictEqual(finished.results.length, 2000 * 50);
+});
+
+test('cancelling a search that has already finished does nothing', {'a.txt': 'needle\n'}, async directory => {
+    const search = start({pattern: 'needle'}, directory);
+    const {results, summary} = await search.done;
+    bindings.cancelSearch(search.token);
+    assert.strictEqual(summary.cancelled, false);
+    assert.strictEqual(results.length, 1);
+});
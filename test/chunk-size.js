@@ -0,0 +1,29 @@
+const assert = require('assert');
+const {test, search} = require('./helpers');
+
+// Matching and non-matching lines of different lengths, so chunks split in different places on each line,
+// ending in a match without a line terminator
+const lines = Array.from({length: 500}, (_, i) => (i % 3 ? `line ${i}` : `needle ${'x'.repeat(i % 7)} ${i}`));
+const files = {'big.txt': lines.join('\n') + '\nlast needle'};
+
+test('a file split into chunks has the same results, in the same order, as one that isn\'t', files, async directory => {
+    for (const options of [{}, {invertMatch: true}, {includeMatchRanges: true}]) {
+        const whole = await search({pattern: 'needle', ...options}, directory);
+        const chunked = await search({pattern: 'needle', chunkSize: 64, ...options}, directory);
+        assert.deepStrictEqual(chunked.results, whole.results);
+    }
+    const {results} = await search({pattern: 'needle', chunkSize: 64}, directory);
+    assert.strictEqual(results.length, 168);
+    assert.deepStrictEqual(results[0].lineNumber, 1);
+    assert.deepStrictEqual(results.map(result => result.seq), results.map((_, i) => i));
+    // The final chunk's last line has no line terminator
+    assert.deepStrictEqual(results[results.length - 1].matchedLines, ['last needle']);
+    assert.strictEqual(results[results.length - 1].lineNumber, 501);
+});
+
+test('maxTotalMatches keeps files whole, so its matches are the first ones', files, async directory => {
+    const whole = await search({pattern: 'needle', maxTotalMatches: 10}, directory);
+    const chunked = await search({pattern: 'needle', chunkSize: 64, maxTotalMatches: 10}, directory);
+    assert.deepStrictEqual(chunked.results, whole.results);
+    assert.deepStrictEqual(chunked.results.map(result => result.lineNumber), [1, 4, 7, 10, 13, 16, 19, 22, 25, 28]);
+});
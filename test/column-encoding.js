@@ -0,0 +1,24 @@
+const assert = require('assert');
+const {test, search} = require('./helpers');
+
+// An `e` with a combining acute accent, then an emoji, which UTF-16 needs a surrogate pair for
+const files = {'unicode.txt': 'e\u0301\u{1F600} needle needle\n'};
+
+test('columns and match ranges count in the columnEncoding\'s unit', files, async directory => {
+    const matchAt = async columnEncoding => {
+        const {results} = await search({pattern: 'needle', columnEncoding, includeMatchRanges: true}, directory);
+        return [results[0].column, results[0].matchRanges.map(range => [range.start, range.end])];
+    };
+    // 1 + 2 + 4 + 1 bytes come before it
+    assert.deepStrictEqual(await matchAt('utf8'), [9, [[8, 14], [15, 21]]]);
+    // 1 + 1 + 2 + 1 code units, so it lines up with the JavaScript string
+    assert.deepStrictEqual(await matchAt('utf16'), [6, [[5, 11], [12, 18]]]);
+    assert.strictEqual(files['unicode.txt'].indexOf('needle'), 5);
+    // 1 + 1 + 1 + 1 characters
+    assert.deepStrictEqual(await matchAt('char'), [5, [[4, 10], [11, 17]]]);
+});
+
+test('there\'s no column without a columnEncoding', files, async directory => {
+    const {results} = await search({pattern: 'needle'}, directory);
+    assert.strictEqual(results[0].column, undefined);
+});
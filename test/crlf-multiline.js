@@ -0,0 +1,27 @@
+const assert = require('assert');
+const {test, search} = require('./helpers');
+
+const files = {'crlf.txt': 'alpha\r\nbeta one\r\ngamma\r\nbeta two\r\n'};
+
+test('with crlf, a multiline pattern can match across \\r\\n, with $ before it', files, async directory => {
+    const matches = async (pattern, crlf) => {
+        const {results} = await search({pattern, multilineSearch: true, crlf}, directory);
+        return results.map(result => [result.lineNumber, result.matchedLines]);
+    };
+    assert.deepStrictEqual(await matches('alpha\\r\\nbeta one$', true), [[1, ['alpha\r\n', 'beta one\r\n']]]);
+    assert.deepStrictEqual(await matches('^gamma$\\r\\n^beta', true), [[3, ['gamma\r\n', 'beta two\r\n']]]);
+    // Without it, `$` only matches before `\n`, after the `\r`
+    assert.deepStrictEqual(await matches('alpha\\r\\nbeta one$', false), []);
+});
+
+test('with crlf, multiline matches don\'t take in the \\r before a line terminator', files, async directory => {
+    const {results} = await search(
+        {pattern: 'alpha\\s+beta.*$|^gamma.*$', multilineSearch: true, crlf: true, includeMatchRanges: true},
+        directory
+    );
+    // Both matches are in one block of lines, since they're next to each other
+    assert.strictEqual(results.length, 1);
+    assert.deepStrictEqual(results[0].matchedLines, ['alpha\r\n', 'beta one\r\n', 'gamma\r\n']);
+    // `alpha\r\nbeta one` and `gamma`
+    assert.deepStrictEqual(results[0].matchRanges.map(range => [range.start, range.end]), [[0, 15], [17, 22]]);
+});
@@ -0,0 +1,37 @@
+const assert = require('assert');
+const path = require('path');
+const {test, search} = require('./helpers');
+
+const files = {
+    'main.rs': 'needle\n',
+    'build.rs': 'needle\n',
+    'notes.note': 'needle\n',
+    'script.js': 'needle\n',
+    'sub/more.note': 'needle\n',
+};
+
+/** The paths of the files with results, relative to `directory` */
+const searched = (results, directory) => results.map(result => path.relative(directory, result.path)).sort();
+
+test('fileTypes and fileTypesNot select built-in and custom types', files, async directory => {
+    const {results} = await search({
+        pattern: 'needle',
+        customTypes: [{name: 'notes', globs: '*.note'}, {name: 'buildscript', globs: ['build.rs']}],
+        fileTypes: ['rust', 'notes'],
+        fileTypesNot: 'buildscript',
+    }, directory);
+    assert.deepStrictEqual(searched(results, directory), ['main.rs', 'notes.note', path.join('sub', 'more.note')]);
+});
+
+test('a type defined more than once matches all of its globs', files, async directory => {
+    const {results} = await search({
+        pattern: 'needle',
+        customTypes: [{name: 'js', globs: '*.note'}],
+        fileTypes: 'js',
+    }, directory);
+    assert.deepStrictEqual(searched(results, directory), ['notes.note', 'script.js', path.join('sub', 'more.note')]);
+});
+
+test('an unknown type is thrown', files, async directory => {
+    await assert.rejects(search({pattern: 'needle', fileTypes: 'nonexistent'}, directory));
+});
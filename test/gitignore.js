@@ -0,0 +1,24 @@
+const assert = require('assert');
+const path = require('path');
+const {test, search} = require('./helpers');
+
+test('respectGitignore leaves out what the .gitignore files in and above each directory match', {
+    'tree/.gitignore': '*.log\n/build/\n',
+    'tree/notes.txt': 'needle\n',
+    'tree/build/out.txt': 'needle\n',
+    'tree/sub/app.log': 'needle\n',
+    'tree/sub/kept.log': 'needle\n',
+    'tree/sub/build/out.txt': 'needle\n',
+    'tree/sub/.gitignore': '!kept.log\n',
+}, async directory => {
+    const tree = path.join(directory, 'tree');
+    const searched = async (respectGitignore, root = tree) => {
+        const {results} = await search({pattern: 'needle', respectGitignore, respectGlobalGitignore: false}, root);
+        return results.map(result => path.relative(tree, result.path)).sort();
+    };
+    // `/build/` only matches next to the .gitignore it's in, and the nearer one brings back kept.log
+    assert.deepStrictEqual(await searched(true), ['notes.txt', 'sub/build/out.txt', 'sub/kept.log']);
+    assert.strictEqual((await searched(false)).length, 5);
+    // The rules from above the root of the search apply too
+    assert.deepStrictEqual(await searched(true, path.join(tree, 'sub')), ['sub/build/out.txt', 'sub/kept.log']);
+});
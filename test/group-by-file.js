@@ -0,0 +1,25 @@
+const assert = require('assert');
+const path = require('path');
+const {test, search} = require('./helpers');
+
+const files = Object.fromEntries(Array.from({length: 20}, (_, i) => [
+    `file${i}.txt`,
+    i % 4 ? `needle ${i}\nnothing\nneedle again\n` : 'nothing\n',
+]));
+
+test('groupByFile reports each file\'s results together, leaving out files without any', files, async directory => {
+    const {results} = await search({pattern: 'needle', groupByFile: true, afterContext: 1}, directory);
+    assert.strictEqual(results.length, 15);
+    for (const group of results) {
+        const i = Number(path.basename(group.path).match(/\d+/)[0]);
+        assert.deepStrictEqual(group.matches.map(result => [result.lineNumber, result.matchedLines]), [
+            [1, [`needle ${i}\n`]],
+            [2, ['nothing\n']],
+            [3, ['needle again\n']],
+        ]);
+        assert.ok(group.matches.every(result => result.path === group.path));
+    }
+    // Groups' `seq`s count up in the order they're reported, along with those of the results in them
+    const seqs = results.map(group => group.seq);
+    assert.deepStrictEqual(seqs, [...new Set(seqs)].sort((a, b) => a - b));
+});
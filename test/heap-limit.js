@@ -0,0 +1,15 @@
+const assert = require('assert');
+const path = require('path');
+const {test, search} = require('./helpers');
+
+test('a file that exceeds the heapLimit is skipped with a HEAP_LIMIT error, and the rest are searched', {
+    'long.txt': 'needle '.repeat(64 * 1024) + '\n',
+    'short.txt': 'a needle\nnothing\n',
+}, async directory => {
+    const {results, summary, errors} = await search({pattern: 'needle', heapLimit: 1024}, directory);
+    assert.deepStrictEqual(results.map(result => result.matchedLines), [['a needle\n']]);
+    assert.strictEqual(errors.length, 1);
+    assert.strictEqual(errors[0].code, 'HEAP_LIMIT');
+    assert.strictEqual(errors[0].path, path.join(directory, 'long.txt'));
+    assert.strictEqual(summary.filesWithMatches, 1);
+});
@@ -0,0 +1,56 @@
+/**
+ * What the tests share: temporary directories of files to search, and searching them through the bindings.
+ */
+
+const fs = require('fs');
+const os = require('os');
+const path = require('path');
+const bindings = require('../dist/ripgrepjs.node');
+
+/** Every test, as `test` adds them, for `run.js` to run */
+const tests = [];
+
+/**
+ * Adds a test. `fn` is passed a temporary directory with `files` in it (paths relative to it mapped to their
+ * contents), which is removed once it's done.
+ */
+function test(description, files, fn) {
+    tests.push({description, files, fn});
+}
+
+/** Creates a temporary directory with the given files in it, creating any directories they're in. */
+function makeDirectory(files) {
+    const directory = fs.mkdtempSync(path.join(os.tmpdir(), 'ripgrepjs-test-'));
+    for (const [name, contents] of Object.entries(files)) {
+        const file = path.join(directory, name);
+        fs.mkdirSync(path.dirname(file), {recursive: true});
+        fs.writeFileSync(file, contents);
+    }
+    return directory;
+}
+
+/**
+ * Searches `targets` with one of the bindings (`multithreadedSearchDirectory` by default), resolving to
+ * the results passed to its callback, the summary passed to `onComplete`, and the errors passed to `onError`.
+ */
+function search(options, targets, binding = 'multithreadedSearchDirectory') {
+    return new Promise((resolve, reject) => {
+        const results = [];
+        const errors = [];
+        try {
+            bindings[binding](
+                {
+                    ...options,
+                    onError: error => errors.push(error),
+                    onComplete: summary => resolve({results, summary, errors}),
+                },
+                targets,
+                result => results.push(result)
+            );
+        } catch (error) {
+            reject(error);
+        }
+    });
+}
+
+module.exports = {tests, test, makeDirectory, search, bindings};
@@ -0,0 +1,37 @@
+const assert = require('assert');
+const {test, search} = require('./helpers');
+
+test('an inverted search reports the lines that don\'t match, with the ones that do as context', {
+    'mixed.txt': 'keep one\ndrop me\nkeep two\nkeep three\ndrop again\n',
+}, async directory => {
+    const {results, summary} = await search(
+        {pattern: 'drop', invertMatch: true, beforeContext: 1, afterContext: 1},
+        directory
+    );
+    assert.deepStrictEqual(results.map(result => [result.lineNumber, result.matchedLines, result.contextKind]), [
+        [1, ['keep one\n'], undefined],
+        [2, ['drop me\n'], 'after'],
+        [3, ['keep two\n'], undefined],
+        [4, ['keep three\n'], undefined],
+        [5, ['drop again\n'], 'after'],
+    ]);
+    assert.strictEqual(summary.filesWithMatches, 1);
+});
+
+test('an inverted search of a file where every line matches reports nothing', {
+    'all.txt': 'drop\ndrop\n',
+}, async directory => {
+    const {results, summary} = await search({pattern: 'drop', invertMatch: true, afterContext: 1}, directory);
+    assert.deepStrictEqual(results, []);
+    assert.strictEqual(summary.filesWithMatches, 0);
+});
+
+test('an inverted search of a file where no line matches reports every line', {
+    'none.txt': 'keep\nkeep too\n',
+}, async directory => {
+    const {results} = await search({pattern: 'drop', invertMatch: true}, directory);
+    assert.deepStrictEqual(results.map(result => [result.lineNumber, result.matchedLines]), [
+        [1, ['keep\n']],
+        [2, ['keep too\n']],
+    ]);
+});
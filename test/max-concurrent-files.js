@@ -0,0 +1,28 @@
+const assert = require('assert');
+const path = require('path');
+const {test, search} = require('./helpers');
+
+const files = {
+    'a.md': '# Title\nintro\n## Usage\nneedle here\n',
+    'b.md': '# Other\nneedle too\n',
+    'unrelated.md': '# Nothing\nhere\n',
+    'skipped.md': '# Generated\nneedle\n',
+};
+for (let i = 0; i < 100; i++) files[`many/${i}.md`] = `# File ${i}\nneedle\n`;
+
+/** Each result's path relative to `directory`, lines, heading and header lines, in order of path */
+const summarize = (results, directory) => results
+    .map(result => [path.relative(directory, result.path), result.matchedLines, result.heading, result.headerLines])
+    .sort((a, b) => (a[0] < b[0] ? -1 : 1));
+
+test('maxConcurrentFiles searches every file, reading all it needs from the same open file', files, async directory => {
+    const options = {pattern: 'needle', headingPattern: '^#', headerLines: 1, skipFilePattern: 'Generated'};
+    const limited = summarize((await search({...options, maxConcurrentFiles: 1}, directory)).results, directory);
+    assert.deepStrictEqual(limited, summarize((await search(options, directory)).results, directory));
+    assert.strictEqual(limited.length, 102);
+    assert.deepStrictEqual(limited.slice(0, 3), [
+        ['a.md', ['needle here\n'], '## Usage', ['# Title']],
+        ['b.md', ['needle too\n'], '# Other', ['# Other']],
+        [path.join('many', '0.md'), ['needle\n'], '# File 0', ['# File 0']],
+    ]);
+});
@@ -0,0 +1,28 @@
+const assert = require('assert');
+const {test, search} = require('./helpers');
+
+// Three-line matches starting on every 7th line, so they straddle the edges of windows in different places
+const lines = [];
+for (let i = 0; i < 300; i++) lines.push(i % 7 ? `line ${i}` : 'begin', ...(i % 7 ? [] : ['middle', 'end']));
+const files = {'big.txt': lines.join('\n') + '\n'};
+
+/** What each result matched, and where */
+const matches = results => results.map(result => [result.lineNumber, result.matchedLines]);
+
+test('multilineWindow finds every match spanning up to that many lines exactly once', files, async directory => {
+    const options = {pattern: 'begin\\nmiddle\\nend', multilineSearch: true};
+    const whole = await search(options, directory);
+    assert.strictEqual(whole.results.length, 43);
+    for (const multilineWindow of [3, 4, 5, 16]) {
+        const windowed = await search({...options, multilineWindow}, directory);
+        assert.deepStrictEqual(matches(windowed.results), matches(whole.results), `multilineWindow ${multilineWindow}`);
+    }
+});
+
+test('multilineWindow never finds matches spanning more than twice as many lines', files, async directory => {
+    const {results} = await search(
+        {pattern: 'begin\\nmiddle\\nend', multilineSearch: true, multilineWindow: 1},
+        directory
+    );
+    assert.deepStrictEqual(results, []);
+});
@@ -0,0 +1,42 @@
+const assert = require('assert');
+const {test, search} = require('./helpers');
+
+const files = {'words.txt': 'Ελληνικά\nйогурт\nTiếng Việt\nCafé\ncafe\u0301\ncafx\nsmørrebrød\n'};
+
+/** The lines that match `pattern` with `normalizeForMatch` */
+async function matchedLines(pattern, directory, options = {}) {
+    const {results} = await search({pattern, normalizeForMatch: true, ...options}, directory);
+    return results.map(result => result.matchedLines[0]);
+}
+
+test('normalizeForMatch strips case and marks in every script', files, async directory => {
+    assert.deepStrictEqual(await matchedLines('ελληνικα', directory), ['Ελληνικά\n']);
+    assert.deepStrictEqual(await matchedLines('иогурт', directory), ['йогурт\n']);
+    assert.deepStrictEqual(await matchedLines('tieng viet', directory), ['Tiếng Việt\n']);
+    // The pattern's literals are normalized too, whether each é is one character or an e and a combining accent
+    assert.deepStrictEqual(await matchedLines('CAFÉ', directory), ['Café\n', 'cafe\u0301\n']);
+    assert.deepStrictEqual(await matchedLines('cafe\u0301', directory), ['Café\n', 'cafe\u0301\n']);
+    // Letters that don't decompose stay as they are
+    assert.deepStrictEqual(await matchedLines('smorrebrod', directory), []);
+});
+
+test('normalizeForMatch matches classes against the normalized line as they are', files, async directory => {
+    assert.deepStrictEqual(await matchedLines('caf[^e]', directory), ['cafx\n']);
+    assert.deepStrictEqual(await matchedLines('^caf[e]$', directory), ['Café\n', 'cafe\u0301\n']);
+    assert.deepStrictEqual(await matchedLines('[ά]', directory), ['Ελληνικά\n']);
+});
+
+test('normalizeForMatch maps offsets back into the original lines', files, async directory => {
+    const {results} = await search(
+        {pattern: 'viet|cafe', normalizeForMatch: true, includeMatchRanges: true},
+        directory
+    );
+    assert.deepStrictEqual(
+        results.map(result => result.matchRanges.map(({start, end}) => [start, end])),
+        [[[8, 14]], [[0, 5]], [[0, 6]]]
+    );
+});
+
+test('normalizeForMatch can\'t be used with multilineSearch', files, async directory => {
+    await assert.rejects(search({pattern: 'cafe', normalizeForMatch: true, multilineSearch: true}, directory));
+});
@@ -0,0 +1,52 @@
+const assert = require('assert');
+const path = require('path');
+const {test, search} = require('./helpers');
+
+/** Searches with `debugIgnores`, resolving to the results and what was passed to `onIgnore`, in order of path */
+async function searchWithIgnores(options, directory) {
+    const ignores = [];
+    const {results} = await search({
+        ...options,
+        debugIgnores: true,
+        onIgnore: (file, rule, source) => ignores.push([path.relative(directory, file), rule, source]),
+    }, directory);
+    return {results, ignores: ignores.sort()};
+}
+
+const tree = {
+    '.gitignore': '# build output\n/build/\n*.log\n',
+    'build/out.txt': 'needle\n',
+    'src/main.txt': 'needle\n',
+    'src/debug.log': 'needle\n',
+};
+
+test('onIgnore reports what gitignore rules leave out, with each rule and where it\'s from', tree, async directory => {
+    const {results, ignores} = await searchWithIgnores({pattern: 'needle', respectGitignore: true}, directory);
+    const gitignore = path.join(directory, '.gitignore');
+    assert.deepStrictEqual(ignores, [
+        ['build', '/build/', gitignore],
+        [path.join('src', 'debug.log'), '*.log', gitignore],
+    ]);
+    assert.deepStrictEqual(
+        results.map(result => path.relative(directory, result.path)),
+        [path.join('src', 'main.txt')]
+    );
+});
+
+const typed = {'main.rs': 'needle\n', 'build.rs': 'needle\n', 'script.js': 'needle\n'};
+
+test('onIgnore reports which filter on file names left each file out', typed, async directory => {
+    const {ignores} = await searchWithIgnores({
+        pattern: 'needle',
+        customTypes: [{name: 'buildscript', globs: 'build.rs'}],
+        fileTypes: 'rust',
+        fileTypesNot: 'buildscript',
+    }, directory);
+    assert.deepStrictEqual(ignores, [['build.rs', 'build.rs', 'fileTypesNot'], ['script.js', null, 'fileTypes']]);
+});
+
+test('onIgnore isn\'t called without debugIgnores', tree, async directory => {
+    const ignores = [];
+    await search({pattern: 'needle', respectGitignore: true, onIgnore: file => ignores.push(file)}, directory);
+    assert.deepStrictEqual(ignores, []);
+});
@@ -0,0 +1,59 @@
+const assert = require('assert');
+const path = require('path');
+const {test, bindings} = require('./helpers');
+
+// 200 files with 10 matches each
+const files = {};
+for (let i = 0; i < 200; i++) files[`${i}.txt`] = 'needle\n'.repeat(10);
+
+/** Pulls the next result from an `openSearch` handle, resolving to what's passed to the callback. */
+function next(handle) {
+    return new Promise((resolve, reject) => {
+        bindings.nextSearchResult(handle, (error, result, summary) => {
+            if (error) reject(error);
+            else resolve({result, summary});
+        });
+    });
+}
+
+test('pulling an openSearch handle until it runs out gives every result, then the summary', files, async directory => {
+    const handle = bindings.openSearch({pattern: 'needle'}, directory);
+    const seen = new Set();
+    for (;;) {
+        const {result, summary} = await next(handle);
+        if (!result) {
+            assert.ok(summary);
+            assert.strictEqual(summary.filesWithMatches, 200);
+            break;
+        }
+        seen.add(`${path.basename(result.path)}:${result.lineNumber}`);
+    }
+    assert.strictEqual(seen.size, 2000);
+    // Once it's done, it stays done
+    assert.deepStrictEqual(await next(handle), {result: undefined, summary: undefined});
+});
+
+test('closeSearch winds down a search waiting for its queue to be pulled from', files, async directory => {
+    const handle = bindings.openSearch({pattern: 'needle'}, directory);
+    assert.ok((await next(handle)).result);
+    // Give the search time to fill its queue, so that it's waiting for it to be pulled from when it's closed
+    await new Promise(resolve => setTimeout(resolve, 100));
+    bindings.closeSearch(handle);
+
+    const {result, summary} = await next(handle);
+    assert.strictEqual(result, undefined);
+    assert.strictEqual(summary.cancelled, true);
+    assert.ok(summary.filesWithMatches < 200, `${summary.filesWithMatches} files with matches`);
+    // Once the summary has been pulled, there's nothing left, however many times it's closed
+    bindings.closeSearch(handle);
+    assert.deepStrictEqual(await next(handle), {result: undefined, summary: undefined});
+});
+
+test('closing a search once its summary has been pulled does nothing', files, async directory => {
+    const handle = bindings.openSearch({pattern: 'needle'}, directory);
+    let pulled = 0;
+    while ((await next(handle)).result) pulled++;
+    assert.strictEqual(pulled, 2000);
+    bindings.closeSearch(handle);
+    assert.deepStrictEqual(await next(handle), {result: undefined, summary: undefined});
+});
@@ -0,0 +1,46 @@
+const assert = require('assert');
+const path = require('path');
+const {test, bindings} = require('./helpers');
+
+// Files of different lengths, so they finish being searched in a different order than they start
+const files = Object.fromEntries(Array.from({length: 50}, (_, i) => [
+    `${i}.txt`,
+    'needle\n'.repeat((i * 37) % 11 + 1) + 'haystack\n'.repeat(i % 2 ? 20000 : 0),
+]));
+
+/** Searches with `orderByFile`, resolving to every callback's call in the order they came */
+function searchInOrder(directory) {
+    return new Promise(resolve => {
+        const events = [];
+        bindings.multithreadedSearchDirectory(
+            {
+                pattern: 'needle',
+                orderByFile: true,
+                onFileStart: file => events.push(['start', path.basename(file)]),
+                onFileEnd: (file, matchCount) => events.push(['end', path.basename(file), matchCount]),
+                onComplete: () => resolve(events),
+            },
+            directory,
+            result => events.push(['result', path.basename(result.path), result.lineNumber, result.seq])
+        );
+    });
+}
+
+test('orderByFile keeps each file\'s results together, between onFileStart and onFileEnd', files, async directory => {
+    const events = await searchInOrder(directory);
+    const seen = new Set();
+    let seq = 0;
+    for (let i = 0; i < events.length;) {
+        const [kind, file] = events[i++];
+        assert.strictEqual(kind, 'start');
+        assert.ok(!seen.has(file), `${file} started twice`);
+        seen.add(file);
+        let line = 1;
+        for (; events[i][0] === 'result'; i++) {
+            assert.deepStrictEqual(events[i], ['result', file, line++, seq++]);
+        }
+        assert.deepStrictEqual(events[i++], ['end', file, line - 1]);
+        assert.strictEqual(line - 1, files[file].split('needle').length - 1);
+    }
+    assert.strictEqual(seen.size, 50);
+});
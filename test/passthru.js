@@ -0,0 +1,23 @@
+const assert = require('assert');
+const {test, search} = require('./helpers');
+
+const files = {'file.txt': 'one\nneedle\ntwo\nthree needle\nfour\n'};
+
+test('passthru reports every line, flagging which ones matched', files, async directory => {
+    const {results} = await search({pattern: 'needle', passthru: true}, directory);
+    assert.deepStrictEqual(
+        results.map(result => [result.lineNumber, result.matchedLines, result.matched, result.contextKind]),
+        [
+            [1, ['one\n'], false, 'other'],
+            [2, ['needle\n'], true, undefined],
+            [3, ['two\n'], false, 'other'],
+            [4, ['three needle\n'], true, undefined],
+            [5, ['four\n'], false, 'other'],
+        ]
+    );
+});
+
+test('results aren\'t flagged without passthru', files, async directory => {
+    const {results} = await search({pattern: 'needle', beforeContext: 1}, directory);
+    assert.ok(results.every(result => result.matched === undefined));
+});
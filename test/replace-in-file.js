@@ -0,0 +1,48 @@
+const assert = require('assert');
+const fs = require('fs');
+const path = require('path');
+const {test, bindings} = require('./helpers');
+
+/** Replaces the pattern's matches in a file, resolving to how many there were */
+function replaceInFile(options, file, replacement) {
+    return new Promise((resolve, reject) => {
+        bindings.replaceInFile(options, file, replacement, (error, replacements) => {
+            if (error) reject(error);
+            else resolve(replacements);
+        });
+    });
+}
+
+const files = {'target.txt': 'old one\r\nkept\nold two', 'bystander.txt': 'untouched\n'};
+
+test('replaceInFile replaces matches in each line, leaving the rest as it was', files, async directory => {
+    const file = path.join(directory, 'target.txt');
+    assert.strictEqual(await replaceInFile({pattern: 'old (\\w+)'}, file, '$1 is new'), 2);
+    assert.strictEqual(fs.readFileSync(file, 'utf8'), 'one is new\r\nkept\ntwo is new');
+    assert.deepStrictEqual(fs.readdirSync(directory).sort(), ['bystander.txt', 'target.txt']);
+});
+
+test('replaceInFile doesn\'t touch a file without any matches', files, async directory => {
+    const file = path.join(directory, 'bystander.txt');
+    const {mtimeMs} = fs.statSync(file);
+    assert.strictEqual(await replaceInFile({pattern: 'old'}, file, 'new'), 0);
+    assert.strictEqual(fs.statSync(file).mtimeMs, mtimeMs);
+});
+
+test('replaceInFile rewrites the file a symlink points to, keeping the link', files, async directory => {
+    const link = path.join(directory, 'link.txt');
+    fs.symlinkSync(path.join(directory, 'target.txt'), link);
+    // A link where a temporary file next to the target could have been named, which mustn't be written through
+    const planted = path.join(directory, '.target.txt.ripgrepjs-tmp');
+    fs.symlinkSync(path.join(directory, 'bystander.txt'), planted);
+
+    assert.strictEqual(await replaceInFile({pattern: 'old'}, link, 'new'), 2);
+    assert.ok(fs.lstatSync(link).isSymbolicLink());
+    assert.strictEqual(fs.readFileSync(link, 'utf8'), 'new one\r\nkept\nnew two');
+    assert.ok(fs.lstatSync(planted).isSymbolicLink());
+    assert.strictEqual(fs.readFileSync(path.join(directory, 'bystander.txt'), 'utf8'), 'untouched\n');
+    assert.deepStrictEqual(
+        fs.readdirSync(directory).sort(),
+        ['.target.txt.ripgrepjs-tmp', 'bystander.txt', 'link.txt', 'target.txt']
+    );
+});
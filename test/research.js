@@ -0,0 +1,53 @@
+const assert = require('assert');
+const fs = require('fs');
+const path = require('path');
+const {test, bindings} = require('./helpers');
+
+const files = {'a.txt': 'needle one\n', 'b.txt': 'needle two\n', 'c.txt': 'nothing\n'};
+
+/** Searches `paths` again into `cache`, resolving to every result in it. */
+function research(cache, paths) {
+    return new Promise((resolve, reject) => {
+        bindings.research(cache, paths, (error, results) => (error ? reject(error) : resolve(results)));
+    });
+}
+
+/** Each result's file name and lines */
+const summarize = results => results.map(result => [path.basename(result.path), result.matchedLines]);
+
+test('research only searches files again once their size or modification time changes', files, async directory => {
+    // A modification time that can be set again exactly
+    const a = path.join(directory, 'a.txt');
+    fs.utimesSync(a, 1000000000, 1000000000);
+    const cache = bindings.createResultCache({pattern: 'needle'});
+    assert.deepStrictEqual(summarize(await research(cache, [directory])), [
+        ['a.txt', ['needle one\n']],
+        ['b.txt', ['needle two\n']],
+    ]);
+
+    // Changed without its size or modification time changing, so its old results are kept
+    fs.writeFileSync(a, 'needle ONE\n');
+    fs.utimesSync(a, 1000000000, 1000000000);
+    // A different size
+    fs.writeFileSync(path.join(directory, 'b.txt'), 'needle two\nneedle three\n');
+    // The same size, but modified later
+    const c = path.join(directory, 'c.txt');
+    fs.writeFileSync(c, 'needle!\n');
+    fs.utimesSync(c, new Date(), new Date(Date.now() + 10000));
+
+    assert.deepStrictEqual(summarize(await research(cache, [directory])), [
+        ['a.txt', ['needle one\n']],
+        ['b.txt', ['needle two\n']],
+        ['b.txt', ['needle three\n']],
+        ['c.txt', ['needle!\n']],
+    ]);
+});
+
+test('research drops files that are gone from the cache', files, async directory => {
+    const cache = bindings.createResultCache({pattern: 'needle'});
+    await research(cache, [directory]);
+    fs.unlinkSync(path.join(directory, 'a.txt'));
+    const results = await research(cache, [path.join(directory, 'a.txt')]);
+    assert.deepStrictEqual(summarize(results), [['b.txt', ['needle two\n']]]);
+    assert.deepStrictEqual(results.map(result => result.seq), [0]);
+});
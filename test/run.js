@@ -0,0 +1,38 @@
+/**
+ * Runs the tests in this directory one at a time, or just those in the files given as arguments.
+ *
+ * Run with `node test/run.js` after `npm run build`.
+ */
+
+const fs = require('fs');
+const path = require('path');
+const {tests, makeDirectory} = require('./helpers');
+
+const files = process.argv.length > 2 ?
+    process.argv.slice(2) :
+    fs.readdirSync(__dirname).filter(name => name.endsWith('.js') && name !== 'helpers.js' && name !== 'run.js');
+
+(async () => {
+    let failures = 0;
+    for (const file of files) {
+        const start = tests.length;
+        require(path.resolve(__dirname, file));
+        for (const {description, files, fn} of tests.slice(start)) {
+            const directory = makeDirectory(files);
+            try {
+                await fn(directory);
+                console.log(`ok ${file}: ${description}`);
+            } catch (error) {
+                failures++;
+                console.error(`FAILED ${file}: ${description}`);
+                console.error(error);
+            } finally {
+                fs.rmSync(directory, {recursive: true, force: true});
+            }
+        }
+    }
+    if (failures) {
+        console.error(`${failures} failed`);
+        process.exitCode = 1;
+    }
+})();
@@ -0,0 +1,42 @@
+const assert = require('assert');
+const {test, search} = require('./helpers');
+
+const files = {'file.txt': 'before\nneedle\nafter\n'};
+
+/** Searches through the TypeScript wrapper, resolving to its results. */
+function searchWrapper(options, directory) {
+    const {searchWithEventEmitter} = require('../dist/index.js');
+    return new Promise(resolve => {
+        const results = [];
+        searchWithEventEmitter(options, directory)
+            .on('result', result => results.push(result))
+            .on('end', () => resolve(results));
+    });
+}
+
+test('the bindings default every option but the pattern', files, async directory => {
+    const {results} = await search({pattern: 'needle'}, directory);
+    assert.deepStrictEqual(results.map(result => [result.matchedLines, result.lineNumber]), [[['needle\n'], 2]]);
+});
+
+test('the bindings take one context option without the other', files, async directory => {
+    const {results} = await search({pattern: 'needle', beforeContext: 1}, directory);
+    assert.deepStrictEqual(
+        results.map(result => [result.matchedLines, result.contextKind]),
+        [[['before\n'], 'before'], [['needle\n'], undefined]]
+    );
+});
+
+test('the bindings take options that default to true being turned off', files, async directory => {
+    const {results} = await search({pattern: 'needle', includeLineNumbers: false}, directory);
+    assert.strictEqual(results[0].lineNumber, undefined);
+});
+
+test('the wrapper fills in its own defaults without overriding ones it was given', files, async directory => {
+    let results = await searchWrapper({pattern: 'needle'}, directory);
+    assert.strictEqual(results[0].lineNumber, 2);
+    results = await searchWrapper({pattern: 'needle', includeLineNumbers: false}, directory);
+    assert.strictEqual(results[0].lineNumber, undefined);
+    results = await searchWrapper({pattern: 'needle', invertMatch: true}, directory);
+    assert.deepStrictEqual(results.map(result => result.matchedLines), [['before\n'], ['after\n']]);
+});
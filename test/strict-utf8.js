@@ -0,0 +1,22 @@
+const assert = require('assert');
+const path = require('path');
+const {test, search} = require('./helpers');
+
+const files = {'bad.txt': Buffer.from('fine needle\nbad \xff needle\nneedle after\n', 'latin1')};
+
+test('strictUtf8 stops at the first match that isn\'t UTF-8, with its error in the summary', files, async directory => {
+    const {results, summary, errors} = await search({pattern: 'needle', strictUtf8: true}, directory);
+    assert.deepStrictEqual(results.map(result => result.matchedLines), [['fine needle\n']]);
+    assert.deepStrictEqual(errors, []);
+    assert.strictEqual(summary.cancelled, false);
+    assert.deepStrictEqual(
+        summary.errors.map(error => [error.code, path.basename(error.path), error.lineNumber]),
+        [['INVALID_UTF8', 'bad.txt', 2]]
+    );
+});
+
+test('without strictUtf8, a match that isn\'t UTF-8 goes to onError', files, async directory => {
+    const {results, errors} = await search({pattern: 'needle'}, directory);
+    assert.deepStrictEqual(results.map(result => result.lineNumber), [1, 3]);
+    assert.deepStrictEqual(errors.map(error => [error.code, error.lineNumber]), [['INVALID_UTF8', 2]]);
+});
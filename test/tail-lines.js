@@ -0,0 +1,29 @@
+const assert = require('assert');
+const path = require('path');
+const {test, search} = require('./helpers');
+
+const lines = Array.from({length: 1000}, (_, i) => `needle ${i + 1}`);
+const files = {'log.txt': lines.join('\n') + '\n', 'short.txt': 'needle 1\nneedle 2\n'};
+
+/** The line number and text of each result from one file, in order */
+const linesIn = (results, name) => results
+    .filter(result => path.basename(result.path) === name)
+    .map(result => [result.lineNumber, result.matchedLines[0]])
+    .sort((a, b) => a[0] - b[0]);
+
+test('tailLines only searches the end of each file, still numbering lines from the start', files, async directory => {
+    const {results} = await search({pattern: 'needle', tailLines: 3}, directory);
+    assert.deepStrictEqual(linesIn(results, 'log.txt'), [
+        [998, 'needle 998\n'],
+        [999, 'needle 999\n'],
+        [1000, 'needle 1000\n'],
+    ]);
+    // A file with fewer lines is searched whole
+    assert.deepStrictEqual(linesIn(results, 'short.txt'), [[1, 'needle 1\n'], [2, 'needle 2\n']]);
+});
+
+test('tailLines without line numbers doesn\'t read the rest of the file', files, async directory => {
+    const {results, summary} = await search({pattern: 'needle', tailLines: 1, includeLineNumbers: false}, directory);
+    assert.deepStrictEqual(results.map(result => result.lineNumber), [undefined, undefined]);
+    assert.strictEqual(summary.bytesSearched, 'needle 1000\n'.length + 'needle 2\n'.length);
+});
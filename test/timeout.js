@@ -0,0 +1,17 @@
+const assert = require('assert');
+const {test, search} = require('./helpers');
+
+// Enough files that searching them takes far longer than starting the timer does
+const files = Object.fromEntries(Array.from({length: 2000}, (_, i) => [`file${i}.txt`, 'needle\n'.repeat(50)]));
+
+test('a search that runs past timeoutMs stops early with timedOut set', files, async directory => {
+    const {results, summary} = await search({pattern: 'needle', timeoutMs: 0}, directory);
+    assert.strictEqual(summary.timedOut, true);
+    assert.ok(results.length < 2000 * 50, `${results.length} results`);
+});
+
+test('a search that finishes in time isn\'t timed out', files, async directory => {
+    const {results, summary} = await search({pattern: 'needle', timeoutMs: 60 * 1000}, directory);
+    assert.strictEqual(summary.timedOut, false);
+    assert.strictEqual(results.length, 2000 * 50);
+});
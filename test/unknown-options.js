@@ -0,0 +1,27 @@
+const assert = require('assert');
+const {test, search, bindings} = require('./helpers');
+
+const files = {'a.txt': 'needle\n'};
+
+test('a misspelled option is thrown, with the option it was probably meant to be', files, async directory => {
+    await assert.rejects(
+        search({pattern: 'needle', caseInsensitve: true}, directory),
+        {name: 'TypeError', message: 'unknown option caseInsensitve (did you mean caseInsensitive?)'}
+    );
+    await assert.rejects(
+        search({pattern: 'needle', aftercontext: 1}, directory),
+        {name: 'TypeError', message: 'unknown option aftercontext (did you mean afterContext?)'}
+    );
+    await assert.rejects(
+        search({pattern: 'needle', somethingElse: 1}, directory),
+        {name: 'TypeError', message: 'unknown option somethingElse'}
+    );
+});
+
+test('options that only apply to searches are unknown to compilePattern', files, async () => {
+    assert.throws(
+        () => bindings.compilePattern({pattern: 'needle', afterContext: 1}),
+        {name: 'TypeError', message: 'unknown option afterContext'}
+    );
+    assert.ok(bindings.compilePattern({pattern: 'needle', caseInsensitive: true}));
+});
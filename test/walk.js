@@ -0,0 +1,54 @@
+const assert = require('assert');
+const fs = require('fs');
+const path = require('path');
+const {test, search, bindings} = require('./helpers');
+
+// More entries in one directory than fit in the walk's queue at once, so workers have to search some themselves,
+// and directories nested deeper than there are threads
+const files = Object.fromEntries(Array.from({length: 3000}, (_, i) => [`wide/${i}.txt`, 'needle\n']));
+for (let depth = 1; depth <= 100; depth++) files[`${'deep/'.repeat(depth)}file.txt`] = 'needle\n';
+
+/** The paths of the files with results, relative to `directory` */
+const relativePaths = (results, directory) => results.map(result => path.relative(directory, result.path)).sort();
+
+test('walking a tree finishes once every file in it has been searched, each once', files, async directory => {
+    const {results, summary} = await search({pattern: 'needle'}, directory);
+    assert.deepStrictEqual(relativePaths(results, directory), Object.keys(files).sort());
+    assert.strictEqual(summary.filesWithMatches, 3100);
+});
+
+const nested = {'a/b/file.txt': 'needle\n'};
+
+test('each directory is walked once, however many ways it can be reached', nested, async directory => {
+    // A link back up the tree, and roots inside each other
+    fs.symlinkSync(path.join(directory, 'a'), path.join(directory, 'a/b/loop'));
+    const {results} = await search(
+        {pattern: 'needle', followSymlinks: true},
+        [directory, path.join(directory, 'a'), path.join(directory, 'a/b')]
+    );
+    assert.deepStrictEqual(relativePaths(results, directory), ['a/b/file.txt']);
+});
+
+test('an entry that can\'t be searched is reported without stopping the walk', files, async directory => {
+    fs.symlinkSync(path.join(directory, 'nowhere'), path.join(directory, 'deep/deep/dangling'));
+    const {results, errors} = await search({pattern: 'needle', followSymlinks: true}, directory);
+    assert.strictEqual(results.length, 3100);
+    assert.deepStrictEqual(
+        errors.map(error => [error.code, path.relative(directory, error.path)]),
+        [['ENOENT', 'deep/deep/dangling']]
+    );
+});
+
+test('a cancelled walk stops without searching the rest of the tree', files, async directory => {
+    const results = [];
+    const summary = await new Promise(resolve => {
+        const token = bindings.multithreadedSearchDirectory(
+            {pattern: 'needle', onComplete: resolve},
+            directory,
+            result => results.push(result)
+        );
+        bindings.cancelSearch(token);
+    });
+    assert.strictEqual(summary.cancelled, true);
+    assert.ok(results.length < 3100, `${results.length} results`);
+});